@@ -1,211 +1,503 @@
 #![allow(dead_code)]
-use std::{fs, io, path::PathBuf, sync::mpsc::Sender};
+use std::{
+    cell::{Ref, RefMut},
+    fs, io,
+    path::PathBuf,
+    rc::Rc,
+    sync::mpsc::Sender,
+};
 
 use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
 use uuid::Uuid;
 
-use crate::editor::{BufferEvent, EditorEvent};
-
-pub struct Line {
-    pub start: usize,
-    pub end: usize,
-}
-
-impl Line {
-    pub fn len(&self) -> usize {
-        self.end - self.start + 1
-    }
-}
-
+use crate::{
+    diagnostics::{Diagnostic, Severity},
+    document::{Change, ChangeReader, Document, Line, SharedDocument},
+    editor::{BufferEvent, EditorEvent, ExplorerEvent},
+    explorer::Explorer,
+    highlight::Span,
+    history::EditKind,
+    language::Language,
+    path_completion,
+    selector::{Selector, SelectorItem},
+    syntax::NodeKind,
+    word_completion::WordCompletion,
+};
+
+/// A view's cursor into a `Document`, which may be shared with other views
 pub struct BufferData {
-    data: Vec<char>,
-    lines: Vec<Line>,
+    document: SharedDocument,
     cursor: usize,
     prev_cursor_offset: Option<usize>,
+    /// Number of document changes the cursor was adjusted for, see `sync`
+    synced_changes: ChangeReader,
+    /// Folded regions, from the start of their header line to the end of their last hidden line.
+    /// They are kept as positions in the text so they move along with edits, like the cursor.
+    folds: Vec<(usize, usize)>,
 }
 
 impl BufferData {
     pub fn new() -> Self {
-        let mut buf_data = Self {
-            data: vec![],
-            lines: vec![],
-            cursor: 0,
-            prev_cursor_offset: None,
-        };
-        buf_data.recalculate_lines();
-        buf_data
+        Self::view_of(Document::new().shared())
     }
 
     pub fn from(data: String) -> Self {
-        let data = data
-            .chars()
-            .map(|b| b as char)
-            .filter(|c| *c != '\r')
-            .collect();
-
-        let mut buf_data = Self {
-            data,
-            lines: vec![],
+        Self::view_of(Document::from(data).shared())
+    }
+
+    /// Creates a view of `document`, with the cursor at the start
+    pub fn view_of(document: SharedDocument) -> Self {
+        let synced_changes = document.borrow().reader();
+
+        Self {
+            document,
             cursor: 0,
             prev_cursor_offset: None,
-        };
-        buf_data.recalculate_lines();
-        buf_data
+            synced_changes,
+            folds: vec![],
+        }
     }
 
-    pub fn line_count(&self) -> usize {
-        self.lines.len()
+    /// Creates another view of the same document, with the cursor at the same position
+    pub fn new_view(&self) -> Self {
+        Self {
+            document: self.document.clone(),
+            cursor: self.cursor,
+            prev_cursor_offset: self.prev_cursor_offset,
+            synced_changes: self.document.borrow().reader_at(self.synced_changes.get()),
+            folds: self.folds.clone(),
+        }
     }
 
-    pub fn recalculate_lines(&mut self) {
-        let mut previous_begining = 0;
-        self.lines.clear();
-
-        for (i, ch) in self.data.iter().enumerate() {
-            if *ch == '\n' {
-                self.lines.push(Line {
-                    start: previous_begining,
-                    end: i,
-                });
-                previous_begining = i + 1;
-            }
-        }
+    pub fn document(&self) -> Ref<'_, Document> {
+        self.document.borrow()
+    }
 
-        assert!(self.data.len() >= previous_begining);
-        let end = if previous_begining == self.data.len() {
-            previous_begining
-        } else {
-            self.data.len() - 1
-        };
+    pub fn document_mut(&self) -> RefMut<'_, Document> {
+        self.document.borrow_mut()
+    }
 
-        self.lines.push(Line {
-            start: previous_begining,
-            end,
-        });
+    /// Returns true if both views show the same document
+    pub fn same_document(&self, other: &BufferData) -> bool {
+        Rc::ptr_eq(&self.document, &other.document)
     }
 
-    pub fn current_line(&self) -> usize {
-        let mut current_line = 0;
+    /// Moves the cursor along with edits made through other views of the document.
+    /// Returns true if the document changed since the last sync.
+    pub fn sync(&mut self) -> bool {
+        let document = self.document.borrow();
+        let changes = document.changes_since(self.synced_changes.get());
+        // The text may also have been replaced as a whole, without logging changes
+        if changes.is_empty() && self.cursor <= document.len() {
+            return false;
+        }
 
-        for Line { start, end } in self.lines.iter() {
-            if *start <= self.cursor && *end >= self.cursor {
-                return current_line;
-            } else {
-                current_line += 1;
+        for change in changes {
+            match change.kind {
+                EditKind::Insert if change.pos < self.cursor => self.cursor += 1,
+                EditKind::Delete if change.pos < self.cursor => self.cursor -= 1,
+                _ => (),
             }
         }
+        move_folds(&mut self.folds, changes);
+
+        self.cursor = std::cmp::min(self.cursor, document.len());
+        self.synced_changes.set(document.change_count());
+        self.prev_cursor_offset = None;
+        true
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.document.borrow().line_count()
+    }
 
-        assert!(self.cursor == self.data.len());
-        current_line - 1
+    /// Position of the cursor in the text
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn current_line(&self) -> usize {
+        self.document.borrow().line_of(self.cursor)
     }
 
     pub fn move_cursor_right(&mut self, dx: usize) {
-        if self.cursor + dx <= self.data.len() {
-            self.cursor += dx;
+        {
+            let mut document = self.document.borrow_mut();
+            document.break_group();
+
+            if self.cursor + dx <= document.len() {
+                self.cursor += dx;
+            }
         }
 
         self.prev_cursor_offset = None;
+        self.skip_folded(true);
     }
 
     pub fn move_cursor_left(&mut self, dx: usize) {
+        self.document.borrow_mut().break_group();
+
         if self.cursor >= dx {
             self.cursor -= dx;
         }
 
         self.prev_cursor_offset = None;
+        self.skip_folded(false);
     }
 
     pub fn move_cursor_up(&mut self, dy: usize) {
-        let mut current_line = self.current_line();
+        self.move_cursor_vertically(dy, false);
+    }
 
-        if current_line >= dy {
-            let line = &self.lines[current_line];
-            let mut x_offset = match self.prev_cursor_offset {
-                Some(offset) => offset,
-                None => self.cursor - line.start,
-            };
+    pub fn move_cursor_down(&mut self, dy: usize) {
+        self.move_cursor_vertically(dy, true);
+    }
 
-            current_line -= dy;
+    /// Moves the cursor `dy` visible lines down or up, keeping its column. Folded lines are
+    /// skipped, and the cursor stays put if there are not enough lines.
+    fn move_cursor_vertically(&mut self, dy: usize, down: bool) {
+        let mut document = self.document.borrow_mut();
+        document.break_group();
+
+        let hidden = hidden_lines(&document, &self.folds);
+        let lines = document.lines();
+        let current_line = document.line_of(self.cursor);
+
+        let mut target = current_line;
+        for _ in 0..dy {
+            loop {
+                target = match down {
+                    true if target + 1 < lines.len() => target + 1,
+                    false if target > 0 => target - 1,
+                    _ => return,
+                };
+                if !is_hidden(&hidden, target) {
+                    break;
+                }
+            }
+        }
 
-            let line = &self.lines[current_line];
+        let line = &lines[current_line];
+        let mut x_offset = match self.prev_cursor_offset {
+            Some(offset) => offset,
+            None => self.cursor - line.start,
+        };
 
-            if x_offset >= line.len() {
-                self.prev_cursor_offset = Some(x_offset);
-                x_offset = line.len() - 1;
-            }
+        let line = &lines[target];
 
-            self.cursor = line.start + x_offset;
+        if x_offset >= line.len() {
+            self.prev_cursor_offset = Some(x_offset);
+            x_offset = line.len() - 1;
         }
+
+        self.cursor = line.start + x_offset;
     }
 
-    pub fn move_cursor_down(&mut self, dy: usize) {
-        let mut current_line = self.current_line();
+    /// Moves the cursor to the start of `line`, clamped to the last line
+    pub fn move_cursor_to_line(&mut self, line: usize) {
+        let document = self.document.borrow();
+        let lines = document.lines();
 
-        if current_line + dy < self.lines.len() {
-            let line = &self.lines[current_line];
-            let mut x_offset = match self.prev_cursor_offset {
-                Some(offset) => offset,
-                None => self.cursor - line.start,
-            };
+        let line = std::cmp::min(line, lines.len() - 1);
+        self.cursor = lines[line].start;
+        self.prev_cursor_offset = None;
+    }
 
-            current_line += dy;
+    /// Moves the cursor to a 0-indexed line and column, clamped to the text
+    pub fn move_cursor_to(&mut self, line: usize, column: usize) {
+        let document = self.document.borrow();
+        let (data, lines) = (document.chars(), document.lines());
 
-            let line = &self.lines[current_line];
+        let line = std::cmp::min(line, lines.len() - 1);
+        let Line { start, end } = lines[line];
 
-            if x_offset >= line.len() {
-                self.prev_cursor_offset = Some(x_offset);
-                x_offset = line.len() - 1;
-            }
+        // `end` is the '\n' of the line, except for the last line
+        let max_column = if end < data.len() && data[end] == '\n' {
+            end - start
+        } else {
+            data.len() - start
+        };
+
+        self.cursor = start + std::cmp::min(column, max_column);
+        self.prev_cursor_offset = None;
+    }
+
+    /// Moves the cursor to the character at `pos`, clamped to the text
+    pub fn move_cursor_to_pos(&mut self, pos: usize) {
+        let mut document = self.document.borrow_mut();
+        document.break_group();
 
-            self.cursor = line.start + x_offset;
+        self.cursor = std::cmp::min(pos, document.len());
+        self.prev_cursor_offset = None;
+    }
+
+    pub fn move_cursor_to_end(&mut self) {
+        self.cursor = self.document.borrow().len();
+        self.prev_cursor_offset = None;
+    }
+
+    /// Lines hidden by folds, as sorted ranges of first and last line that don't overlap
+    pub fn hidden_lines(&self) -> Vec<(usize, usize)> {
+        hidden_lines(&self.document.borrow(), &self.folds)
+    }
+
+    /// Returns true if the lines after `header` up to `last` are folded
+    pub fn is_folded(&self, header: usize, last: usize) -> bool {
+        let document = self.document.borrow();
+        self.folds
+            .iter()
+            .any(|fold| fold_lines(&document, *fold) == Some((header, last)))
+    }
+
+    /// Returns true if a fold starts on `line`
+    pub fn has_fold_at(&self, line: usize) -> bool {
+        let document = self.document.borrow();
+        self.folds
+            .iter()
+            .any(|fold| fold_lines(&document, *fold).is_some_and(|(header, _)| header == line))
+    }
+
+    /// Hides the lines after `header` up to `last`
+    pub fn fold(&mut self, header: usize, last: usize) {
+        let document = self.document.borrow();
+        let lines = document.lines();
+        if let (Some(header), Some(last)) = (lines.get(header), lines.get(last)) {
+            self.folds.push((header.start, last.end));
         }
     }
 
+    /// Opens the folds starting on `line`. Returns false if there were none.
+    pub fn unfold(&mut self, line: usize) -> bool {
+        let document = self.document.borrow();
+        let count = self.folds.len();
+        self.folds
+            .retain(|fold| fold_lines(&document, *fold).is_some_and(|(header, _)| header != line));
+        self.folds.len() != count
+    }
+
+    pub fn unfold_all(&mut self) {
+        self.folds.clear();
+    }
+
+    /// Opens the folds hiding the line of the cursor, after it jumped there
+    pub fn reveal_cursor(&mut self) {
+        let document = self.document.borrow();
+        let line = document.line_of(self.cursor);
+        self.folds.retain(|fold| {
+            fold_lines(&document, *fold).is_some_and(|(header, last)| line <= header || last < line)
+        });
+    }
+
+    /// Moves the cursor out of folded lines, to the start of the line after them when moving
+    /// `forward`, or else to the end of their header line
+    fn skip_folded(&mut self, forward: bool) {
+        let document = self.document.borrow();
+        let hidden = hidden_lines(&document, &self.folds);
+        let line = document.line_of(self.cursor);
+        let Some((first, last)) = hidden.into_iter().find(|(f, l)| *f <= line && line <= *l) else {
+            return;
+        };
+
+        let lines = document.lines();
+        self.cursor = match (forward, lines.get(last + 1)) {
+            (true, Some(next)) => next.start,
+            // The '\n' of the header line
+            _ => lines[first - 1].end,
+        };
+    }
+
     pub fn insert_ch(&mut self, ch: char) {
-        self.data.insert(self.cursor, ch);
+        let mut document = self.document.borrow_mut();
+        document.insert(self.cursor, ch);
         self.cursor += 1;
+        let changes = document.changes_since(self.synced_changes.get());
+        move_folds(&mut self.folds, changes);
+        self.synced_changes.set(document.change_count());
     }
 
     /// Same as backspace key pressed
     pub fn backspace(&mut self) {
         if self.cursor > 0 {
+            let mut document = self.document.borrow_mut();
             self.cursor -= 1;
-            self.data.remove(self.cursor);
+            document.remove(self.cursor);
+            let changes = document.changes_since(self.synced_changes.get());
+            move_folds(&mut self.folds, changes);
+            self.synced_changes.set(document.change_count());
         }
     }
 
     /// Same as delete key pressed
     pub fn delete(&mut self) {
-        if self.cursor < self.data.len() {
-            self.data.remove(self.cursor);
+        let mut document = self.document.borrow_mut();
+        if self.cursor < document.len() {
+            document.remove(self.cursor);
+            let changes = document.changes_since(self.synced_changes.get());
+            move_folds(&mut self.folds, changes);
+            self.synced_changes.set(document.change_count());
+        }
+    }
+
+    /// Deletes the characters from `start` up to `end`, leaving the cursor at `start`
+    pub fn remove_range(&mut self, start: usize, end: usize) {
+        let mut document = self.document.borrow_mut();
+        document.replace(start, end, "");
+        self.cursor = start;
+        let changes = document.changes_since(self.synced_changes.get());
+        move_folds(&mut self.folds, changes);
+        self.synced_changes.set(document.change_count());
+        self.prev_cursor_offset = None;
+    }
+
+    /// Reverts the last group of edits. Returns false if there was nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        let mut document = self.document.borrow_mut();
+        let edits = document.undo();
+
+        // The cursor ends up where the oldest edit of the group was made
+        if let Some(edit) = edits.last() {
+            self.cursor = match edit.kind {
+                EditKind::Insert => edit.pos,
+                EditKind::Delete => edit.pos + 1,
+            };
+        }
+
+        let changes = document.changes_since(self.synced_changes.get());
+        move_folds(&mut self.folds, changes);
+        self.synced_changes.set(document.change_count());
+        self.prev_cursor_offset = None;
+        !edits.is_empty()
+    }
+
+    /// Re-applies the last undone group of edits. Returns false if there was nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        let mut document = self.document.borrow_mut();
+        let edits = document.redo();
+
+        // The cursor ends up where the newest edit of the group was made
+        if let Some(edit) = edits.last() {
+            self.cursor = match edit.kind {
+                EditKind::Insert => edit.pos + 1,
+                EditKind::Delete => edit.pos,
+            };
         }
+
+        let changes = document.changes_since(self.synced_changes.get());
+        move_folds(&mut self.folds, changes);
+        self.synced_changes.set(document.change_count());
+        self.prev_cursor_offset = None;
+        !edits.is_empty()
+    }
+
+    /// Revision of the current text, see `History`
+    pub fn revision(&self) -> usize {
+        self.document.borrow().revision()
     }
 
     pub fn digits_in_line_num(&self) -> usize {
-        let mut max = self.lines.len();
-        let mut digits = 1; // start with a small gap
-        while max > 0 {
-            digits += 1;
-            max = max.saturating_div(10);
+        self.document.borrow().digits_in_line_num()
+    }
+}
+
+/// Returns the header line and last hidden line of a fold, `None` if edits left nothing to hide
+fn fold_lines(document: &Document, (start, end): (usize, usize)) -> Option<(usize, usize)> {
+    if end > document.len() {
+        return None;
+    }
+    let (header, last) = (document.line_of(start), document.line_of(end));
+    (last > header).then_some((header, last))
+}
+
+/// See `BufferData::hidden_lines`
+fn hidden_lines(document: &Document, folds: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = folds
+        .iter()
+        .filter_map(|fold| fold_lines(document, *fold))
+        .map(|(header, last)| (header + 1, last))
+        .collect();
+    ranges.sort();
+
+    // Nested and adjacent folds hide one range
+    let mut merged: Vec<(usize, usize)> = vec![];
+    for (first, last) in ranges {
+        match merged.last_mut() {
+            Some((_, merged_last)) if first <= *merged_last + 1 => {
+                *merged_last = std::cmp::max(*merged_last, last)
+            }
+            _ => merged.push((first, last)),
+        }
+    }
+    merged
+}
+
+/// Returns the row `line` is shown on, counted from the top of the text
+fn display_row(hidden: &[(usize, usize)], line: usize) -> usize {
+    let mut row = line;
+    for (first, last) in hidden.iter().take_while(|(first, _)| *first < line) {
+        row -= std::cmp::min(*last, line - 1) - first + 1;
+    }
+    row
+}
+
+/// Returns the line shown on `row`, counted from the top of the text
+fn line_at_row(hidden: &[(usize, usize)], row: usize) -> usize {
+    let mut line = row;
+    for (first, last) in hidden {
+        if *first > line {
+            break;
+        }
+        line += last - first + 1;
+    }
+    line
+}
+
+fn is_hidden(hidden: &[(usize, usize)], line: usize) -> bool {
+    let i = hidden.partition_point(|(_, last)| *last < line);
+    hidden.get(i).is_some_and(|(first, _)| *first <= line)
+}
+
+/// Moves folds along with edits, like `BufferData::sync` moves the cursor
+fn move_folds(folds: &mut Vec<(usize, usize)>, changes: &[Change]) {
+    for change in changes {
+        for (start, end) in folds.iter_mut() {
+            match change.kind {
+                EditKind::Insert => {
+                    if change.pos < *start {
+                        *start += 1;
+                    }
+                    if change.pos <= *end {
+                        *end += 1;
+                    }
+                }
+                EditKind::Delete => {
+                    if change.pos < *start {
+                        *start -= 1;
+                    }
+                    if change.pos < *end {
+                        *end -= 1;
+                    }
+                }
+            }
         }
-        digits
     }
+    folds.retain(|(start, end)| start < end);
 }
 
-impl ToString for BufferData {
-    fn to_string(&self) -> String {
-        self.data.iter().collect()
+impl std::fmt::Display for BufferData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.document.borrow().fmt(f)
     }
 }
 
 // EditorBufferType
 // InputBoxBufferType
 // SelectorBufferType
-#[derive(PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum BufferLogic {
     Editor,
     InputBox,
     Selector,
+    Explorer,
 }
 
 pub struct Padding {
@@ -229,23 +521,38 @@ pub struct Buffer {
     pub scroll_x: usize,
     pub scroll_y: usize,
 
-    pub file_path: Option<PathBuf>,
     read_only: bool,
+    /// Selected text, from the first character up to the second (exclusive)
+    selection: Option<(usize, usize)>,
+    /// Selections that were expanded, the last one is restored when shrinking
+    expanded_selections: Vec<Option<(usize, usize)>>,
     pub visible: bool,
     pub line_numbers: bool,
     pub show_status_line: bool,
 
     pub bordered: bool,
+    title: String,
     pub top_border: String,
     pub bottom_border: String,
 
     pub logic: BufferLogic,
+    pub selector: Option<Selector>,
+    pub explorer: Option<Explorer>,
+    /// Words completing the one being typed, listed at the cursor of editor buffers
+    pub completion: Option<WordCompletion>,
+
+    /// Tab completes the typed text as a path, in input boxes. Relative paths start from this
+    /// folder.
+    path_completion: Option<PathBuf>,
+    /// Entries matching the last path completion, listed in the bottom border
+    completions: Vec<String>,
 
     msg_sender: Sender<EditorEvent>,
     paused_event_id: Uuid,
 }
 
 impl Buffer {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         path: PathBuf,
         x: u16,
@@ -275,36 +582,21 @@ impl Buffer {
             // If it's a directory, we can't load it into a buffer, so we initialize an empty BufferData
             BufferData::new()
         };
+        {
+            let mut document = data.document_mut();
+            document.disk_modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+            document.file_path = Some(path);
+        }
 
         let line_numbers = match logic {
             BufferLogic::Editor => true,
             BufferLogic::InputBox => false,
             BufferLogic::Selector => false,
-        };
-
-        let top_border = if bordered {
-            let mut s = String::from('╭');
-            s.push_str(title);
-            let border_dash_len = (width - 2) as usize - title.len();
-            s.push_str(&"─".repeat(border_dash_len));
-            s.push('╮');
-            s
-        } else {
-            String::new()
-        };
-
-        let bottom_border = if bordered {
-            let mut s = String::from('╰');
-            let border_dash_len = (width - 2) as usize;
-            s.push_str(&"─".repeat(border_dash_len));
-            s.push('╯');
-            s
-        } else {
-            String::new()
+            BufferLogic::Explorer => false,
         };
 
         // Create a new Buffer instance
-        Ok(Self {
+        let mut buffer = Self {
             id: Uuid::nil(),       // nil UUID
             is_overlay: false,     // Default to not overlaying
             data,                  // Set the BufferData
@@ -314,26 +606,69 @@ impl Buffer {
             height,                // Height
             scroll_x: 0,           // Default scroll position
             scroll_y: 0,           // Default scroll position
-            file_path: Some(path), // Store the file path
             read_only: false,      // Default to not read-only
+            selection: None,
+            expanded_selections: vec![],
             visible: true,         // Default to visible
             line_numbers,
             show_status_line,
             bordered,
-            top_border,
-            bottom_border,
+            title: title.to_string(),
+            top_border: String::new(),
+            bottom_border: String::new(),
             logic,      // Default logic type is Editor
+            selector: None,
+            explorer: None,
+            completion: None,
+            path_completion: None,
+            completions: vec![],
             msg_sender, // Channel to send messages to editor
             paused_event_id: Uuid::nil(),
-        })
+        };
+        buffer.build_borders();
+
+        Ok(buffer)
+    }
+
+    /// Rebuilds the border strings for the current width, cutting the title off if it doesn't fit
+    fn build_borders(&mut self) {
+        if !self.bordered {
+            self.top_border.clear();
+            self.bottom_border.clear();
+            return;
+        }
+
+        let inner_width = (self.width as usize).saturating_sub(2);
+        let title: String = self.title.chars().take(inner_width).collect();
+
+        let mut top_border = String::from('╭');
+        top_border.push_str(&title);
+        top_border.push_str(&"─".repeat(inner_width - title.chars().count()));
+        top_border.push('╮');
+
+        let completions: String = self.completions.join("  ").chars().take(inner_width).collect();
+
+        let mut bottom_border = String::from('╰');
+        bottom_border.push_str(&completions);
+        bottom_border.push_str(&"─".repeat(inner_width - completions.chars().count()));
+        bottom_border.push('╯');
+
+        // Narrower than the corners themselves
+        let width = self.width as usize;
+        self.top_border = top_border.chars().take(width).collect();
+        self.bottom_border = bottom_border.chars().take(width).collect();
     }
 
     pub fn get_row(&self, row: usize) -> Option<String> {
-        let line = if let Some(line) = self.data.lines.get(row) {
-            line
-        } else {
-            return None;
+        let (sign, fold_marker) = match self.line_numbers {
+            true => {
+                let sign = self.line_diagnostic(row).map_or(' ', |_| '●');
+                (sign, self.fold_marker(row))
+            }
+            false => (' ', ' '),
         };
+        let document = self.data.document();
+        let line = document.lines().get(row)?;
 
         let mut num_chars = 0;
 
@@ -351,9 +686,13 @@ impl Buffer {
             num_chars += 1;
         }
 
-        let chars_to_take = self.width as usize - left - right;
+        let chars_to_take = (self.width as usize).saturating_sub(left + right);
 
         if self.line_numbers {
+            display_line.push(sign);
+            display_line.push(fold_marker);
+            num_chars += 2;
+
             let digits_count = (row + 1).ilog10() + 1;
             let spaces = (digits_in_line_nums - 1).saturating_sub(digits_count as usize);
             display_line.push_str(&" ".repeat(spaces));
@@ -366,7 +705,7 @@ impl Buffer {
             num_chars += 1;
         }
 
-        if let Some(line_chars) = self.data.data.get(line.start..=line.end) {
+        if let Some(line_chars) = document.chars().get(line.start..=line.end) {
             for ch in line_chars
                 .iter()
                 .skip(self.scroll_x)
@@ -388,18 +727,111 @@ impl Buffer {
         Some(display_line)
     }
 
+    /// Shown in the gutter of line `row`: '▸' on a folded region, '▾' on one that can be folded
+    fn fold_marker(&self, row: usize) -> char {
+        if self.data.has_fold_at(row) {
+            return '▸';
+        }
+
+        let regions = self.data.document_mut().fold_regions();
+        let i = regions.partition_point(|region| region.header < row);
+        match regions.get(i) {
+            Some(region) if region.header == row => '▾',
+            _ => ' ',
+        }
+    }
+
+    /// Returns the most severe diagnostic starting on line `row`, shown in the gutter and after
+    /// the text of the line
+    pub fn line_diagnostic(&self, row: usize) -> Option<(Severity, String)> {
+        let document = self.data.document();
+        let line = document.lines().get(row)?;
+
+        document
+            .diagnostics()
+            .iter()
+            .filter(|d| line.start <= d.start && d.start <= line.end)
+            .min_by_key(|d| d.severity)
+            .map(|d| (d.severity, d.summary().to_string()))
+    }
+
+    /// Returns the columns of line `row` covered by diagnostics, as runs from a start column up to
+    /// an end column with the most severe diagnostic over them. The '\n' of the line counts as one
+    /// more column, and empty ranges cover the column they are on.
+    pub fn diagnostic_columns(&self, row: usize) -> Vec<(usize, usize, Severity)> {
+        let document = self.data.document();
+        let Some(line) = document.lines().get(row) else {
+            return vec![];
+        };
+
+        let mut columns: Vec<Option<Severity>> = vec![None; line.len()];
+        for diagnostic in document.diagnostics() {
+            let end = std::cmp::max(diagnostic.end, diagnostic.start + 1);
+            if end <= line.start || diagnostic.start > line.end {
+                continue;
+            }
+
+            let start = diagnostic.start.saturating_sub(line.start);
+            let end = std::cmp::min(end - line.start, line.len());
+            for column in columns[start..end].iter_mut() {
+                *column = match *column {
+                    Some(severity) => Some(std::cmp::min(severity, diagnostic.severity)),
+                    None => Some(diagnostic.severity),
+                };
+            }
+        }
+
+        let mut runs: Vec<(usize, usize, Severity)> = vec![];
+        for (column, severity) in columns.into_iter().enumerate() {
+            let Some(severity) = severity else {
+                continue;
+            };
+            match runs.last_mut() {
+                Some((_, end, last)) if *end == column && *last == severity => *end += 1,
+                _ => runs.push((column, column + 1, severity)),
+            }
+        }
+        runs
+    }
+
+    /// Moves the cursor to the start of the next diagnostic after it, or the previous one before
+    /// it, going around the ends of the text. Returns the diagnostic, `None` if there is none.
+    pub fn goto_diagnostic(&mut self, forward: bool) -> Option<Diagnostic> {
+        self.sync();
+        let cursor = self.data.cursor;
+        let diagnostic = {
+            let document = self.data.document();
+            let diagnostics = document.diagnostics();
+            let found = match forward {
+                true => diagnostics
+                    .iter()
+                    .find(|d| d.start > cursor)
+                    .or(diagnostics.first()),
+                false => diagnostics
+                    .iter()
+                    .rev()
+                    .find(|d| d.start < cursor)
+                    .or(diagnostics.last()),
+            };
+            found?.clone()
+        };
+
+        self.clear_selection();
+        self.data.move_cursor_to_pos(diagnostic.start);
+        let (column, line) = self.cursor_xy_relative();
+        self.goto(line, column);
+        Some(diagnostic)
+    }
+
     pub fn get_status_line(&self) -> String {
         let mut line = String::with_capacity(self.width as usize);
         line.push(' ');
 
-        let file_name = match &self.file_path {
-            Some(path) => match path.file_name() {
-                Some(name) => name.to_str().unwrap().to_string(),
-                None => "NO NAME".to_string(),
-            }
-            None => "NO NAME".to_string(),
-        };
-        
+        let mut file_name = self.display_name();
+        if self.read_only {
+            file_name.push_str(" [RO]");
+        }
+
         let mut content_width = file_name.len();
         line.push_str(&file_name);
 
@@ -412,7 +844,7 @@ impl Buffer {
         // "(x, y)"
         content_width += 1 + cursor_x_str.len() + 2 + cursor_y_str.len() + 1;
 
-        for _ in 0..(self.width as usize - 2 - content_width) {
+        for _ in 0..(self.width as usize).saturating_sub(2 + content_width) {
             line.push(' ');
         }
 
@@ -427,113 +859,719 @@ impl Buffer {
     }
 
     pub fn get_padding(&self) -> Padding {
+        // Line numbers come after a column of diagnostic signs and one of fold markers
         let line_numbers_offset = if self.line_numbers {
-            self.data.digits_in_line_num()
+            2 + self.data.digits_in_line_num()
         } else {
             0
         };
 
         let border_offset = if self.bordered { 1 } else { 0 };
 
+        // The explorer draws a separator line on its right edge
+        let separator_offset = if self.logic == BufferLogic::Explorer { 1 } else { 0 };
+
         let status_line_offset = if self.show_status_line { 1 } else { 0 };
 
         Padding {
             top: border_offset,
-            right: border_offset,
+            right: border_offset + separator_offset,
             bottom: border_offset + status_line_offset,
             left: border_offset + line_numbers_offset,
         }
     }
 
-    pub fn set_paused_event_id(&mut self, id: Uuid) {
-        self.paused_event_id = id;
-    }
+    /// Returns the display line for a selector item, label on the left and detail on the right
+    pub fn get_selector_row(&self, item: &SelectorItem) -> String {
+        let Padding { right, left, .. } = self.get_padding();
+        let inner_width = (self.width as usize).saturating_sub(left + right);
 
-    pub fn set_path(&mut self, path: PathBuf) -> Result<(), ()> {
-        if path.file_name().is_none() || (path.exists() && path.is_dir()) {
-            return Err(());
-        } else if path.exists() && !path.is_file() {
-            return Err(());
+        let mut display_line = String::with_capacity(self.width as usize);
+        if self.bordered {
+            display_line.push('│');
         }
 
-        self.file_path = Some(path);
-        Ok(())
-    }
+        let detail = item.detail.as_deref().unwrap_or("");
+        let label_width = self.selector_label_width(item);
 
-    pub fn move_to(&mut self, x: u16, y: u16) {
-        self.x = x;
-        self.y = y;
-    }
+        let mut num_chars = 0;
+        for ch in item.label.chars().take(label_width) {
+            display_line.push(ch);
+            num_chars += 1;
+        }
 
-    pub fn resize(&mut self, w: u16, h: u16) {
-        self.width = w;
-        self.height = h;
-    }
+        let spaces = inner_width.saturating_sub(num_chars + detail.chars().count());
+        display_line.push_str(&" ".repeat(spaces));
+        num_chars += spaces;
 
-    /// Returns the cursor x, y position on Terminal
-    /// Position can be negative, which usually means cursor is currently outside the displayable bounds
-    #[allow(unused_assignments)]
-    pub fn cursor_xy(&self) -> (isize, isize) {
-        let mut x = 0isize;
-        let mut y = 0isize;
+        for ch in detail.chars().take(inner_width - num_chars) {
+            display_line.push(ch);
+        }
 
-        let Padding { left, top, .. } = self.get_padding();
+        if self.bordered {
+            display_line.push('│');
+        }
 
-        for Line { start, end } in self.data.lines.iter() {
-            if *start <= self.data.cursor && *end >= self.data.cursor {
-                x = self.data.cursor as isize - *start as isize - self.scroll_x as isize
-                    + left as isize;
+        display_line
+    }
 
-                return (
-                    x + self.x as isize,
-                    y - self.scroll_y as isize + self.y as isize + top as isize,
-                );
-            } else {
-                y += 1;
-            }
-        }
+    /// Number of columns available for the label of a selector item, the rest is used by its detail
+    pub fn selector_label_width(&self, item: &SelectorItem) -> usize {
+        let Padding { right, left, .. } = self.get_padding();
+        let inner_width = (self.width as usize).saturating_sub(left + right);
+        let detail_width = item.detail.as_ref().map_or(0, |d| d.chars().count() + 1);
 
-        let last_line = self
-            .data
-            .lines
-            .last()
-            .expect("Buffer should always have atleast one line");
+        inner_width.saturating_sub(detail_width)
+    }
 
-        (
-            last_line.end as isize - last_line.start as isize + 1 + self.x as isize
-                - self.scroll_x as isize
-                + left as isize,
-            y - 1 - self.scroll_y as isize + self.y as isize + top as isize,
-        )
+    /// Number of selector items that fit in the buffer, below the query line
+    pub fn selector_rows(&self) -> usize {
+        let Padding { top, bottom, .. } = self.get_padding();
+        (self.height as usize).saturating_sub(top + bottom + 1)
     }
 
-    /// Returns the x, y position of the cursor relative to current buffer only
-    #[allow(unused_assignments)]
-    pub fn cursor_xy_relative(&self) -> (usize, usize) {
-        let mut x = 0usize;
-        let mut y = 0usize;
+    pub fn set_selector(&mut self, selector: Selector) {
+        self.selector = Some(selector);
+    }
 
-        for Line { start, end } in self.data.lines.iter() {
-            if *start <= self.data.cursor && *end >= self.data.cursor {
-                x = self.data.cursor - *start;
+    pub fn set_explorer(&mut self, explorer: Explorer) {
+        self.explorer = Some(explorer);
+        self.sync_explorer(0);
+    }
 
-                return (x, y);
-            } else {
-                y += 1;
+    /// Re-reads the explorer tree from disk, keeping the selection on the same path if it still exists
+    pub fn refresh_explorer(&mut self, select: Option<PathBuf>) {
+        let line = self.data.current_line();
+        let selected = select.or_else(|| {
+            self.explorer
+                .as_ref()
+                .and_then(|e| e.get(line))
+                .map(|e| e.path.clone())
+        });
+
+        let index = match &mut self.explorer {
+            Some(explorer) => {
+                explorer.refresh();
+                selected
+                    .and_then(|p| explorer.index_of(&p))
+                    .unwrap_or(line)
             }
-        }
+            None => return,
+        };
 
-        let last_line = self
-            .data
-            .lines
-            .last()
-            .expect("Buffer should always have atleast one line");
+        self.sync_explorer(index);
+    }
 
-        (last_line.end - last_line.start + 1, y - 1)
+    /// Renders the explorer tree into the buffer and selects the entry at `index`
+    fn sync_explorer(&mut self, index: usize) {
+        if let Some(explorer) = &self.explorer {
+            self.data = BufferData::from(explorer.render());
+            self.data.move_cursor_to_line(index);
+            self.scroll();
+        }
     }
 
-    pub fn scroll(&mut self) {
-        let (x, y) = self.cursor_xy();
+    /// Moves the cursor to a 0-indexed line and column, and scrolls so it's in the middle of the buffer
+    pub fn goto(&mut self, line: usize, column: usize) {
+        self.sync();
+        self.data.move_cursor_to(line, column);
+
+        let Padding { top, bottom, .. } = self.get_padding();
+        let rows = (self.height as usize).saturating_sub(top + bottom);
+        self.data.reveal_cursor();
+        let (_, cursor_y) = self.cursor_xy_relative();
+        let hidden = self.data.hidden_lines();
+        self.scroll_y = line_at_row(&hidden, display_row(&hidden, cursor_y).saturating_sub(rows / 2));
+        self.scroll_x = 0;
+        self.scroll();
+    }
+
+    /// Moves the cursor to a 0-indexed line and column and restores a remembered scroll offset,
+    /// scrolling further only if the cursor would be out of view
+    pub fn restore_position(&mut self, line: usize, column: usize, scroll: (usize, usize)) {
+        self.sync();
+        self.data.move_cursor_to(line, column);
+
+        // The file may have gotten shorter since
+        (self.scroll_x, self.scroll_y) = scroll;
+        self.scroll_y = std::cmp::min(self.scroll_y, self.data.line_count().saturating_sub(1));
+        self.scroll();
+    }
+
+    /// Replaces the text of the buffer, placing the cursor at the end
+    pub fn set_contents(&mut self, text: &str) {
+        self.data = BufferData::from(text.to_string());
+        self.data.move_cursor_to_end();
+        self.scroll();
+    }
+
+    /// Replaces the whole text of the document, keeping the cursor on the same line and column.
+    /// The undo history is cleared, and the text counts as saved unless `modified` is set.
+    pub fn replace_text(&mut self, text: String, modified: bool) {
+        let (column, line) = self.cursor_xy_relative();
+        {
+            let mut document = self.data.document_mut();
+            document.replace_text(text);
+            if modified {
+                document.mark_modified();
+            }
+        }
+        // Folds are not kept over a whole new text
+        self.data.unfold_all();
+        self.sync();
+        self.data.move_cursor_to(line, column);
+        self.scroll();
+    }
+
+    /// Replaces ranges of the text, each from a start up to an end, as one undo group. The ranges
+    /// are positions in the text before any of them is replaced, and must not overlap.
+    pub fn apply_edits(&mut self, edits: Vec<(usize, usize, String)>) {
+        self.sync();
+        self.data.document_mut().replace_ranges(edits);
+        // The cursor and folds follow the edits like they follow edits made by other views
+        self.sync();
+    }
+
+    /// Returns true if another program modified the file since it was loaded or saved
+    pub fn changed_on_disk(&self) -> bool {
+        let document = self.data.document();
+        let (Some(path), Some(known)) = (&document.file_path, document.disk_modified) else {
+            return false;
+        };
+
+        match fs::metadata(path).and_then(|m| m.modified()) {
+            Ok(modified) => modified != known,
+            // Deleted, saving just writes it again
+            Err(_) => false,
+        }
+    }
+
+    /// Remembers the current modification time of the file, after it was loaded or saved
+    pub fn update_disk_modified(&mut self) {
+        let mut document = self.data.document_mut();
+        document.disk_modified = document
+            .file_path
+            .as_ref()
+            .and_then(|p| fs::metadata(p).and_then(|m| m.modified()).ok());
+    }
+
+    /// Forgets the modification time of the file, so the next save writes it without checking
+    pub fn forget_disk_modified(&mut self) {
+        self.data.document_mut().disk_modified = None;
+    }
+
+    /// Returns true if the buffer has edits that were not saved yet
+    pub fn is_modified(&self) -> bool {
+        self.logic == BufferLogic::Editor && self.data.document().is_modified()
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.data.document_mut().mark_saved();
+    }
+
+    pub fn undo(&mut self) {
+        self.sync();
+        self.clear_selection();
+        if self.data.undo() {
+            self.scroll();
+        }
+    }
+
+    pub fn redo(&mut self) {
+        self.sync();
+        self.clear_selection();
+        if self.data.redo() {
+            self.scroll();
+        }
+    }
+
+    /// Catches up with edits made through other views of the document
+    pub fn sync(&mut self) {
+        if self.data.sync() {
+            // The selected text may have moved or be gone
+            self.clear_selection();
+            self.scroll();
+        }
+    }
+
+    /// Creates another view of this buffer's document, with its own cursor and scroll position
+    pub fn new_view(&self) -> Buffer {
+        Buffer {
+            id: Uuid::nil(),
+            is_overlay: self.is_overlay,
+            data: self.data.new_view(),
+            x: self.x,
+            y: self.y,
+            width: self.width,
+            height: self.height,
+            scroll_x: self.scroll_x,
+            scroll_y: self.scroll_y,
+            read_only: self.read_only,
+            selection: None,
+            expanded_selections: vec![],
+            visible: self.visible,
+            line_numbers: self.line_numbers,
+            show_status_line: self.show_status_line,
+            bordered: self.bordered,
+            title: self.title.clone(),
+            top_border: self.top_border.clone(),
+            bottom_border: self.bottom_border.clone(),
+            logic: self.logic,
+            selector: None,
+            explorer: None,
+            completion: None,
+            path_completion: self.path_completion.clone(),
+            completions: vec![],
+            msg_sender: self.msg_sender.clone(),
+            paused_event_id: Uuid::nil(),
+        }
+    }
+
+    /// Returns true if both buffers are views of the same document
+    pub fn shares_document(&self, other: &Buffer) -> bool {
+        self.data.same_document(&other.data)
+    }
+
+    pub fn file_path(&self) -> Option<PathBuf> {
+        self.data.document().file_path.clone()
+    }
+
+    pub fn set_file_path(&mut self, path: Option<PathBuf>) {
+        self.data.document_mut().file_path = path;
+    }
+
+    /// Returns the text of line `row`, without its '\n'
+    pub fn line_text(&self, row: usize) -> String {
+        let document = self.data.document();
+        let Some(line) = document.lines().get(row) else {
+            return String::new();
+        };
+
+        let end = std::cmp::min(line.end + 1, document.len());
+        document.chars()[line.start..end]
+            .iter()
+            .filter(|c| **c != '\n')
+            .collect()
+    }
+
+    /// Highlights the text as `language`, or not at all
+    pub fn set_language(&mut self, language: Option<Rc<Language>>) {
+        self.data.document_mut().set_language(language);
+    }
+
+    /// Returns the highlighted parts of every line in `rows`, see `Document::highlights`
+    pub fn highlights(&self, rows: &[usize]) -> Vec<Vec<Span>> {
+        self.data.document_mut().highlights(rows)
+    }
+
+    pub fn selection(&self) -> Option<(usize, usize)> {
+        self.selection
+    }
+
+    /// Returns the selected columns of line `row`, its '\n' counting as one more column
+    pub fn selected_columns(&self, row: usize) -> Option<(usize, usize)> {
+        let (start, end) = self.selection?;
+        let document = self.data.document();
+        let line = document.lines().get(row)?;
+
+        let start = std::cmp::max(start, line.start);
+        let end = std::cmp::min(end, line.end + 1);
+        (start < end).then(|| (start - line.start, end - line.start))
+    }
+
+    fn clear_selection(&mut self) {
+        self.selection = None;
+        self.expanded_selections.clear();
+    }
+
+    /// Selects the word at the cursor, or else the smallest syntax node around the selection or
+    /// the cursor. The cursor moves to the start of the selection.
+    /// Returns false if the buffer has no syntax tree.
+    pub fn expand_selection(&mut self) -> bool {
+        self.sync();
+        let Some(tree) = self.data.document_mut().syntax_tree() else {
+            return false;
+        };
+
+        let cursor = self.data.cursor;
+        let range = match self.selection {
+            Some((start, end)) => tree.enclosing_range(start, end),
+            None => self
+                .word_at(cursor)
+                .or_else(|| tree.enclosing_range(cursor, cursor)),
+        };
+
+        if let Some((start, end)) = range {
+            self.expanded_selections.push(self.selection);
+            self.selection = Some((start, end));
+            self.data.move_cursor_to_pos(start);
+            self.scroll();
+        }
+        true
+    }
+
+    /// Goes back to the selection before the last expansion
+    pub fn shrink_selection(&mut self) {
+        let Some(selection) = self.expanded_selections.pop() else {
+            return;
+        };
+
+        self.selection = selection;
+        if let Some((start, _)) = selection {
+            self.data.move_cursor_to_pos(start);
+            self.scroll();
+        }
+    }
+
+    /// Returns the word around the cursor at `pos`, or just before it
+    fn word_at(&self, pos: usize) -> Option<(usize, usize)> {
+        let document = self.data.document();
+        let chars = document.chars();
+        let is_word = |i: usize| {
+            chars
+                .get(i)
+                .is_some_and(|c| c.is_alphanumeric() || *c == '_')
+        };
+
+        let mut start = pos;
+        while start > 0 && is_word(start - 1) {
+            start -= 1;
+        }
+        let mut end = pos;
+        while is_word(end) {
+            end += 1;
+        }
+        (start < end).then_some((start, end))
+    }
+
+    /// Returns where the word ending at the cursor starts, the cursor itself if there is none
+    pub fn word_start(&self) -> usize {
+        let cursor = self.data.cursor;
+        match self.word_at(cursor) {
+            Some((start, _)) => start,
+            None => cursor,
+        }
+    }
+
+    /// Returns the word at the cursor, empty if there is none
+    pub fn word_at_cursor(&self) -> String {
+        let Some((start, end)) = self.word_at(self.data.cursor) else {
+            return String::new();
+        };
+        self.data.document().chars()[start..end].iter().collect()
+    }
+
+    /// Returns the part of the word before the cursor, the cursor being at its end while typing
+    pub fn typed_word(&self) -> String {
+        let (start, end) = (self.word_start(), self.data.cursor);
+        self.data.document().chars()[start..end].iter().collect()
+    }
+
+    /// Replaces the text from `start` up to the cursor with `text`, like a completion of the word
+    /// being typed, in one undo group
+    pub fn complete_word(&mut self, start: usize, text: &str) {
+        self.sync();
+        self.clear_selection();
+        let start = std::cmp::min(start, self.data.cursor);
+
+        self.data.document_mut().begin_edit();
+        self.data.remove_range(start, self.data.cursor);
+        for ch in text.chars() {
+            self.data.insert_ch(ch);
+        }
+        self.data.document_mut().end_edit();
+        self.scroll();
+    }
+
+    /// Moves the cursor to the start of the syntax node around it.
+    /// Returns false if the buffer has no syntax tree.
+    pub fn goto_parent_node(&mut self) -> bool {
+        self.sync();
+        let Some(tree) = self.data.document_mut().syntax_tree() else {
+            return false;
+        };
+
+        if let Some(pos) = tree.parent_start(self.data.cursor) {
+            self.clear_selection();
+            self.data.move_cursor_to_pos(pos);
+            self.scroll();
+        }
+        true
+    }
+
+    /// Moves the cursor to the start of the next syntax node on the same level, or the previous
+    /// one. Returns false if the buffer has no syntax tree.
+    pub fn goto_sibling_node(&mut self, forward: bool) -> bool {
+        self.sync();
+        let Some(tree) = self.data.document_mut().syntax_tree() else {
+            return false;
+        };
+
+        if let Some(pos) = tree.sibling_start(self.data.cursor, forward) {
+            self.clear_selection();
+            self.data.move_cursor_to_pos(pos);
+            self.scroll();
+        }
+        true
+    }
+
+    /// Returns up to `count` lines shown from the top of the buffer, skipping folded ones
+    pub fn visible_lines(&self, count: usize) -> Vec<usize> {
+        let hidden = self.data.hidden_lines();
+        let first = line_at_row(&hidden, display_row(&hidden, self.scroll_y));
+
+        (first..self.data.line_count())
+            .filter(|line| !is_hidden(&hidden, *line))
+            .take(count)
+            .collect()
+    }
+
+    /// Folds the innermost region around the cursor that is not folded yet, and moves the cursor
+    /// to its header. Returns false if there is none.
+    pub fn fold(&mut self) -> bool {
+        self.sync();
+        let line = self.data.current_line();
+        let regions = self.data.document_mut().fold_regions();
+
+        let region = regions.iter().rev().find(|region| {
+            region.header <= line
+                && line <= region.last
+                && !self.data.is_folded(region.header, region.last)
+        });
+        let Some(region) = region else {
+            return false;
+        };
+
+        self.data.fold(region.header, region.last);
+        self.move_out_of_folds();
+        true
+    }
+
+    /// Opens the fold on the line of the cursor. Returns false if there is none.
+    pub fn unfold(&mut self) -> bool {
+        self.sync();
+        let unfolded = self.data.unfold(self.data.current_line());
+        self.scroll();
+        unfolded
+    }
+
+    /// Opens all folds, then folds every region at `level` or deeper
+    pub fn fold_to_level(&mut self, level: usize) {
+        self.sync();
+        self.data.unfold_all();
+
+        let regions = self.data.document_mut().fold_regions();
+        for region in regions.iter().filter(|region| region.level >= level) {
+            self.data.fold(region.header, region.last);
+        }
+        self.move_out_of_folds();
+    }
+
+    pub fn unfold_all(&mut self) {
+        self.data.unfold_all();
+        self.scroll();
+    }
+
+    /// Moves the cursor from a folded line to the header of the outermost fold hiding it, in the
+    /// same column
+    fn move_out_of_folds(&mut self) {
+        let (column, line) = self.cursor_xy_relative();
+        let hidden = self.data.hidden_lines();
+
+        if let Some((first, _)) = hidden.iter().find(|(f, l)| *f <= line && line <= *l) {
+            self.data.move_cursor_to(first - 1, column);
+        }
+        self.scroll();
+    }
+
+    /// Starts a new line. In a known language it's indented one level deeper than the line
+    /// opening the bracket around the cursor, and a closing bracket right after the cursor moves
+    /// to a line of its own. Elsewhere the indentation of the current line is kept.
+    fn insert_newline(&mut self) {
+        let cursor = self.data.cursor;
+        let (indent, closing_indent) = {
+            let mut document = self.data.document_mut();
+            let tree = document.syntax_tree();
+            let indent_of = |pos: usize| -> String {
+                let line = &document.lines()[document.line_of(pos)];
+                document.chars()[line.start..std::cmp::max(line.start, pos)]
+                    .iter()
+                    .take_while(|c| **c == ' ' || **c == '\t')
+                    .collect()
+            };
+
+            match tree {
+                // Plain text is not indented
+                None => (String::new(), None),
+                Some(tree) => {
+                    let path = tree.path_at(cursor);
+                    let group = path.iter().rev().find(|node| {
+                        node.start < cursor && !matches!(node.kind, NodeKind::Named(_))
+                    });
+
+                    match group.map(|node| (node.kind, node.start, node.end)) {
+                        Some((NodeKind::Group { close, .. }, start, end)) => {
+                            let base = indent_of(start);
+                            let unit = if base.contains('\t') { "\t" } else { "    " };
+                            let closing = (end == cursor + 1
+                                && document.chars().get(cursor) == Some(&close))
+                            .then(|| base.clone());
+                            (base + unit, closing)
+                        }
+                        // Inside a multiline string or comment, and outside of brackets
+                        _ => (indent_of(cursor), None),
+                    }
+                }
+            }
+        };
+
+        // Undone in one go, like a single character
+        self.data.document_mut().begin_edit();
+        self.data.insert_ch('\n');
+        for ch in indent.chars() {
+            self.data.insert_ch(ch);
+        }
+        if let Some(closing_indent) = closing_indent {
+            let cursor = self.data.cursor;
+            self.data.insert_ch('\n');
+            for ch in closing_indent.chars() {
+                self.data.insert_ch(ch);
+            }
+            self.data.move_cursor_to_pos(cursor);
+        }
+        self.data.document_mut().end_edit();
+    }
+
+    /// Name shown in tabs and the status line
+    pub fn display_name(&self) -> String {
+        match &self.data.document().file_path {
+            Some(path) => match path.file_name() {
+                Some(name) => name.to_string_lossy().to_string(),
+                None => "NO NAME".to_string(),
+            },
+            // Scratch buffers, like diffs, are named by their title
+            None if !self.title.is_empty() => self.title.clone(),
+            None => "NO NAME".to_string(),
+        }
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// A read-only buffer can be scrolled and navigated, but its text can't be edited
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// Shown when trying to edit a read-only buffer
+    pub fn read_only_message(&self) -> String {
+        format!("{} is read-only", self.display_name())
+    }
+
+    /// Makes Tab complete the text of an input box as a path, relative paths start from `root`
+    pub fn set_path_completion(&mut self, root: Option<PathBuf>) {
+        self.path_completion = root;
+    }
+
+    /// Completes the typed path, or lists the entries it could continue with
+    fn complete_path(&mut self) {
+        let Some(root) = &self.path_completion else {
+            return;
+        };
+        let text = self.data.to_string();
+        let completion = path_completion::complete(root, &text);
+
+        if completion.text != text {
+            self.set_contents(&completion.text);
+        }
+        self.completions = completion.candidates;
+        self.build_borders();
+    }
+
+    pub fn set_paused_event_id(&mut self, id: Uuid) {
+        self.paused_event_id = id;
+    }
+
+    pub fn set_path(&mut self, path: PathBuf) -> Result<(), ()> {
+        if path.file_name().is_none() || (path.exists() && !path.is_file()) {
+            return Err(());
+        }
+
+        self.set_file_path(Some(path));
+        Ok(())
+    }
+
+    pub fn move_to(&mut self, x: u16, y: u16) {
+        self.x = x;
+        self.y = y;
+    }
+
+    /// Resizes the buffer, keeping the cursor (or selected item) in view
+    pub fn resize(&mut self, w: u16, h: u16) {
+        self.width = w;
+        self.height = h;
+        self.build_borders();
+
+        let rows = self.selector_rows();
+        if let Some(selector) = &mut self.selector {
+            selector.scroll_to_selection(rows);
+        }
+
+        // Undo horizontal scrolling a narrower size needed, if the cursor fits again
+        let Padding { left, right, .. } = self.get_padding();
+        let (cursor_x, _) = self.cursor_xy_relative();
+        if cursor_x < (self.width as usize).saturating_sub(left + right) {
+            self.scroll_x = 0;
+        }
+        self.scroll();
+    }
+
+    /// Returns the cursor x, y position on Terminal
+    /// Position can be negative, which usually means cursor is currently outside the displayable bounds
+    pub fn cursor_xy(&self) -> (isize, isize) {
+        let Padding { left, top, .. } = self.get_padding();
+        let (column, line) = self.cursor_xy_relative();
+
+        // Folded lines take no rows
+        let hidden = self.data.hidden_lines();
+        let row = display_row(&hidden, line) as isize - display_row(&hidden, self.scroll_y) as isize;
+
+        (
+            column as isize - self.scroll_x as isize + left as isize + self.x as isize,
+            row + self.y as isize + top as isize,
+        )
+    }
+
+    /// Returns the x, y position of the cursor relative to current buffer only
+    #[allow(unused_assignments)]
+    pub fn cursor_xy_relative(&self) -> (usize, usize) {
+        let mut x = 0usize;
+        let mut y = 0usize;
+
+        let document = self.data.document();
+        for Line { start, end } in document.lines().iter() {
+            if *start <= self.data.cursor && *end >= self.data.cursor {
+                x = self.data.cursor - *start;
+
+                return (x, y);
+            } else {
+                y += 1;
+            }
+        }
+
+        let last_line = document
+            .lines()
+            .last()
+            .expect("Buffer should always have atleast one line");
+
+        (last_line.end - last_line.start + 1, y - 1)
+    }
+
+    pub fn scroll(&mut self) {
+        // The cursor may have jumped into a folded region
+        self.data.reveal_cursor();
+        let (x, y) = self.cursor_xy();
 
         let Padding {
             top,
@@ -547,12 +1585,15 @@ impl Buffer {
         let top_bound = self.y as isize + top as isize;
         let bot_bound = (self.y + self.height) as isize - bottom as isize;
 
+        // Scrolled by rows, the folded lines between them are skipped
+        let hidden = self.data.hidden_lines();
+        let scroll_row = display_row(&hidden, self.scroll_y);
         if y < top_bound {
             let dy = top_bound.saturating_sub(y) as usize;
-            self.scroll_y = self.scroll_y.saturating_sub(dy);
+            self.scroll_y = line_at_row(&hidden, scroll_row.saturating_sub(dy));
         } else if y >= bot_bound {
             let dy = y - bot_bound + 1;
-            self.scroll_y += dy as usize;
+            self.scroll_y = line_at_row(&hidden, scroll_row + dy as usize);
         }
 
         if x < left_bound {
@@ -565,18 +1606,17 @@ impl Buffer {
     }
 
     pub fn parse_input(&mut self, event: Event) {
+        self.sync();
+
         match self.logic {
             BufferLogic::Editor => self.editor_logic(event),
             BufferLogic::InputBox => self.input_box_logic(event),
-            BufferLogic::Selector => todo!(),
+            BufferLogic::Selector => self.selector_logic(event),
+            BufferLogic::Explorer => self.explorer_logic(event),
         }
     }
 
     pub fn editor_logic(&mut self, event: Event) {
-        if self.read_only {
-            return;
-        }
-
         if let Event::Key(KeyEvent {
             code,
             modifiers,
@@ -584,21 +1624,36 @@ impl Buffer {
             ..
         }) = event
         {
-            match (code, modifiers) {
-                // Save As: Ctrl + Shift + S
-                (KeyCode::Char('S'), modifier)
-                    if modifier == KeyModifiers::CONTROL | KeyModifiers::SHIFT =>
-                {
-                    self.msg_sender
-                        .send(EditorEvent::Buffer(BufferEvent::SaveAs { id: self.id }))
-                        .expect("Failed to send a msg to the editor");
-                }
-                // Save: Ctrl + S
-                (KeyCode::Char('s'), KeyModifiers::CONTROL) => {
-                    self.msg_sender
-                        .send(EditorEvent::Buffer(BufferEvent::Save { id: self.id }))
-                        .expect("Failed to send a msg to the editor");
+            // NOTE: Key bindings for editor commands (Save, Save As, ...) are handled by the
+            //       `Editor` before the event reaches the buffer. See `command.rs`
+
+            // Typing replaces the selection, Backspace and Delete delete it, other keys drop it.
+            // Replacing it is undone in one go.
+            let mut replacing = false;
+            if let Some((start, end)) = self.selection {
+                self.clear_selection();
+
+                let edits = matches!(
+                    (code, modifiers),
+                    (KeyCode::Char(_), KeyModifiers::NONE | KeyModifiers::SHIFT)
+                        | (
+                            KeyCode::Enter | KeyCode::Backspace | KeyCode::Delete,
+                            KeyModifiers::NONE
+                        )
+                );
+                if edits && !self.read_only {
+                    self.data.document_mut().begin_edit();
+                    replacing = true;
+                    self.data.remove_range(start, end);
+                    if matches!(code, KeyCode::Backspace | KeyCode::Delete) {
+                        self.data.document_mut().end_edit();
+                        self.scroll();
+                        return;
+                    }
                 }
+            }
+
+            match (code, modifiers) {
                 // Arrow Keys (No modifiers)
                 (KeyCode::Left, KeyModifiers::NONE) => {
                     self.data.move_cursor_left(1);
@@ -612,6 +1667,15 @@ impl Buffer {
                 (KeyCode::Down, KeyModifiers::NONE) => {
                     self.data.move_cursor_down(1);
                 }
+                // Keys that edit the text only say why nothing happens in a read-only buffer
+                (KeyCode::Char(_), KeyModifiers::NONE | KeyModifiers::SHIFT)
+                | (KeyCode::Enter | KeyCode::Backspace | KeyCode::Delete, KeyModifiers::NONE)
+                    if self.read_only =>
+                {
+                    self.msg_sender
+                        .send(EditorEvent::ShowMessage(self.read_only_message()))
+                        .unwrap();
+                }
                 // Character insertion without modifiers
                 (KeyCode::Char(c), KeyModifiers::NONE) => {
                     self.data.insert_ch(c);
@@ -622,7 +1686,7 @@ impl Buffer {
                 }
                 // Enter key
                 (KeyCode::Enter, KeyModifiers::NONE) => {
-                    self.data.insert_ch('\n');
+                    self.insert_newline();
                 }
                 // Backspace key
                 (KeyCode::Backspace, KeyModifiers::NONE) => {
@@ -635,12 +1699,46 @@ impl Buffer {
                 // Catch-all for any other key combinations (could be used for logging or other actions)
                 _ => (),
             }
+
+            if replacing {
+                self.data.document_mut().end_edit();
+            }
         }
 
-        self.data.recalculate_lines();
         self.scroll();
     }
 
+    /// Handles the keys of the word completions while they are listed: the arrows select a word,
+    /// Tab and Enter complete it, and Esc closes the list. Returns false for the other keys.
+    pub fn completion_logic(&mut self, event: &Event) -> bool {
+        let Some(completion) = &mut self.completion else {
+            return false;
+        };
+        let Event::Key(KeyEvent {
+            code,
+            modifiers: KeyModifiers::NONE,
+            kind: KeyEventKind::Press,
+            ..
+        }) = event
+        else {
+            return false;
+        };
+
+        match code {
+            KeyCode::Up => completion.select_prev(),
+            KeyCode::Down => completion.select_next(),
+            KeyCode::Tab | KeyCode::Enter => {
+                let word_start = completion.word_start;
+                let word = completion.selected_word().unwrap_or_default().to_string();
+                self.completion = None;
+                self.complete_word(word_start, &word);
+            }
+            KeyCode::Esc => self.completion = None,
+            _ => return false,
+        }
+        true
+    }
+
     pub fn input_box_logic(&mut self, event: Event) {
         if self.read_only {
             return;
@@ -676,9 +1774,14 @@ impl Buffer {
                     self.data.insert_ch(c.to_ascii_uppercase());
                 }
 
+                (KeyCode::Tab, KeyModifiers::NONE) if self.path_completion.is_some() => {
+                    self.complete_path();
+                    return;
+                }
+
                 // Enter key press
                 (KeyCode::Enter, KeyModifiers::NONE) => {
-                    let result: String = self.data.data.iter().collect();
+                    let result = self.data.to_string();
                     self.msg_sender
                         .send(EditorEvent::Buffer(BufferEvent::ResumeEvent {
                             paused_event_id: self.paused_event_id,
@@ -719,9 +1822,311 @@ impl Buffer {
                 // Catch-all for other key events that don't match any of the cases
                 _ => (),
             }
+
+            // The listed completions are only for the text they were found for
+            if !self.completions.is_empty() {
+                self.completions.clear();
+                self.build_borders();
+            }
+        }
+
+        self.scroll();
+    }
+
+    pub fn selector_logic(&mut self, event: Event) {
+        if let Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press,
+            ..
+        }) = event
+        {
+            let mut query_changed = false;
+
+            match (code, modifiers) {
+                // Query cursor movement
+                (KeyCode::Left, KeyModifiers::NONE) => {
+                    self.data.move_cursor_left(1);
+                }
+                (KeyCode::Right, KeyModifiers::NONE) => {
+                    self.data.move_cursor_right(1);
+                }
+
+                // Selection movement
+                (KeyCode::Up, KeyModifiers::NONE) => {
+                    if let Some(selector) = &mut self.selector {
+                        selector.select_prev(1);
+                    }
+                }
+                (KeyCode::Down, KeyModifiers::NONE) => {
+                    if let Some(selector) = &mut self.selector {
+                        selector.select_next(1);
+                    }
+                }
+                (KeyCode::PageUp, KeyModifiers::NONE) => {
+                    let rows = self.selector_rows();
+                    if let Some(selector) = &mut self.selector {
+                        selector.select_prev(rows);
+                    }
+                }
+                (KeyCode::PageDown, KeyModifiers::NONE) => {
+                    let rows = self.selector_rows();
+                    if let Some(selector) = &mut self.selector {
+                        selector.select_next(rows);
+                    }
+                }
+                (KeyCode::Home, KeyModifiers::CONTROL) => {
+                    if let Some(selector) = &mut self.selector {
+                        selector.select_first();
+                    }
+                }
+                (KeyCode::End, KeyModifiers::CONTROL) => {
+                    if let Some(selector) = &mut self.selector {
+                        selector.select_last();
+                    }
+                }
+
+                // Query editing
+                (KeyCode::Char(c), KeyModifiers::NONE) => {
+                    self.data.insert_ch(c);
+                    query_changed = true;
+                }
+                (KeyCode::Char(c), KeyModifiers::SHIFT) => {
+                    self.data.insert_ch(c.to_ascii_uppercase());
+                    query_changed = true;
+                }
+                (KeyCode::Backspace, KeyModifiers::NONE) => {
+                    self.data.backspace();
+                    query_changed = true;
+                }
+                (KeyCode::Delete, KeyModifiers::NONE) => {
+                    self.data.delete();
+                    query_changed = true;
+                }
+
+                // Choose the selected item
+                (KeyCode::Enter, KeyModifiers::NONE) => {
+                    let selected = self
+                        .selector
+                        .as_ref()
+                        .and_then(|s| s.selected_item())
+                        .map(|item| item.value.clone());
+
+                    let event = match selected {
+                        Some(result) => BufferEvent::ResumeEvent {
+                            paused_event_id: self.paused_event_id,
+                            result,
+                        },
+                        None => BufferEvent::CancelEvent {
+                            paused_event_id: self.paused_event_id,
+                        },
+                    };
+                    self.msg_sender.send(EditorEvent::Buffer(event)).unwrap();
+                    self.msg_sender
+                        .send(EditorEvent::Buffer(BufferEvent::Close {
+                            id: self.id,
+                            is_overlay: self.is_overlay,
+                        }))
+                        .unwrap();
+                }
+
+                // Cancel
+                (KeyCode::Esc, KeyModifiers::NONE) => {
+                    self.msg_sender
+                        .send(EditorEvent::Buffer(BufferEvent::CancelEvent {
+                            paused_event_id: self.paused_event_id,
+                        }))
+                        .unwrap();
+                    self.msg_sender
+                        .send(EditorEvent::Buffer(BufferEvent::Close {
+                            id: self.id,
+                            is_overlay: self.is_overlay,
+                        }))
+                        .unwrap();
+                }
+
+                _ => (),
+            }
+
+            if query_changed {
+                let query = self.data.to_string();
+                if let Some(selector) = &mut self.selector {
+                    selector.filter(&query);
+                }
+            }
+
+            let rows = self.selector_rows();
+            if let Some(selector) = &mut self.selector {
+                selector.scroll_to_selection(rows);
+            }
         }
 
-        self.data.recalculate_lines();
         self.scroll();
     }
+
+    pub fn explorer_logic(&mut self, event: Event) {
+        let Some(explorer) = &mut self.explorer else {
+            return;
+        };
+
+        let Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press,
+            ..
+        }) = event
+        else {
+            return;
+        };
+
+        let index = self.data.current_line();
+        let entry = match explorer.get(index) {
+            Some(entry) => entry,
+            None => return,
+        };
+        let (path, is_dir) = (entry.path.clone(), entry.is_dir);
+
+        let mut select = index;
+        let mut request = None;
+
+        match (code, modifiers) {
+            (KeyCode::Up, KeyModifiers::NONE) => {
+                select = index.saturating_sub(1);
+            }
+            (KeyCode::Down, KeyModifiers::NONE) => {
+                select = index + 1;
+            }
+            (KeyCode::PageUp, KeyModifiers::NONE) => {
+                select = index.saturating_sub(self.height as usize);
+            }
+            (KeyCode::PageDown, KeyModifiers::NONE) => {
+                select = index + self.height as usize;
+            }
+            (KeyCode::Home, KeyModifiers::NONE) => {
+                select = 0;
+            }
+            (KeyCode::End, KeyModifiers::NONE) => {
+                select = usize::MAX;
+            }
+
+            // Open a file, or expand/collapse a folder
+            (KeyCode::Enter, KeyModifiers::NONE) => {
+                if is_dir {
+                    explorer.toggle(index);
+                } else {
+                    self.msg_sender
+                        .send(EditorEvent::OpenFile(path.clone()))
+                        .expect("Failed to send a msg to the editor");
+                }
+            }
+            (KeyCode::Right, KeyModifiers::NONE) if is_dir => {
+                explorer.expand(index);
+            }
+            // Collapse the folder, or jump to the parent folder
+            (KeyCode::Left, KeyModifiers::NONE) => {
+                if is_dir && explorer.is_expanded(&path) && index != 0 {
+                    explorer.collapse(index);
+                } else if let Some(parent) = explorer.parent_index(index) {
+                    select = parent;
+                }
+            }
+
+            // File operations
+            (KeyCode::Char('n'), KeyModifiers::NONE) => {
+                request = Some(ExplorerEvent::NewFile {
+                    dir: explorer.target_dir(index),
+                });
+            }
+            (KeyCode::Char('N'), KeyModifiers::SHIFT) => {
+                request = Some(ExplorerEvent::NewFolder {
+                    dir: explorer.target_dir(index),
+                });
+            }
+            // The root folder can't be renamed or deleted from here
+            (KeyCode::Char('r'), KeyModifiers::NONE) | (KeyCode::F(2), KeyModifiers::NONE)
+                if index != 0 =>
+            {
+                request = Some(ExplorerEvent::Rename { path });
+            }
+            (KeyCode::Char('d'), KeyModifiers::NONE) | (KeyCode::Delete, KeyModifiers::NONE)
+                if index != 0 =>
+            {
+                request = Some(ExplorerEvent::Delete { path });
+            }
+            (KeyCode::F(5), KeyModifiers::NONE) => {
+                explorer.refresh();
+            }
+            _ => (),
+        }
+
+        if let Some(event) = request {
+            self.msg_sender
+                .send(EditorEvent::Explorer { id: self.id, event })
+                .expect("Failed to send a msg to the editor");
+        }
+
+        let select = std::cmp::min(select, explorer.entries().len().saturating_sub(1));
+        self.sync_explorer(select);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(start: usize, end: usize, severity: Severity) -> Diagnostic {
+        Diagnostic {
+            start,
+            end,
+            severity,
+            message: String::new(),
+            source: "test".to_string(),
+        }
+    }
+
+    #[test]
+    fn diagnostic_columns_show_the_most_severe() {
+        let (sender, _receiver) = std::sync::mpsc::channel();
+        let mut buffer = Buffer::new(
+            PathBuf::new(),
+            0,
+            0,
+            40,
+            10,
+            false,
+            false,
+            BufferLogic::Editor,
+            "",
+            sender,
+        )
+        .unwrap();
+        buffer.data = BufferData::from("let bad = 1;\nnext\n".to_string());
+        buffer.data.document_mut().set_diagnostics(
+            "test",
+            vec![
+                diagnostic(4, 7, Severity::Error),
+                diagnostic(0, 9, Severity::Warning),
+                // Over the '\n' of the first line
+                diagnostic(10, 15, Severity::Information),
+                // Empty, at the end of the second line
+                diagnostic(17, 17, Severity::Hint),
+            ],
+        );
+
+        assert_eq!(
+            buffer.diagnostic_columns(0),
+            vec![
+                (0, 4, Severity::Warning),
+                (4, 7, Severity::Error),
+                (7, 9, Severity::Warning),
+                (10, 13, Severity::Information),
+            ]
+        );
+        assert_eq!(
+            buffer.diagnostic_columns(1),
+            vec![(0, 2, Severity::Information), (4, 5, Severity::Hint)]
+        );
+        assert_eq!(buffer.diagnostic_columns(2), vec![]);
+        assert_eq!(buffer.diagnostic_columns(3), vec![]);
+    }
 }
@@ -0,0 +1,410 @@
+#![allow(dead_code)]
+use std::fmt;
+
+use crossterm::event::{KeyCode, KeyModifiers};
+
+/// A key combination that triggers a `Command`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct KeyBinding {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    pub const fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        Self { code, modifiers }
+    }
+
+    /// Checks if a key press matches this binding.
+    ///
+    /// NOTE: Terminals report Shift + letter as either upper or lower case, so letters are compared
+    /// in upper case whenever Shift is held.
+    pub fn matches(&self, code: KeyCode, modifiers: KeyModifiers) -> bool {
+        let code = match code {
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => {
+                KeyCode::Char(c.to_ascii_uppercase())
+            }
+            code => code,
+        };
+
+        self.code == code && self.modifiers == modifiers
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = vec![];
+
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            parts.push("Ctrl".to_string());
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            parts.push("Alt".to_string());
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            parts.push("Shift".to_string());
+        }
+
+        let key = match self.code {
+            KeyCode::Char(' ') => "Space".to_string(),
+            KeyCode::Char(c) => c.to_ascii_uppercase().to_string(),
+            KeyCode::F(n) => format!("F{n}"),
+            KeyCode::Tab | KeyCode::BackTab => "Tab".to_string(),
+            KeyCode::Enter => "Enter".to_string(),
+            KeyCode::Esc => "Esc".to_string(),
+            KeyCode::Backspace => "Backspace".to_string(),
+            KeyCode::Delete => "Delete".to_string(),
+            KeyCode::Left => "Left".to_string(),
+            KeyCode::Right => "Right".to_string(),
+            KeyCode::Up => "Up".to_string(),
+            KeyCode::Down => "Down".to_string(),
+            KeyCode::PageUp => "PageUp".to_string(),
+            KeyCode::PageDown => "PageDown".to_string(),
+            KeyCode::Home => "Home".to_string(),
+            KeyCode::End => "End".to_string(),
+            code => format!("{code:?}"),
+        };
+        parts.push(key);
+
+        write!(f, "{}", parts.join(" + "))
+    }
+}
+
+/// Every editor level action that can be triggered by a key binding or from the command palette
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Command {
+    Save,
+    SaveAs,
+    /// Replaces the buffer's text with its file on disk
+    ReloadFile,
+    /// Switches the buffer between read-only and editable
+    ToggleReadOnly,
+    Undo,
+    Redo,
+    /// Selects the syntax node around the selection or the cursor
+    ExpandSelection,
+    /// Restores the selection before the last expansion
+    ShrinkSelection,
+    GotoParentNode,
+    GotoNextSibling,
+    GotoPrevSibling,
+    /// Folds the innermost region around the cursor
+    Fold,
+    /// Opens the fold on the cursor's line
+    Unfold,
+    /// Asks for a level, and folds every region that deep or deeper
+    FoldToLevel,
+    UnfoldAll,
+    /// Shows what the language server knows about the symbol at the cursor
+    Hover,
+    GotoDefinition,
+    /// Lists the places where the symbol at the cursor is used
+    FindReferences,
+    /// Asks for a new name, and renames the symbol at the cursor everywhere
+    RenameSymbol,
+    /// Formats the buffer with its language server
+    FormatDocument,
+    /// Lists the completions the language server has for the word at the cursor
+    ShowCompletions,
+    /// Lists the words of the open buffers completing the one being typed
+    CompleteWord,
+    /// Moves the cursor to the next problem in the buffer, wrapping around at the end
+    NextDiagnostic,
+    PrevDiagnostic,
+    /// Lists the problems of all open buffers, the most severe first
+    ListDiagnostics,
+    CloseBuffer,
+    OpenCommandPalette,
+    FindFile,
+    NextBuffer,
+    PrevBuffer,
+    /// Activates the buffer in the nth tab (1-indexed)
+    GotoBuffer(u8),
+    SwitchBuffer,
+    ToggleExplorer,
+    FocusExplorer,
+    /// Splits the active pane, placing the new pane on the right
+    SplitRight,
+    /// Splits the active pane, placing the new pane below
+    SplitDown,
+    ClosePane,
+    FocusPaneLeft,
+    FocusPaneRight,
+    FocusPaneUp,
+    FocusPaneDown,
+    GrowPaneWidth,
+    ShrinkPaneWidth,
+    GrowPaneHeight,
+    ShrinkPaneHeight,
+    /// Gives all panes the same size
+    EqualizePanes,
+    Quit,
+    /// Quits without asking about unsaved changes
+    ForceQuit,
+}
+
+impl Command {
+    /// All registered commands, in the order they are listed in the command palette
+    pub const ALL: &'static [Command] = &[
+        Command::Save,
+        Command::SaveAs,
+        Command::ReloadFile,
+        Command::ToggleReadOnly,
+        Command::Undo,
+        Command::Redo,
+        Command::ExpandSelection,
+        Command::ShrinkSelection,
+        Command::GotoParentNode,
+        Command::GotoNextSibling,
+        Command::GotoPrevSibling,
+        Command::Fold,
+        Command::Unfold,
+        Command::FoldToLevel,
+        Command::UnfoldAll,
+        Command::Hover,
+        Command::GotoDefinition,
+        Command::FindReferences,
+        Command::RenameSymbol,
+        Command::FormatDocument,
+        Command::ShowCompletions,
+        Command::CompleteWord,
+        Command::NextDiagnostic,
+        Command::PrevDiagnostic,
+        Command::ListDiagnostics,
+        Command::CloseBuffer,
+        Command::OpenCommandPalette,
+        Command::FindFile,
+        Command::NextBuffer,
+        Command::PrevBuffer,
+        Command::GotoBuffer(1),
+        Command::GotoBuffer(2),
+        Command::GotoBuffer(3),
+        Command::GotoBuffer(4),
+        Command::GotoBuffer(5),
+        Command::GotoBuffer(6),
+        Command::GotoBuffer(7),
+        Command::GotoBuffer(8),
+        Command::GotoBuffer(9),
+        Command::SwitchBuffer,
+        Command::ToggleExplorer,
+        Command::FocusExplorer,
+        Command::SplitRight,
+        Command::SplitDown,
+        Command::ClosePane,
+        Command::FocusPaneLeft,
+        Command::FocusPaneRight,
+        Command::FocusPaneUp,
+        Command::FocusPaneDown,
+        Command::GrowPaneWidth,
+        Command::ShrinkPaneWidth,
+        Command::GrowPaneHeight,
+        Command::ShrinkPaneHeight,
+        Command::EqualizePanes,
+        Command::Quit,
+        Command::ForceQuit,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Save => "Save",
+            Command::SaveAs => "Save As",
+            Command::ReloadFile => "Reload File",
+            Command::ToggleReadOnly => "Toggle Read Only",
+            Command::Undo => "Undo",
+            Command::Redo => "Redo",
+            Command::ExpandSelection => "Expand Selection",
+            Command::ShrinkSelection => "Shrink Selection",
+            Command::GotoParentNode => "Go to Parent Node",
+            Command::GotoNextSibling => "Go to Next Sibling Node",
+            Command::GotoPrevSibling => "Go to Previous Sibling Node",
+            Command::Fold => "Fold",
+            Command::Unfold => "Unfold",
+            Command::FoldToLevel => "Fold to Level",
+            Command::UnfoldAll => "Unfold All",
+            Command::Hover => "Show Hover Information",
+            Command::GotoDefinition => "Go to Definition",
+            Command::FindReferences => "Find References",
+            Command::RenameSymbol => "Rename Symbol",
+            Command::FormatDocument => "Format Document",
+            Command::ShowCompletions => "Show Completions",
+            Command::CompleteWord => "Complete Word",
+            Command::NextDiagnostic => "Next Diagnostic",
+            Command::PrevDiagnostic => "Previous Diagnostic",
+            Command::ListDiagnostics => "List Diagnostics",
+            Command::CloseBuffer => "Close Buffer",
+            Command::OpenCommandPalette => "Command Palette",
+            Command::FindFile => "Find File",
+            Command::NextBuffer => "Next Buffer",
+            Command::PrevBuffer => "Previous Buffer",
+            Command::GotoBuffer(1) => "Go to Buffer 1",
+            Command::GotoBuffer(2) => "Go to Buffer 2",
+            Command::GotoBuffer(3) => "Go to Buffer 3",
+            Command::GotoBuffer(4) => "Go to Buffer 4",
+            Command::GotoBuffer(5) => "Go to Buffer 5",
+            Command::GotoBuffer(6) => "Go to Buffer 6",
+            Command::GotoBuffer(7) => "Go to Buffer 7",
+            Command::GotoBuffer(8) => "Go to Buffer 8",
+            Command::GotoBuffer(_) => "Go to Buffer 9",
+            Command::SwitchBuffer => "Switch Buffer",
+            Command::ToggleExplorer => "Toggle File Explorer",
+            Command::FocusExplorer => "Focus File Explorer",
+            Command::SplitRight => "Split Pane Right",
+            Command::SplitDown => "Split Pane Down",
+            Command::ClosePane => "Close Pane",
+            Command::FocusPaneLeft => "Focus Pane Left",
+            Command::FocusPaneRight => "Focus Pane Right",
+            Command::FocusPaneUp => "Focus Pane Up",
+            Command::FocusPaneDown => "Focus Pane Down",
+            Command::GrowPaneWidth => "Grow Pane Width",
+            Command::ShrinkPaneWidth => "Shrink Pane Width",
+            Command::GrowPaneHeight => "Grow Pane Height",
+            Command::ShrinkPaneHeight => "Shrink Pane Height",
+            Command::EqualizePanes => "Equalize Panes",
+            Command::Quit => "Quit",
+            Command::ForceQuit => "Force Quit",
+        }
+    }
+
+    pub fn key_binding(&self) -> Option<KeyBinding> {
+        let ctrl = KeyModifiers::CONTROL;
+        let ctrl_shift = KeyModifiers::CONTROL | KeyModifiers::SHIFT;
+        let alt = KeyModifiers::ALT;
+        let alt_shift = KeyModifiers::ALT | KeyModifiers::SHIFT;
+
+        let binding = match self {
+            Command::Save => KeyBinding::new(KeyCode::Char('s'), ctrl),
+            Command::SaveAs => KeyBinding::new(KeyCode::Char('S'), ctrl_shift),
+            // Only run from the command palette
+            Command::ReloadFile
+            | Command::ToggleReadOnly
+            | Command::FoldToLevel
+            | Command::ShowCompletions => return None,
+            Command::Undo => KeyBinding::new(KeyCode::Char('z'), ctrl),
+            Command::Redo => KeyBinding::new(KeyCode::Char('y'), ctrl),
+            Command::ExpandSelection => KeyBinding::new(KeyCode::Up, ctrl),
+            Command::ShrinkSelection => KeyBinding::new(KeyCode::Down, ctrl),
+            Command::GotoParentNode => KeyBinding::new(KeyCode::Up, ctrl_shift),
+            Command::GotoNextSibling => KeyBinding::new(KeyCode::Right, ctrl_shift),
+            Command::GotoPrevSibling => KeyBinding::new(KeyCode::Left, ctrl_shift),
+            Command::Fold => KeyBinding::new(KeyCode::Char('f'), alt),
+            Command::Unfold => KeyBinding::new(KeyCode::Char('u'), alt),
+            Command::UnfoldAll => KeyBinding::new(KeyCode::Char('U'), alt_shift),
+            Command::Hover => KeyBinding::new(KeyCode::Char('k'), ctrl),
+            Command::GotoDefinition => KeyBinding::new(KeyCode::F(12), KeyModifiers::NONE),
+            Command::FindReferences => KeyBinding::new(KeyCode::F(12), KeyModifiers::SHIFT),
+            Command::RenameSymbol => KeyBinding::new(KeyCode::F(2), KeyModifiers::NONE),
+            Command::FormatDocument => KeyBinding::new(KeyCode::Char('F'), alt_shift),
+            Command::CompleteWord => KeyBinding::new(KeyCode::Char(' '), ctrl),
+            Command::NextDiagnostic => KeyBinding::new(KeyCode::F(8), KeyModifiers::NONE),
+            Command::PrevDiagnostic => KeyBinding::new(KeyCode::F(8), KeyModifiers::SHIFT),
+            Command::ListDiagnostics => KeyBinding::new(KeyCode::Char('d'), alt),
+            Command::CloseBuffer => KeyBinding::new(KeyCode::Char('w'), ctrl),
+            Command::OpenCommandPalette => KeyBinding::new(KeyCode::Char('P'), ctrl_shift),
+            Command::FindFile => KeyBinding::new(KeyCode::Char('p'), ctrl),
+            Command::NextBuffer => KeyBinding::new(KeyCode::Tab, ctrl),
+            Command::PrevBuffer => KeyBinding::new(KeyCode::BackTab, ctrl_shift),
+            Command::GotoBuffer(n) => KeyBinding::new(KeyCode::Char((b'0' + n) as char), alt),
+            Command::SwitchBuffer => KeyBinding::new(KeyCode::Char('b'), alt),
+            Command::ToggleExplorer => KeyBinding::new(KeyCode::Char('b'), ctrl),
+            Command::FocusExplorer => KeyBinding::new(KeyCode::Char('e'), ctrl),
+            Command::SplitRight => KeyBinding::new(KeyCode::Char('\\'), alt),
+            Command::SplitDown => KeyBinding::new(KeyCode::Char('-'), alt),
+            Command::ClosePane => KeyBinding::new(KeyCode::Char('w'), alt),
+            Command::FocusPaneLeft => KeyBinding::new(KeyCode::Left, alt),
+            Command::FocusPaneRight => KeyBinding::new(KeyCode::Right, alt),
+            Command::FocusPaneUp => KeyBinding::new(KeyCode::Up, alt),
+            Command::FocusPaneDown => KeyBinding::new(KeyCode::Down, alt),
+            Command::GrowPaneWidth => KeyBinding::new(KeyCode::Right, alt_shift),
+            Command::ShrinkPaneWidth => KeyBinding::new(KeyCode::Left, alt_shift),
+            Command::GrowPaneHeight => KeyBinding::new(KeyCode::Down, alt_shift),
+            Command::ShrinkPaneHeight => KeyBinding::new(KeyCode::Up, alt_shift),
+            Command::EqualizePanes => KeyBinding::new(KeyCode::Char('='), alt),
+            Command::Quit => KeyBinding::new(KeyCode::Char('q'), ctrl),
+            Command::ForceQuit => KeyBinding::new(KeyCode::Char('q'), ctrl | alt),
+        };
+
+        Some(binding)
+    }
+
+    /// Returns true if the command can run while an overlay has focus
+    pub fn is_global(&self) -> bool {
+        matches!(self, Command::Quit | Command::ForceQuit)
+    }
+
+    pub fn from_name(name: &str) -> Option<Command> {
+        Self::ALL.iter().find(|c| c.name() == name).copied()
+    }
+
+    pub fn from_key(code: KeyCode, modifiers: KeyModifiers) -> Option<Command> {
+        Self::ALL
+            .iter()
+            .find(|c| match c.key_binding() {
+                Some(binding) => binding.matches(code, modifiers),
+                None => false,
+            })
+            .copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_parse_back_to_their_command() {
+        for command in Command::ALL {
+            assert_eq!(Command::from_name(command.name()), Some(*command));
+        }
+        assert_eq!(Command::from_name("save"), None);
+        assert_eq!(Command::from_name(""), None);
+    }
+
+    #[test]
+    fn key_bindings_dont_collide() {
+        let bindings: Vec<(Command, KeyBinding)> = Command::ALL
+            .iter()
+            .filter_map(|command| Some((*command, command.key_binding()?)))
+            .collect();
+
+        for (command, binding) in &bindings {
+            assert_eq!(
+                Command::from_key(binding.code, binding.modifiers),
+                Some(*command),
+                "{binding} runs another command"
+            );
+
+            // Letters with Shift are matched in upper case, a lower case one could never be typed
+            if let KeyCode::Char(c) = binding.code {
+                if binding.modifiers.contains(KeyModifiers::SHIFT) {
+                    assert!(!c.is_ascii_lowercase(), "{binding} can't be typed");
+                    let lower = KeyCode::Char(c.to_ascii_lowercase());
+                    assert_eq!(Command::from_key(lower, binding.modifiers), Some(*command));
+                }
+            }
+        }
+
+        for (i, (command, binding)) in bindings.iter().enumerate() {
+            for (other, other_binding) in &bindings[i + 1..] {
+                // Also keeps the palette from showing the same keys for two commands
+                assert_ne!(
+                    binding.to_string(),
+                    other_binding.to_string(),
+                    "{command:?} and {other:?} are both bound to {binding}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn unbound_keys_run_nothing() {
+        assert_eq!(
+            Command::from_key(KeyCode::Char('s'), KeyModifiers::NONE),
+            None
+        );
+        assert_eq!(
+            Command::from_key(KeyCode::Char('s'), KeyModifiers::ALT),
+            None
+        );
+        assert_eq!(Command::from_key(KeyCode::F(1), KeyModifiers::NONE), None);
+    }
+}
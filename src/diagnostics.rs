@@ -0,0 +1,103 @@
+#![allow(dead_code)]
+use crate::{document::Change, history::EditKind};
+
+/// How bad a problem is, the most severe comes first
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Severity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Information => "info",
+            Severity::Hint => "hint",
+        }
+    }
+}
+
+/// A problem in a document, from `start` up to `end` (exclusive), reported by `source`.
+///
+/// The range is kept as positions in the text, so it moves along with edits like the cursor.
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub start: usize,
+    pub end: usize,
+    pub severity: Severity,
+    pub message: String,
+    /// What reported the problem, like a language server or a linter. Each source replaces its
+    /// own diagnostics only.
+    pub source: String,
+}
+
+impl Diagnostic {
+    /// First line of the message, for places that only have one
+    pub fn summary(&self) -> &str {
+        self.message.lines().next().unwrap_or_default()
+    }
+}
+
+/// Moves the diagnostics along with an edit of the text
+pub fn move_diagnostics(diagnostics: &mut [Diagnostic], change: &Change) {
+    for diagnostic in diagnostics.iter_mut() {
+        match change.kind {
+            EditKind::Insert => {
+                if change.pos < diagnostic.start {
+                    diagnostic.start += 1;
+                }
+                if change.pos < diagnostic.end {
+                    diagnostic.end += 1;
+                }
+            }
+            EditKind::Delete => {
+                if change.pos < diagnostic.start {
+                    diagnostic.start -= 1;
+                }
+                if change.pos < diagnostic.end {
+                    diagnostic.end -= 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns where a diagnostic over 2..5 is after `kind` at `pos`
+    fn moved(kind: EditKind, pos: usize) -> (usize, usize) {
+        let mut diagnostics = [Diagnostic {
+            start: 2,
+            end: 5,
+            severity: Severity::Error,
+            message: String::new(),
+            source: String::new(),
+        }];
+        move_diagnostics(&mut diagnostics, &Change { kind, pos, ch: 'x' });
+        (diagnostics[0].start, diagnostics[0].end)
+    }
+
+    #[test]
+    fn inserting_moves_diagnostics() {
+        // Before, at the start, inside and after
+        assert_eq!(moved(EditKind::Insert, 0), (3, 6));
+        assert_eq!(moved(EditKind::Insert, 2), (2, 6));
+        assert_eq!(moved(EditKind::Insert, 3), (2, 6));
+        assert_eq!(moved(EditKind::Insert, 5), (2, 5));
+    }
+
+    #[test]
+    fn deleting_moves_diagnostics() {
+        // Before, the first character, inside and after
+        assert_eq!(moved(EditKind::Delete, 0), (1, 4));
+        assert_eq!(moved(EditKind::Delete, 2), (2, 4));
+        assert_eq!(moved(EditKind::Delete, 4), (2, 4));
+        assert_eq!(moved(EditKind::Delete, 5), (2, 5));
+    }
+}
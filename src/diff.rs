@@ -0,0 +1,179 @@
+#![allow(dead_code)]
+use std::cmp::min;
+
+/// Unchanged lines shown around every change
+const CONTEXT: usize = 3;
+/// Above this many line pairs the changed middle part is shown as removed and re-added, instead of
+/// spending a lot of time and memory on the smallest diff
+const MAX_COMPARISONS: usize = 4_000_000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Returns a unified diff of two texts, line by line
+pub fn unified(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let ops = diff_lines(&old_lines, &new_lines);
+
+    let mut out = format!("--- {old_name}\n+++ {new_name}\n");
+
+    let changed: Vec<usize> = (0..ops.len())
+        .filter(|i| ops[*i].0 != Op::Equal)
+        .collect();
+    if changed.is_empty() {
+        out.push_str("No differences\n");
+        return out;
+    }
+
+    // Changes closer than twice the context share a hunk
+    let mut hunks: Vec<(usize, usize)> = vec![];
+    for i in changed {
+        let start = i.saturating_sub(CONTEXT);
+        let end = min(i + CONTEXT + 1, ops.len());
+        match hunks.last_mut() {
+            Some(hunk) if start <= hunk.1 => hunk.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    for (start, end) in hunks {
+        let old_before = ops[..start].iter().filter(|op| op.0 != Op::Insert).count();
+        let new_before = ops[..start].iter().filter(|op| op.0 != Op::Delete).count();
+        let old_count = ops[start..end].iter().filter(|op| op.0 != Op::Insert).count();
+        let new_count = ops[start..end].iter().filter(|op| op.0 != Op::Delete).count();
+
+        // An empty range starts at the line before it
+        let old_start = if old_count == 0 { old_before } else { old_before + 1 };
+        let new_start = if new_count == 0 { new_before } else { new_before + 1 };
+        out.push_str(&format!(
+            "@@ -{old_start},{old_count} +{new_start},{new_count} @@\n"
+        ));
+
+        for (op, line) in &ops[start..end] {
+            let prefix = match op {
+                Op::Equal => ' ',
+                Op::Delete => '-',
+                Op::Insert => '+',
+            };
+            out.push(prefix);
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+
+    out
+}
+
+/// Finds the smallest set of lines to delete from `old` and insert from `new`, with the longest
+/// common subsequence
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(Op, &'a str)> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    let mut ops: Vec<(Op, &str)> = old[..prefix].iter().map(|l| (Op::Equal, *l)).collect();
+
+    let (n, m) = (old_middle.len(), new_middle.len());
+    if n.saturating_mul(m) > MAX_COMPARISONS {
+        ops.extend(old_middle.iter().map(|l| (Op::Delete, *l)));
+        ops.extend(new_middle.iter().map(|l| (Op::Insert, *l)));
+    } else {
+        // lcs[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+        let mut lcs = vec![0u32; (n + 1) * (m + 1)];
+        let at = |i: usize, j: usize| i * (m + 1) + j;
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lcs[at(i, j)] = if old_middle[i] == new_middle[j] {
+                    lcs[at(i + 1, j + 1)] + 1
+                } else {
+                    std::cmp::max(lcs[at(i + 1, j)], lcs[at(i, j + 1)])
+                };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && old_middle[i] == new_middle[j] {
+                ops.push((Op::Equal, old_middle[i]));
+                i += 1;
+                j += 1;
+            } else if j == m || (i < n && lcs[at(i + 1, j)] >= lcs[at(i, j + 1)]) {
+                ops.push((Op::Delete, old_middle[i]));
+                i += 1;
+            } else {
+                ops.push((Op::Insert, new_middle[j]));
+                j += 1;
+            }
+        }
+    }
+
+    ops.extend(old[old.len() - suffix..].iter().map(|l| (Op::Equal, *l)));
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shows_changes_with_context() {
+        let old = "a\nb\nc\nd\ne\nf\ng\n";
+        let new = "a\nb\nc\nD\ne\nf\ng\nh\n";
+        assert_eq!(
+            unified(old, new, "file", "file (unsaved changes)"),
+            "--- file\n\
+             +++ file (unsaved changes)\n\
+             @@ -1,7 +1,8 @@\n a\n b\n c\n-d\n+D\n e\n f\n g\n+h\n"
+        );
+    }
+
+    #[test]
+    fn keeps_distant_changes_in_separate_hunks() {
+        let old: String = (1..=20).map(|i| format!("{i}\n")).collect();
+        let new: String = (1..=20)
+            .filter(|i| *i != 19)
+            .map(|i| match i {
+                2 => "two\n".to_string(),
+                i => format!("{i}\n"),
+            })
+            .collect();
+        assert_eq!(
+            unified(&old, &new, "old", "new"),
+            "--- old\n+++ new\n\
+             @@ -1,5 +1,5 @@\n 1\n-2\n+two\n 3\n 4\n 5\n\
+             @@ -16,5 +16,4 @@\n 16\n 17\n 18\n-19\n 20\n"
+        );
+    }
+
+    #[test]
+    fn empty_ranges_start_at_the_line_before() {
+        assert_eq!(
+            unified("", "a\n", "old", "new"),
+            "--- old\n+++ new\n@@ -0,0 +1,1 @@\n+a\n"
+        );
+        assert_eq!(
+            unified("a\n", "", "old", "new"),
+            "--- old\n+++ new\n@@ -1,1 +0,0 @@\n-a\n"
+        );
+    }
+
+    #[test]
+    fn says_when_nothing_changed() {
+        assert_eq!(
+            unified("a\n", "a", "old", "new"),
+            "--- old\n+++ new\nNo differences\n"
+        );
+    }
+}
@@ -0,0 +1,640 @@
+#![allow(dead_code)]
+use std::{
+    cell::{Cell, RefCell},
+    path::PathBuf,
+    rc::{Rc, Weak},
+    time::SystemTime,
+};
+
+use crate::{
+    diagnostics::{self, Diagnostic},
+    highlight::{Highlighter, Span},
+    history::{Edit, EditKind, History},
+    fold::{self, FoldRegion},
+    language::Language,
+    syntax::{IncrementalParser, SyntaxTree},
+    word_completion::WordIndex,
+};
+
+pub struct Line {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Line {
+    pub fn len(&self) -> usize {
+        self.end - self.start + 1
+    }
+}
+
+/// A character inserted or deleted at `pos`, through any view of the document
+#[derive(Clone, Copy)]
+pub struct Change {
+    pub kind: EditKind,
+    pub pos: usize,
+    pub ch: char,
+}
+
+/// How far a reader of the change log got, as a number of changes since the document was created.
+///
+/// The changes every reader got past are dropped from the log, a reader that is dropped no longer
+/// holds them back.
+pub struct ChangeReader(Rc<Cell<usize>>);
+
+impl ChangeReader {
+    pub fn get(&self) -> usize {
+        self.0.get()
+    }
+
+    pub fn set(&self, count: usize) {
+        self.0.set(count);
+    }
+}
+
+/// Changes the readers got past are only dropped once there are this many, so the log isn't
+/// shifted on every edit
+const TRIM_CHANGES: usize = 1024;
+
+/// A document shared by every buffer showing it
+pub type SharedDocument = Rc<RefCell<Document>>;
+
+/// Text of a file, shared by all the buffers (views) showing it.
+///
+/// Views keep their own cursor and scroll position. Every edit is appended to a change log, so a
+/// view can move its cursor when the text was edited through another view. The log only keeps the
+/// changes some `ChangeReader` has not read yet.
+pub struct Document {
+    data: Vec<char>,
+    lines: Vec<Line>,
+    history: History,
+    pub file_path: Option<PathBuf>,
+    /// Modification time of the file when it was last loaded or saved, to notice when another
+    /// program changes it
+    pub disk_modified: Option<SystemTime>,
+    /// Revision of the text that was last loaded from or saved to `file_path`
+    saved_revision: usize,
+    changes: Vec<Change>,
+    /// Number of changes dropped from the front of `changes`
+    trimmed_changes: usize,
+    readers: RefCell<Vec<Weak<Cell<usize>>>>,
+    /// Number of times the whole text was replaced without logging changes, see `replace_text`
+    replacements: usize,
+    /// `None` for text that is not in a known language
+    highlighter: Option<Highlighter>,
+    /// Parser of languages with a bundled grammar, they are highlighted from its tree instead of
+    /// by `highlighter`
+    parser: Option<IncrementalParser>,
+    /// Set when the text was edited since `parser` last parsed it
+    parse_pending: bool,
+    /// Structure of the text, parsed again after an edit when it's next asked for
+    syntax_tree: Option<Rc<SyntaxTree>>,
+    /// Regions that can be folded, found again after an edit when they are next asked for
+    fold_regions: Option<Rc<Vec<FoldRegion>>>,
+    /// Problems found in the text, sorted by their start
+    diagnostics: Vec<Diagnostic>,
+    /// Words of the text for completion, with the change count they were found at
+    word_index: Option<(usize, Rc<WordIndex>)>,
+}
+
+impl Document {
+    pub fn new() -> Self {
+        Self::from(String::new())
+    }
+
+    pub fn from(data: String) -> Self {
+        let data = data.chars().filter(|c| *c != '\r').collect();
+
+        let mut document = Self {
+            data,
+            lines: vec![],
+            history: History::new(),
+            file_path: None,
+            disk_modified: None,
+            saved_revision: 0,
+            changes: vec![],
+            trimmed_changes: 0,
+            readers: RefCell::new(vec![]),
+            replacements: 0,
+            highlighter: None,
+            parser: None,
+            parse_pending: false,
+            syntax_tree: None,
+            fold_regions: None,
+            diagnostics: vec![],
+            word_index: None,
+        };
+        document.recalculate_lines();
+        document
+    }
+
+    pub fn shared(self) -> SharedDocument {
+        Rc::new(RefCell::new(self))
+    }
+
+    pub fn chars(&self) -> &[char] {
+        &self.data
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    fn recalculate_lines(&mut self) {
+        let mut previous_begining = 0;
+        self.lines.clear();
+
+        for (i, ch) in self.data.iter().enumerate() {
+            if *ch == '\n' {
+                self.lines.push(Line {
+                    start: previous_begining,
+                    end: i,
+                });
+                previous_begining = i + 1;
+            }
+        }
+
+        assert!(self.data.len() >= previous_begining);
+        let end = if previous_begining == self.data.len() {
+            previous_begining
+        } else {
+            self.data.len() - 1
+        };
+
+        self.lines.push(Line {
+            start: previous_begining,
+            end,
+        });
+    }
+
+    /// Returns the line containing the character at `pos`
+    pub fn line_of(&self, pos: usize) -> usize {
+        assert!(pos <= self.data.len());
+        // Lines are sorted, and the last one ends with the text
+        let line = self.lines.partition_point(|line| line.end < pos);
+        std::cmp::min(line, self.lines.len() - 1)
+    }
+
+    pub fn insert(&mut self, pos: usize, ch: char) {
+        self.data.insert(pos, ch);
+        self.history.record(EditKind::Insert, pos, ch);
+        self.log_change(EditKind::Insert, pos, ch);
+        self.recalculate_lines();
+        self.invalidate_highlights(pos);
+    }
+
+    pub fn remove(&mut self, pos: usize) -> char {
+        let ch = self.data.remove(pos);
+        self.history.record(EditKind::Delete, pos, ch);
+        self.log_change(EditKind::Delete, pos, ch);
+        self.recalculate_lines();
+        self.invalidate_highlights(pos);
+        ch
+    }
+
+    /// Replaces the characters from `start` up to `end` with `text`, as one undo group
+    pub fn replace(&mut self, start: usize, end: usize, text: &str) {
+        self.replace_ranges(vec![(start, end, text.to_string())]);
+    }
+
+    /// Replaces the characters from `start` up to `end` of every edit with its text, as one undo
+    /// group. The ranges are in the text before the edits, and don't overlap.
+    pub fn replace_ranges(&mut self, mut edits: Vec<(usize, usize, String)>) {
+        // From the end, so the ranges left to replace don't move
+        edits.sort_by_key(|(start, _, _)| std::cmp::Reverse(*start));
+        let Some(first) = edits.last().map(|(start, _, _)| *start) else {
+            return;
+        };
+
+        self.begin_edit();
+        for (start, end, text) in edits {
+            self.splice(start, end, &text);
+        }
+        self.end_edit();
+        self.recalculate_lines();
+        self.invalidate_highlights(first);
+    }
+
+    /// Replaces a range of the text at once, and records it character by character. The lines
+    /// are left for the caller to find again.
+    fn splice(&mut self, start: usize, end: usize, text: &str) {
+        let end = std::cmp::min(end, self.data.len());
+        let start = std::cmp::min(start, end);
+        let text: Vec<char> = text.chars().filter(|c| *c != '\r').collect();
+        let removed: Vec<char> = self.data.splice(start..end, text.iter().copied()).collect();
+
+        // Like characters deleted with Delete, then typed
+        for ch in removed {
+            self.history.record(EditKind::Delete, start, ch);
+            self.log_change(EditKind::Delete, start, ch);
+        }
+        for (i, ch) in text.into_iter().enumerate() {
+            self.history.record(EditKind::Insert, start + i, ch);
+            self.log_change(EditKind::Insert, start + i, ch);
+        }
+    }
+
+    /// Makes the next edit start a new undo group
+    pub fn break_group(&mut self) {
+        self.history.break_group();
+    }
+
+    /// Makes the edits until the matching `end_edit` be undone in one go
+    pub fn begin_edit(&mut self) {
+        self.history.begin_edit();
+    }
+
+    pub fn end_edit(&mut self) {
+        self.history.end_edit();
+    }
+
+    /// Reverts the last group of edits, and returns them most recent first
+    pub fn undo(&mut self) -> Vec<Edit> {
+        let edits = self.history.undo();
+
+        for edit in edits.iter() {
+            match edit.kind {
+                EditKind::Insert => self.revert_insert(edit.pos),
+                EditKind::Delete => self.revert_delete(edit.pos, edit.ch),
+            }
+        }
+
+        self.recalculate_lines();
+        if let Some(pos) = edits.iter().map(|edit| edit.pos).min() {
+            self.invalidate_highlights(pos);
+        }
+        edits
+    }
+
+    /// Re-applies the last undone group of edits, and returns them oldest first
+    pub fn redo(&mut self) -> Vec<Edit> {
+        let edits = self.history.redo();
+
+        for edit in edits.iter() {
+            match edit.kind {
+                EditKind::Insert => self.revert_delete(edit.pos, edit.ch),
+                EditKind::Delete => self.revert_insert(edit.pos),
+            }
+        }
+
+        self.recalculate_lines();
+        if let Some(pos) = edits.iter().map(|edit| edit.pos).min() {
+            self.invalidate_highlights(pos);
+        }
+        edits
+    }
+
+    // Undo and redo edit the text without recording history, but other views still follow them
+
+    fn revert_insert(&mut self, pos: usize) {
+        let ch = self.data.remove(pos);
+        self.log_change(EditKind::Delete, pos, ch);
+    }
+
+    fn revert_delete(&mut self, pos: usize, ch: char) {
+        self.data.insert(pos, ch);
+        self.log_change(EditKind::Insert, pos, ch);
+    }
+
+    /// Appends an edit to the change log, and moves the diagnostics along with it
+    fn log_change(&mut self, kind: EditKind, pos: usize, ch: char) {
+        let change = Change { kind, pos, ch };
+        diagnostics::move_diagnostics(&mut self.diagnostics, &change);
+        self.changes.push(change);
+        self.trim_changes();
+    }
+
+    /// Drops the changes every reader got past, once they make up most of the log
+    fn trim_changes(&mut self) {
+        let mut readers = self.readers.borrow_mut();
+        readers.retain(|reader| reader.strong_count() > 0);
+        let read = readers
+            .iter()
+            .filter_map(|reader| reader.upgrade())
+            .map(|reader| reader.get())
+            .min()
+            .unwrap_or(self.trimmed_changes + self.changes.len());
+
+        let unused = read.saturating_sub(self.trimmed_changes);
+        if unused >= TRIM_CHANGES && unused * 2 >= self.changes.len() {
+            self.changes.drain(..unused);
+            self.trimmed_changes += unused;
+        }
+    }
+
+    /// Revision of the current text, see `History`
+    pub fn revision(&self) -> usize {
+        self.history.revision()
+    }
+
+    /// Returns true if the text has edits that were not saved yet
+    pub fn is_modified(&self) -> bool {
+        self.history.revision() != self.saved_revision
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.saved_revision = self.history.revision();
+    }
+
+    /// Replaces the whole text, e.g. with a newer version of the file. The undo history is cleared,
+    /// and the new text counts as saved.
+    ///
+    /// NOTE: No change is logged, views only clamp their cursor to the new text.
+    pub fn replace_text(&mut self, text: String) {
+        self.data = text.chars().filter(|c| *c != '\r').collect();
+        self.replacements += 1;
+        // They are reported again for the new text
+        self.diagnostics.clear();
+        self.word_index = None;
+        self.history = History::new();
+        self.saved_revision = self.history.revision();
+        self.recalculate_lines();
+        self.invalidate_highlights(0);
+    }
+
+    /// Makes the current text count as unsaved, for text that doesn't come from the file
+    pub fn mark_modified(&mut self) {
+        // No revision ever gets this number
+        self.saved_revision = usize::MAX;
+    }
+
+    /// Number of edits made since the document was created
+    pub fn change_count(&self) -> usize {
+        self.trimmed_changes + self.changes.len()
+    }
+
+    /// Returns a reader of the change log that has read all the changes made so far
+    pub fn reader(&self) -> ChangeReader {
+        self.reader_at(self.change_count())
+    }
+
+    /// Returns a reader of the change log that has read the first `count` changes, like another
+    /// reader that is at `count`
+    pub fn reader_at(&self, count: usize) -> ChangeReader {
+        let reader = Rc::new(Cell::new(count));
+        self.readers.borrow_mut().push(Rc::downgrade(&reader));
+        ChangeReader(reader)
+    }
+
+    /// Number of times the whole text was replaced, views of the text that follow the change log
+    /// have to start over when it changes
+    pub fn replacements(&self) -> usize {
+        self.replacements
+    }
+
+    /// Returns the edits made after the first `count` ones.
+    ///
+    /// NOTE: Only the changes some reader has not read yet are kept, `count` must come from one.
+    pub fn changes_since(&self, count: usize) -> &[Change] {
+        let count = count.saturating_sub(self.trimmed_changes);
+        self.changes.get(count..).unwrap_or(&[])
+    }
+
+    pub fn language(&self) -> Option<&Rc<Language>> {
+        self.highlighter.as_ref().map(|h| h.language())
+    }
+
+    /// Highlights the text as `language`, or not at all
+    pub fn set_language(&mut self, language: Option<Rc<Language>>) {
+        self.parser = language.as_ref().and_then(|language| {
+            IncrementalParser::new(language.parser.as_ref()?, &language.brackets)
+        });
+        self.parse_pending = true;
+        self.highlighter = language.map(Highlighter::new);
+        self.syntax_tree = None;
+        self.fold_regions = None;
+    }
+
+    /// Returns the highlighted parts of every line in `rows`, which go down. With a parser the
+    /// rows that follow each other are highlighted at once, the ones between are folded.
+    pub fn highlights(&mut self, rows: &[usize]) -> Vec<Vec<Span>> {
+        self.parse();
+        if let Some(parser) = &self.parser {
+            return rows
+                .chunk_by(|row, next| row + 1 == *next)
+                .flat_map(|run| {
+                    parser.spans(&self.data, &self.lines, run[0]..run[run.len() - 1] + 1)
+                })
+                .collect();
+        }
+
+        match &mut self.highlighter {
+            Some(highlighter) => rows
+                .iter()
+                .map(|row| highlighter.spans(&self.data, &self.lines, *row).to_vec())
+                .collect(),
+            None => vec![vec![]; rows.len()],
+        }
+    }
+
+    /// Parses the edits made since the parser of the language last parsed the text
+    fn parse(&mut self) {
+        if !self.parse_pending {
+            return;
+        }
+        // The parser reads the changes of the document
+        if let Some(mut parser) = self.parser.take() {
+            parser.parse(self);
+            self.parser = Some(parser);
+        }
+        self.parse_pending = false;
+    }
+
+    /// Returns the syntax tree of the text, `None` if it's not in a known language.
+    ///
+    /// Languages with a bundled parser get the tree of their grammar, which is parsed again
+    /// around the edits. For the others the tree is built from the highlighted lines, so after an
+    /// edit only the lines from the edited one down are highlighted again to parse it.
+    pub fn syntax_tree(&mut self) -> Option<Rc<SyntaxTree>> {
+        if let Some(tree) = &self.syntax_tree {
+            return Some(tree.clone());
+        }
+
+        self.parse();
+        let tree = match &self.parser {
+            Some(parser) => parser.syntax_tree(&self.data)?,
+            None => {
+                let highlighter = self.highlighter.as_mut()?;
+                let language = highlighter.language().clone();
+                let (chars, lines) = (&self.data, &self.lines);
+                SyntaxTree::parse(&language, chars, lines, |row| {
+                    highlighter.spans(chars, lines, row).to_vec()
+                })
+            }
+        };
+
+        let tree = Rc::new(tree);
+        self.syntax_tree = Some(tree.clone());
+        Some(tree)
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Replaces the diagnostics reported by `source`, keeping the ones of other sources. They may
+    /// have been found for an older text, so the ranges are cut down to the text.
+    pub fn set_diagnostics(&mut self, source: &str, diagnostics: Vec<Diagnostic>) {
+        let len = self.data.len();
+        self.diagnostics.retain(|d| d.source != source);
+        for mut diagnostic in diagnostics {
+            diagnostic.start = std::cmp::min(diagnostic.start, len);
+            diagnostic.end = diagnostic.end.clamp(diagnostic.start, len);
+            self.diagnostics.push(diagnostic);
+        }
+        self.diagnostics.sort_by_key(|d| (d.start, d.severity));
+    }
+
+    /// Returns the words of the text, found again when it changed since they were last asked for
+    pub fn word_index(&mut self) -> Rc<WordIndex> {
+        let change_count = self.change_count();
+        if let Some((count, index)) = &self.word_index {
+            if *count == change_count {
+                return index.clone();
+            }
+        }
+
+        let index = Rc::new(WordIndex::new(&self.data));
+        self.word_index = Some((change_count, index.clone()));
+        index
+    }
+
+    /// Returns the regions of the text that can be folded, see `fold::regions`
+    pub fn fold_regions(&mut self) -> Rc<Vec<FoldRegion>> {
+        if let Some(regions) = &self.fold_regions {
+            return regions.clone();
+        }
+
+        let tree = self.syntax_tree();
+        let regions = Rc::new(fold::regions(&self.data, &self.lines, tree.as_deref()));
+        self.fold_regions = Some(regions.clone());
+        regions
+    }
+
+    /// Makes the lines from the one containing `pos` down be highlighted again, and the syntax
+    /// tree and fold regions be found again
+    fn invalidate_highlights(&mut self, pos: usize) {
+        self.fold_regions = None;
+        if self.highlighter.is_none() {
+            return;
+        }
+        self.syntax_tree = None;
+        self.parse_pending = true;
+
+        let line = self.line_of(std::cmp::min(pos, self.data.len()));
+        if let Some(highlighter) = &mut self.highlighter {
+            highlighter.invalidate_from(line);
+        }
+    }
+
+    pub fn digits_in_line_num(&self) -> usize {
+        let mut max = self.lines.len();
+        let mut digits = 1; // start with a small gap
+        while max > 0 {
+            digits += 1;
+            max = max.saturating_div(10);
+        }
+        digits
+    }
+}
+
+impl std::fmt::Display for Document {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s: String = self.data.iter().collect();
+        f.write_str(&s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_text(document: &mut Document, text: &str) {
+        for ch in text.chars() {
+            document.insert(document.len(), ch);
+        }
+    }
+
+    #[test]
+    fn changes_read_by_every_reader_are_dropped() {
+        let mut document = Document::new();
+        let reader = document.reader();
+        type_text(&mut document, &"a".repeat(TRIM_CHANGES * 2));
+
+        reader.set(document.change_count());
+        document.insert(0, 'b');
+
+        assert_eq!(document.change_count(), TRIM_CHANGES * 2 + 1);
+        assert_eq!(document.changes.len(), 1);
+        assert_eq!(document.changes_since(reader.get())[0].ch, 'b');
+    }
+
+    #[test]
+    fn a_lagging_reader_keeps_the_changes() {
+        let mut document = Document::new();
+        let behind = document.reader();
+        let ahead = document.reader();
+        type_text(&mut document, &"a".repeat(TRIM_CHANGES * 2));
+        ahead.set(document.change_count());
+        document.insert(0, 'b');
+
+        let kept = document.changes_since(behind.get());
+        assert_eq!(kept.len(), TRIM_CHANGES * 2 + 1);
+
+        // A dropped reader no longer holds the log back
+        drop(behind);
+        document.insert(0, 'c');
+        assert_eq!(document.changes_since(ahead.get()).len(), 2);
+        assert_eq!(document.changes.len(), 2);
+    }
+
+    #[test]
+    fn replaces_ranges_in_one_undo_group() {
+        let mut document = Document::from("ab\ncd\nef".to_string());
+        let reader = document.reader();
+        let edits = vec![
+            (0, 1, "x\r\ny".to_string()),
+            (6, 8, String::new()),
+            (3, 3, "z".to_string()),
+        ];
+        document.replace_ranges(edits);
+        assert_eq!(document.to_string(), "x\nyb\nzcd\n");
+        assert_eq!(document.line_count(), 4);
+        let line = &document.lines()[2];
+        assert_eq!((line.start, line.end), (5, 8));
+
+        // Other views follow the edits character by character, from the last range
+        let changes: Vec<(EditKind, usize, char)> = document
+            .changes_since(reader.get())
+            .iter()
+            .map(|change| (change.kind, change.pos, change.ch))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                (EditKind::Delete, 6, 'e'),
+                (EditKind::Delete, 6, 'f'),
+                (EditKind::Insert, 3, 'z'),
+                (EditKind::Delete, 0, 'a'),
+                (EditKind::Insert, 0, 'x'),
+                (EditKind::Insert, 1, '\n'),
+                (EditKind::Insert, 2, 'y')
+            ]
+        );
+
+        document.undo();
+        assert_eq!(document.to_string(), "ab\ncd\nef");
+        assert_eq!(document.line_count(), 3);
+    }
+}
@@ -1,25 +1,49 @@
 #![allow(dead_code)]
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fs,
     io::{self, Write},
-    path::PathBuf,
-    sync::mpsc::{Receiver, Sender},
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, Sender},
+        Arc,
+    },
 };
 
-use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use serde_json::{json, Value};
 use uuid::Uuid;
 
 use crate::{
     buffer::{Buffer, BufferLogic},
+    command::Command,
+    diff,
+    explorer::Explorer,
+    file_finder, grammar,
+    language::{self, Language},
+    layout::{self, FocusDirection, LayoutNode, Rect, SplitDirection},
+    lsp::{self, Encoding, LanguageServers, Request, ServerEvent},
+    positions::{FilePosition, FilePositions},
+    save,
+    selector::{Selector, SelectorItem},
+    session::{self, Session, SessionBuffer, SessionLayout},
     settings::Settings,
-    terminal::Terminal,
+    swap::{SwapFile, SwapFiles},
+    terminal::{Tab, Terminal},
+    watcher::FileWatcher,
+    word_completion::{self, WordCompletion, WordIndex},
 };
 
 pub enum BufferEvent {
     Save {
         id: Uuid,
     },
+    /// Closes the buffer, asking to save it first if it has unsaved changes
+    RequestClose {
+        id: Uuid,
+    },
     SaveAs {
         id: Uuid,
     },
@@ -36,17 +60,90 @@ pub enum BufferEvent {
     },
 }
 
+/// File operations requested by an explorer buffer, the editor prompts the user before running them
+pub enum ExplorerEvent {
+    NewFile { dir: PathBuf },
+    NewFolder { dir: PathBuf },
+    Rename { path: PathBuf },
+    Delete { path: PathBuf },
+}
+
 // TODO:
 // FocusEvent (Think about how to implement this)
 // ResizeBuffers (Maybe make this an event)
 // OpenFileInSplit?
-// OpenFolder?
 //
 // Can maybe add ReloadSettings to support hot-reloading
 pub enum EditorEvent {
     Input(Event),
     Buffer(BufferEvent),
     OpenFile(PathBuf),
+    /// Opens a file with the cursor at a 1-indexed line and column
+    OpenFileAt {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+    },
+    /// Opens a file that can't be edited until read-only mode is toggled off, optionally with the
+    /// cursor at a 1-indexed (line, column)
+    OpenFileReadOnly {
+        path: PathBuf,
+        position: Option<(usize, usize)>,
+    },
+    OpenFolder(PathBuf),
+    Command(Command),
+    Explorer {
+        id: Uuid,
+        event: ExplorerEvent,
+    },
+    /// A batch of files found by the file finder's indexer for the selector overlay `selector_id`
+    FilesFound {
+        selector_id: Uuid,
+        files: Vec<PathBuf>,
+    },
+    /// Sent periodically by the swap timer, writes the unsaved changes of every document to its
+    /// swap file
+    WriteSwapFiles,
+    /// Asks what to do with the swap file found for the file of buffer `id`
+    RecoverSwap {
+        id: Uuid,
+    },
+    /// Sent by the file watcher when an open file was changed by another program
+    FileChanged(PathBuf),
+    /// Saves buffer `id` to `path`, asking before creating missing folders or overwriting a file
+    SaveTo {
+        id: Uuid,
+        path: PathBuf,
+    },
+    /// Shows a message on the last row until the next key press
+    ShowMessage(String),
+    /// Reopens the files and panes of the last session in the root folder
+    RestoreSession,
+    /// Asks for the level to fold buffer `id` to
+    FoldToLevel {
+        id: Uuid,
+    },
+    /// A message from the language server of `language`, started in the `generation` of the
+    /// servers, see `LanguageServers::set_root`
+    LanguageServer {
+        language: String,
+        generation: usize,
+        message: Value,
+    },
+    /// Sent when the language server of `language` exited
+    LanguageServerStopped {
+        language: String,
+        generation: usize,
+    },
+    /// Asks for the new name of the symbol at the cursor of buffer `id`
+    RenameSymbol {
+        id: Uuid,
+    },
+    /// Replaces the word of buffer `id` that starts at `word_start` with the chosen completion
+    Complete {
+        id: Uuid,
+        word_start: usize,
+    },
 }
 
 pub struct PausedEvent {
@@ -54,14 +151,40 @@ pub struct PausedEvent {
     event: EditorEvent,
 }
 
+/// Where an overlay is placed, it is recomputed when the terminal is resized
+#[derive(Clone, Copy)]
+enum OverlayPlacement {
+    /// A single line input box in the middle of the terminal, at most `width` columns wide
+    InputBox { width: u16 },
+    /// A selector list near the top of the terminal, at most `height` rows tall
+    Selector { height: u16 },
+}
+
+/// Height of a selector listing `items` items: borders + query line + items
+fn selector_height(items: usize) -> u16 {
+    u16::try_from(items).unwrap_or(u16::MAX).saturating_add(3)
+}
+
+/// The file finder overlay that is currently receiving files from the indexer
+struct FileFinder {
+    selector_id: Uuid,
+    cancel: Arc<AtomicBool>,
+    /// Files already listed, so recent files are not listed twice
+    listed: HashSet<PathBuf>,
+}
+
 // TODO: Might wanna change active_buffer, active_overlays to a stack of focus events
 // 		 this can make it easier to keep rewinding the focus
 pub struct Editor<W: Write> {
     settings: Settings,
 
     buffers: HashMap<Uuid, Buffer>,
-    // active_buffer: Option<Uuid>,
+    /// Editor buffers in the order they are shown in the tab bar
+    buffer_list: Vec<Uuid>,
+    /// Panes showing editor buffers, `None` until the first buffer is opened
+    layout: Option<LayoutNode>,
     overlays: HashMap<Uuid, Buffer>,
+    overlay_placements: HashMap<Uuid, OverlayPlacement>,
     // active_overlay: Option<Uuid>,
     focus_stack: Vec<Uuid>,
 
@@ -71,8 +194,44 @@ pub struct Editor<W: Write> {
     sender_copy: Sender<EditorEvent>,
 
     paused_events: Vec<PausedEvent>,
+
+    /// Files opened in this session, most recent first
+    recent_files: Vec<PathBuf>,
+    file_finder: Option<FileFinder>,
+
+    /// Id of the file explorer sidebar, it lives in `buffers`
+    explorer: Option<Uuid>,
+
+    /// Shown on the last row until the next key press
+    message: Option<String>,
+
+    swap_files: SwapFiles,
+    /// Files whose swap file was written by this session, with the revision that was written
+    swapped: HashMap<PathBuf, usize>,
+
+    watcher: FileWatcher,
+    /// Files of the open documents, as passed to the watcher
+    watched: HashSet<PathBuf>,
+
+    /// Folder the editor works in, relative paths are resolved from it and the session is saved
+    /// for it on exit. Starts as the working directory and is replaced by opening a folder.
+    root: PathBuf,
+    /// Set when the session was restored at startup, only then it's saved on exit. Otherwise the
+    /// files passed on the command line would replace it.
+    session_restored: bool,
+    /// Where the cursor was left in recently edited files
+    positions: FilePositions,
+    /// Languages files are highlighted in, the first one matching a file is used
+    languages: Vec<Rc<Language>>,
+    language_servers: LanguageServers,
+
+    should_quit: bool,
 }
 
+const EXPLORER_WIDTH: u16 = 30;
+/// Share of the split moved by one resize command
+const PANE_RESIZE_STEP: f32 = 0.05;
+
 impl<W: Write> Editor<W> {
     pub fn new(
         settings: Settings,
@@ -80,95 +239,213 @@ impl<W: Write> Editor<W> {
         sender_copy: Sender<EditorEvent>,
         msg_receiver: Receiver<EditorEvent>,
     ) -> io::Result<Self> {
+        Self::with_terminal(settings, Terminal::new(out)?, sender_copy, msg_receiver)
+    }
+
+    /// Creates an editor drawing to `terminal`
+    fn with_terminal(
+        settings: Settings,
+        terminal: Terminal<W>,
+        sender_copy: Sender<EditorEvent>,
+        msg_receiver: Receiver<EditorEvent>,
+    ) -> io::Result<Self> {
+        let swap_files = SwapFiles::new(settings.swap_dir.clone());
+        let watcher = FileWatcher::new(sender_copy.clone());
+        let positions = FilePositions::load(settings.positions_file.clone());
+        let root = std::env::current_dir()?;
+        let language_servers = LanguageServers::new(
+            &settings.language_servers,
+            Some(root.clone()),
+            sender_copy.clone(),
+        );
+
+        // User languages come first, so they can replace the built-in ones
+        let (mut languages, errors) = grammar::load_dir(&settings.languages_dir);
+        languages.extend(language::builtin());
+        let message = (!errors.is_empty())
+            .then(|| format!("Problems in ~/.revo/languages: {}", errors.join("; ")));
+
         Ok(Self {
             settings,
             buffers: HashMap::new(),
-            // active_buffer: None,
+            buffer_list: vec![],
+            layout: None,
             overlays: HashMap::new(),
+            overlay_placements: HashMap::new(),
             // active_overlay: None,
             focus_stack: vec![],
-            terminal: Terminal::new(out)?,
+            terminal,
             msg_receiver,
             sender_copy,
             paused_events: vec![],
+            recent_files: vec![],
+            file_finder: None,
+            explorer: None,
+            message,
+            swap_files,
+            swapped: HashMap::new(),
+            watcher,
+            watched: HashSet::new(),
+            root,
+            session_restored: false,
+            positions,
+            languages,
+            language_servers,
+            should_quit: false,
         })
     }
 
     pub fn start(&mut self) -> io::Result<()> {
+        // Shows problems found while loading, before the first event comes in
+        self.draw()?;
+
         loop {
             if let Ok(event) = self.msg_receiver.recv() {
                 match event {
                     EditorEvent::Input(event) => match event {
-                        Event::Key(KeyEvent {
-                            code: KeyCode::Char('q'),
-                            modifiers: KeyModifiers::CONTROL,
-                            ..
-                        }) => break,
-
                         Event::Resize(w, h) => self.resize(w, h),
 
-                        _ => self.forward_event(event),
+                        Event::Key(_) if self.message.is_some() => {
+                            self.message = None;
+                            match self.command_for_event(&event) {
+                                Some(command) => self.run_command(command)?,
+                                None => self.forward_event(event),
+                            }
+                        }
+
+                        _ => match self.command_for_event(&event) {
+                            Some(command) => self.run_command(command)?,
+                            None => self.forward_event(event),
+                        },
                     },
+                    EditorEvent::Command(command) => self.run_command(command)?,
+                    EditorEvent::Explorer { id, event } => self.handle_explorer_event(id, event)?,
+                    EditorEvent::OpenFolder(path) => self.open_folder(path)?,
                     EditorEvent::Buffer(buffer_event) => self.handle_buffer_event(buffer_event)?,
-                    EditorEvent::OpenFile(path_buf) => {
-                        // The x, y, width, and height values are set to default values (you can adjust these as needed)
-                        let x = 0;
-                        let y = 0;
-
-                        // Return the Buffer inside an Option, or None if there was an error
-                        let buf = Buffer::new(
-                            path_buf,
-                            x,
-                            y,
-                            self.terminal.width,
-                            self.terminal.height,
-                            true,
-                            false,
-                            BufferLogic::Editor,
-                            "",
-                            self.sender_copy.clone(),
-                        )?;
-
-                        let id = self.add_buffer(buf);
-                        self.activate_buffer(id);
+                    EditorEvent::FilesFound { selector_id, files } => {
+                        self.add_found_files(selector_id, files)
                     }
+                    EditorEvent::OpenFile(path) => self.open_file(path, None, false),
+                    EditorEvent::OpenFileAt { path, line, column } => {
+                        self.open_file(path, Some((line, column)), false)
+                    }
+                    EditorEvent::OpenFileReadOnly { path, position } => {
+                        self.open_file(path, position, true)
+                    }
+                    EditorEvent::WriteSwapFiles => self.write_swap_files(),
+                    EditorEvent::RecoverSwap { id } => self.offer_swap_recovery(id)?,
+                    EditorEvent::FileChanged(path) => self.handle_file_changed(path)?,
+                    EditorEvent::SaveTo { id, path } => self.save_to(id, path)?,
+                    EditorEvent::ShowMessage(message) => self.show_message(message),
+                    EditorEvent::RestoreSession => self.restore_session(),
+                    EditorEvent::FoldToLevel { id } => self.ask_fold_level(id)?,
+                    EditorEvent::LanguageServer {
+                        language,
+                        generation,
+                        message,
+                    } => self.handle_server_message(&language, generation, message)?,
+                    EditorEvent::LanguageServerStopped {
+                        language,
+                        generation,
+                    } => {
+                        if self.language_servers.stopped(&language, generation) {
+                            self.show_message(format!("The {language} language server stopped"));
+                            self.clear_server_diagnostics(&language);
+                        }
+                    }
+                    EditorEvent::RenameSymbol { id } => self.ask_new_name(id)?,
+                    // Only paused while the completions are listed
+                    EditorEvent::Complete { .. } => (),
                 }
             } else {
                 unreachable!("As long as input thread is running, this should never be reached.");
             }
 
-            self.begin_draw()?;
+            if self.should_quit {
+                break;
+            }
+
+            self.draw()?;
+        }
+
+        // Quitting is a clean exit, even when changes were discarded
+        for path in self.swapped.keys() {
+            self.swap_files.remove(path);
+        }
 
-            self.draw_buffers();
+        self.save_session();
 
-            self.end_draw()?;
-            self.show_cursor()?;
+        for id in self.tabs() {
+            self.remember_position(id);
         }
+        // The editor is closing, there is nobody left to tell
+        let _ = self.positions.save();
 
         Ok(())
     }
 
-    pub fn resize(&mut self, w: u16, h: u16) {
-        // TODO: Handling resize of active buffers?
+    fn draw(&mut self) -> io::Result<()> {
+        self.sync_views();
+        self.sync_language_servers();
+        self.begin_draw()?;
+
+        self.draw_buffers();
+
+        self.end_draw()?;
+        self.show_cursor()
+    }
 
+    pub fn resize(&mut self, w: u16, h: u16) {
         self.terminal.resize(w, h);
+
+        self.layout_buffers();
+        self.layout_overlays();
     }
 
     pub fn add_buffer(&mut self, mut buf: Buffer) -> Uuid {
         let uuid = Uuid::new_v4();
         buf.id = uuid;
         buf.is_overlay = false;
+        if buf.logic == BufferLogic::Editor {
+            self.buffer_list.push(uuid);
+        }
         let result = self.buffers.insert(buf.id, buf);
         assert!(result.is_none()); // If this goes through means we accidentally wrote over a previous buffer
         uuid
     }
 
+    /// Shows and focuses buffer `id`. Ids of closed buffers are ignored, selectors can still list
+    /// a buffer that was closed while they were open.
     pub fn activate_buffer(&mut self, id: Uuid) {
-        if self.buffers.contains_key(&id) {
-            self.focus_stack.push(id);
-        } else {
-            panic!("Tried to activate an invalid buffer.");
+        if !self.buffers.contains_key(&id) {
+            return;
+        }
+
+        if self.buffer_list.contains(&id) {
+            self.show_in_active_pane(id);
+        }
+        self.remove_focus(id);
+        self.focus_stack.push(id);
+    }
+
+    /// Shows an editor buffer in the active pane, unless another pane already shows it
+    fn show_in_active_pane(&mut self, id: Uuid) {
+        let active = self.active_buffer_id();
+
+        match (&mut self.layout, active) {
+            (Some(layout), _) if layout.contains(id) => return,
+            (Some(layout), Some(active)) => {
+                layout.replace(active, id);
+
+                // Extra views of a document are only kept while they are shown
+                if self.has_other_views(active) {
+                    self.drop_view(active);
+                }
+            }
+            _ => self.layout = Some(LayoutNode::Pane(id)),
         }
+
+        self.layout_buffers();
     }
 
     pub fn add_overlay(&mut self, mut ov: Buffer) -> Uuid {
@@ -189,21 +466,18 @@ impl<W: Write> Editor<W> {
     }
 
     fn remove_focus(&mut self, id: Uuid) {
-        self.focus_stack = self.focus_stack.iter().filter(|_id| **_id != id).map(|id| *id).collect();
+        self.focus_stack.retain(|_id| *_id != id);
     }
 
-    fn get_focused_buffer<'a>(&'a mut self) -> Option<&'a Buffer> {
-        let id = match self.focus_stack.last() {
-            Some(id) => id,
-            None => return None,
-        };
+    fn get_focused_buffer(&mut self) -> Option<&Buffer> {
+        let id = self.focus_stack.last()?;
 
-        let buff = self.buffers.get(&id);
+        let buff = self.buffers.get(id);
         if buff.is_some() {
             return buff;
         }
 
-        let buff = self.overlays.get(&id);
+        let buff = self.overlays.get(id);
         if buff.is_some() {
             return buff;
         }
@@ -211,18 +485,15 @@ impl<W: Write> Editor<W> {
         unreachable!("ID was not present in any buffers list")
     }
 
-    fn get_focused_buffer_mut<'a>(&'a mut self) -> Option<&'a mut Buffer> {
-        let id = match self.focus_stack.last() {
-            Some(id) => id,
-            None => return None,
-        };
+    fn get_focused_buffer_mut(&mut self) -> Option<&mut Buffer> {
+        let id = self.focus_stack.last()?;
 
-        let buff = self.buffers.get_mut(&id);
+        let buff = self.buffers.get_mut(id);
         if buff.is_some() {
             return buff;
         }
 
-        let buff = self.overlays.get_mut(&id);
+        let buff = self.overlays.get_mut(id);
         if buff.is_some() {
             return buff;
         }
@@ -234,7 +505,16 @@ impl<W: Write> Editor<W> {
         if self.overlays.contains_key(&id) {
             self.remove_focus(id);
 
+            if self
+                .file_finder
+                .as_ref()
+                .is_some_and(|f| f.selector_id == id)
+            {
+                self.stop_file_finder();
+            }
+
             self.overlays.remove(&id);
+            self.overlay_placements.remove(&id);
         } else {
             panic!("Tried to remove an invalid buffer.");
         }
@@ -249,19 +529,228 @@ impl<W: Write> Editor<W> {
     }
 
     pub fn draw_buffers(&mut self) {
-        if self.buffers.len() > 0 {
-            for buf in self.buffers.values().filter(|b| b.visible) {
-                self.terminal.draw_buffer(buf, &self.settings.theme);
+        if let Some(buf) = self.explorer.and_then(|id| self.buffers.get(&id)) {
+            self.terminal.draw_buffer(buf, &self.settings.theme);
+        }
+
+        // Only the buffers shown in panes are visible, the rest are behind their tabs
+        match &self.layout {
+            Some(layout) => {
+                let (panes, separators) = layout.compute(self.pane_area());
+
+                for (id, _) in panes {
+                    if let Some(buf) = self.buffers.get(&id) {
+                        self.terminal.draw_buffer(buf, &self.settings.theme);
+                    }
+                }
+
+                for separator in separators {
+                    self.terminal
+                        .draw_separator(separator, &self.settings.theme);
+                }
+
+                self.draw_tab_bar();
             }
-        } else {
-            self.terminal.draw_welcome_msg();
+            None => self.terminal.draw_welcome_msg(),
+        }
+
+        // The word completions of the focused buffer are listed over the other buffers
+        let focused = self
+            .focused_buffer_id()
+            .and_then(|id| self.buffers.get(&id));
+        if let Some(buf) = focused {
+            self.terminal.draw_completion(buf, &self.settings.theme);
         }
 
-        if self.overlays.len() > 0 {
+        if !self.overlays.is_empty() {
             for buf in self.overlays.values().filter(|b| b.visible) {
                 self.terminal.draw_buffer(buf, &self.settings.theme);
             }
         }
+
+        if let Some(message) = &self.message {
+            self.terminal.draw_message(message, &self.settings.theme);
+        }
+    }
+
+    fn draw_tab_bar(&mut self) {
+        let active = self.active_buffer_id();
+        let tabs: Vec<Tab> = self
+            .tabs()
+            .iter()
+            .filter_map(|id| self.buffers.get(id))
+            .map(|buf| Tab {
+                title: buf.display_name(),
+                active: Some(buf.id) == active,
+                modified: buf.is_modified(),
+            })
+            .collect();
+
+        let (x, _, width, _) = self.editor_area();
+        self.terminal
+            .draw_tab_bar(x, 0, width, &tabs, &self.settings.theme);
+    }
+
+    /// Shows a message on the last row of the terminal until the next key press
+    pub fn show_message(&mut self, message: impl Into<String>) {
+        self.message = Some(message.into());
+    }
+
+    /// Shown when a command needs the syntax tree of a buffer that's not in a known language
+    fn show_no_syntax_tree_message(&mut self) {
+        self.show_message("The buffer is not in a known language, it has no syntax tree");
+    }
+
+    /// Returns the x, y, width and height of the area where editor buffers are placed
+    fn editor_area(&self) -> (u16, u16, u16, u16) {
+        let explorer_width = match self.explorer {
+            Some(_) => self.explorer_width(),
+            None => 0,
+        };
+
+        // The first row is used by the tab bar
+        (
+            explorer_width,
+            1,
+            self.terminal.width.saturating_sub(explorer_width),
+            self.terminal.height.saturating_sub(1),
+        )
+    }
+
+    fn explorer_width(&self) -> u16 {
+        std::cmp::min(EXPLORER_WIDTH, self.terminal.width / 2)
+    }
+
+    fn pane_area(&self) -> Rect {
+        let (x, y, width, height) = self.editor_area();
+        Rect::new(x, y, width, height)
+    }
+
+    /// Moves the explorer to the left edge, and the buffers shown in panes into their pane's
+    /// rectangle
+    fn layout_buffers(&mut self) {
+        let (explorer_width, height) = (self.explorer_width(), self.terminal.height);
+        if let Some(explorer) = self.explorer.and_then(|id| self.buffers.get_mut(&id)) {
+            explorer.move_to(0, 0);
+            explorer.resize(explorer_width, height);
+        }
+
+        let panes = match &self.layout {
+            Some(layout) => layout.compute(self.pane_area()).0,
+            None => return,
+        };
+
+        for (id, rect) in panes {
+            if let Some(buf) = self.buffers.get_mut(&id) {
+                buf.move_to(rect.x, rect.y);
+                buf.resize(rect.width, rect.height);
+            }
+        }
+    }
+
+    /// Returns the most recently used editor buffer that is not shown in any pane
+    fn hidden_buffer(&self) -> Option<Uuid> {
+        let visible = |id: &Uuid| self.layout.as_ref().is_some_and(|l| l.contains(*id));
+
+        self.focus_stack
+            .iter()
+            .rev()
+            .chain(self.buffer_list.iter())
+            .find(|id| self.buffer_list.contains(id) && !visible(id))
+            .copied()
+    }
+
+    /// Splits the active pane, the new pane shows another view of the same document
+    fn split_pane(&mut self, direction: SplitDirection) {
+        let Some((active, view)) = self
+            .active_buffer_id()
+            .and_then(|id| self.buffers.get(&id))
+            .map(|buf| (buf.id, buf.new_view()))
+        else {
+            return;
+        };
+
+        let new = self.add_buffer(view);
+
+        if let Some(layout) = &mut self.layout {
+            layout.split(active, new, direction);
+        }
+        self.layout_buffers();
+        self.activate_buffer(new);
+    }
+
+    /// Returns true if another buffer is a view of the same document as buffer `id`
+    fn has_other_views(&self, id: Uuid) -> bool {
+        let Some(buf) = self.buffers.get(&id) else {
+            return false;
+        };
+
+        self.buffers
+            .values()
+            .any(|other| other.id != id && other.shares_document(buf))
+    }
+
+    /// Removes a view that is no longer shown, its document stays open in other views
+    fn drop_view(&mut self, id: Uuid) {
+        self.buffers.remove(&id);
+        self.buffer_list.retain(|i| *i != id);
+        self.remove_focus(id);
+    }
+
+    /// Moves the cursors of all views along with edits made through other views
+    fn sync_views(&mut self) {
+        for buf in self.buffers.values_mut() {
+            buf.sync();
+        }
+    }
+
+    /// Removes the active pane, its buffer stays open behind its tab
+    fn close_pane(&mut self) {
+        let active = self.active_buffer_id();
+        let (Some(layout), Some(active)) = (&mut self.layout, active) else {
+            return;
+        };
+
+        if !layout.remove(active) {
+            self.show_message("Can't close the last pane");
+            return;
+        }
+
+        // Extra views of a document are only kept while they are shown
+        if self.has_other_views(active) {
+            self.drop_view(active);
+        }
+
+        self.layout_buffers();
+        if let Some(id) = self.active_buffer_id() {
+            self.activate_buffer(id);
+        }
+    }
+
+    fn focus_pane(&mut self, direction: FocusDirection) {
+        let (Some(layout), Some(active)) = (&self.layout, self.active_buffer_id()) else {
+            return;
+        };
+
+        let (panes, _) = layout.compute(self.pane_area());
+        if let Some(id) = layout::neighbour(&panes, active, direction) {
+            self.activate_buffer(id);
+        }
+    }
+
+    fn resize_pane(&mut self, direction: SplitDirection, delta: f32) {
+        let active = self.active_buffer_id();
+        if let (Some(layout), Some(active)) = (&mut self.layout, active) {
+            layout.resize(active, direction, delta);
+            self.layout_buffers();
+        }
+    }
+
+    fn equalize_panes(&mut self) {
+        if let Some(layout) = &mut self.layout {
+            layout.equalize();
+            self.layout_buffers();
+        }
     }
 
     // TODO: This will work with FocusStack
@@ -271,6 +760,11 @@ impl<W: Write> Editor<W> {
             None => return Ok(()),
         };
 
+        // The explorer highlights the selected line instead
+        if buf.logic == BufferLogic::Explorer {
+            return Ok(());
+        }
+
         let (cursor_x, cursor_y) = buf.cursor_xy();
 
         if cursor_x >= buf.x as isize
@@ -286,119 +780,2489 @@ impl<W: Write> Editor<W> {
 
     // TODO: This will work with FocusStack
     pub fn forward_event(&mut self, event: Event) {
-        if let Some(buf) = self.get_focused_buffer_mut() {
-            buf.parse_input(event);
+        let Some(buf) = self.get_focused_buffer_mut() else {
+            return;
+        };
+        if buf.completion_logic(&event) {
+            return;
+        }
+
+        let (id, is_editor) = (buf.id, buf.logic == BufferLogic::Editor);
+        buf.parse_input(event.clone());
+        if is_editor {
+            self.update_completion(id, &event);
         }
     }
 
-    pub fn handle_buffer_event(&mut self, event: BufferEvent) -> io::Result<()> {
-        match event {
-            BufferEvent::Save { id } => {
-                self.save_buffer(id)?;
-            }
-            BufferEvent::SaveAs { .. } => {
-                // Pause this event
-                let paused_event_id = Uuid::new_v4();
-                self.paused_events.push(PausedEvent {
-                    id: paused_event_id,
-                    event: EditorEvent::Buffer(event),
-                });
+    /// Keeps the word completions of an editor buffer in step with a key sent to it: typing a word
+    /// lists them once it is long enough, typing or deleting narrows them down, and any other key
+    /// closes them
+    fn update_completion(&mut self, id: Uuid, event: &Event) {
+        let Some(buf) = self.buffers.get_mut(&id) else {
+            return;
+        };
 
-                // Open up a new overlay
-                let width = 32;
-                let height = 3;
-                let x = (self.terminal.width / 2).saturating_sub(width / 2);
-                let y = (self.terminal.height / 2).saturating_sub(height);
-
-                let mut overlay = Buffer::new(
-                    PathBuf::from("Save As:"),
-                    x,
-                    y,
-                    width,
-                    height,
-                    false,
-                    true,
-                    BufferLogic::InputBox,
-                    "Save As",
-                    self.sender_copy.clone(),
-                )?;
-                overlay.set_paused_event_id(paused_event_id);
-                let id = self.add_overlay(overlay);
+        let typing = match event {
+            Event::Key(KeyEvent {
+                code: KeyCode::Char(c),
+                modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                ..
+            }) => word_completion::is_word_char(*c),
+            Event::Key(KeyEvent {
+                code: KeyCode::Backspace,
+                modifiers: KeyModifiers::NONE,
+                ..
+            }) => buf.completion.is_some(),
+            _ => false,
+        };
+        let trigger = self.settings.word_completion_trigger;
+        let long_enough = trigger > 0 && buf.typed_word().chars().count() >= trigger;
 
-                // Activate that overlay
-                self.activate_overlay(id);
-            }
-            BufferEvent::Close { id, is_overlay } => {
-                if is_overlay {
-                    self.close_overlay(id);
-                } else {
-                    todo!("Add closing for normal buffers");
-                }
-            }
-            BufferEvent::ResumeEvent {
-                paused_event_id,
-                result,
-            } => {
-                // Look through our paused events and match this id
-                if let Some(event) = self
-                    .paused_events
-                    .iter()
-                    .filter(|e| e.id == paused_event_id)
-                    .next()
-                {
-                    match event.event {
-                        EditorEvent::Buffer(BufferEvent::SaveAs { id }) => {
-                            self.save_buffer_as(id, result)?;
-                        }
-                        _ => (),
+        if typing && !buf.is_read_only() && (buf.completion.is_some() || long_enough) {
+            self.complete_word(id);
+        } else {
+            buf.completion = None;
+        }
+    }
+
+    /// Lists the words of the open documents completing the one typed before the cursor, closing
+    /// the list when there are none
+    fn complete_word(&mut self, id: Uuid) {
+        let Some(buf) = self.buffers.get(&id) else {
+            return;
+        };
+        let prefix = buf.typed_word();
+        let words = if prefix.is_empty() {
+            vec![]
+        } else {
+            let (index, cursor_line) = {
+                let mut document = buf.data.document_mut();
+                (document.word_index(), document.line_of(buf.data.cursor()))
+            };
+            // The words of a document are only found again after it was edited
+            let others: Vec<_> = self
+                .buffers
+                .values()
+                .filter(|b| b.logic == BufferLogic::Editor && !b.shares_document(buf))
+                .map(|b| b.data.document_mut().word_index())
+                .collect();
+            let others: Vec<&WordIndex> = others.iter().map(|index| index.as_ref()).collect();
+            let typed = buf.word_at_cursor();
+            word_completion::rank_words(&prefix, &index, cursor_line, &typed, &others)
+        };
+
+        let word_start = buf.word_start();
+        if let Some(buf) = self.buffers.get_mut(&id) {
+            buf.completion = (!words.is_empty()).then(|| WordCompletion::new(word_start, words));
+        }
+    }
+
+    /// Returns the command bound to a key press, if it should be run in the current focus
+    fn command_for_event(&self, event: &Event) -> Option<Command> {
+        let Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press,
+            ..
+        }) = event
+        else {
+            return None;
+        };
+
+        let command = Command::from_key(*code, *modifiers)?;
+
+        // Overlays consume all input, except for global commands
+        let overlay_focused = match self.focus_stack.last() {
+            Some(id) => self.overlays.contains_key(id),
+            None => false,
+        };
+
+        if overlay_focused && !command.is_global() {
+            None
+        } else {
+            Some(command)
+        }
+    }
+
+    fn get_focused_editor_buffer_mut(&mut self) -> Option<&mut Buffer> {
+        self.focused_buffer_id()
+            .and_then(|id| self.buffers.get_mut(&id))
+            .filter(|b| b.logic == BufferLogic::Editor)
+    }
+
+    /// Removes a buffer without asking, unsaved changes are lost
+    fn close_buffer(&mut self, id: Uuid) {
+        self.remember_position(id);
+        if self.buffers.remove(&id).is_none() {
+            return;
+        }
+
+        let was_focused = self.focus_stack.last() == Some(&id);
+        self.remove_focus(id);
+        self.buffer_list.retain(|i| *i != id);
+
+        // The pane shows another hidden buffer, or is removed if there is none
+        if self.layout.as_ref().is_some_and(|l| l.contains(id)) {
+            let replacement = self.hidden_buffer();
+            if let Some(layout) = &mut self.layout {
+                match replacement {
+                    Some(replacement) => {
+                        layout.replace(id, replacement);
                     }
+                    None if layout.remove(id) => (),
+                    None => self.layout = None,
                 }
             }
-            BufferEvent::CancelEvent { paused_event_id } => {
-                // Look through our paused events and match this id
-                if let Some((i, _)) = self
-                    .paused_events
-                    .iter()
-                    .filter(|e| e.id == paused_event_id)
-                    .enumerate()
-                    .next()
-                {
-                    self.paused_events.remove(i);
+            self.layout_buffers();
+
+            if was_focused {
+                if let Some(next) = replacement.or_else(|| self.active_buffer_id()) {
+                    self.activate_buffer(next);
                 }
             }
         }
 
-        Ok(())
+        if self.explorer == Some(id) {
+            self.explorer = None;
+            self.layout_buffers();
+        }
+
+        self.remove_stale_swap_files();
+        self.update_watched_files();
     }
 
-    fn save_buffer(&mut self, id: Uuid) -> io::Result<()> {
-        if let Some(buf) = self.buffers.get(&id) {
-            let contents: String = buf.data.to_string();
-            if let Some(file_path) = &buf.file_path {
-                fs::write(file_path, contents)?;
-            } else {
-                // If we try to save a buffer without a name...
-                self.handle_buffer_event(BufferEvent::SaveAs { id })?;
+    /// Returns the id of the buffer in the active pane, the pane that was focused last
+    fn active_buffer_id(&self) -> Option<Uuid> {
+        let layout = self.layout.as_ref()?;
+
+        self.focus_stack
+            .iter()
+            .rev()
+            .find(|id| layout.contains(**id))
+            .copied()
+            .or_else(|| layout.panes().first().copied())
+    }
+
+    /// Returns the editor buffers shown as tabs, in order. A document shown in several views has a
+    /// single tab, standing for its active or most recently focused view.
+    fn tabs(&self) -> Vec<Uuid> {
+        let active = self.active_buffer_id();
+        let rank = |id: &Uuid| match self.focus_stack.iter().position(|i| i == id) {
+            _ if Some(*id) == active => usize::MAX,
+            Some(i) => i + 1,
+            None => 0,
+        };
+
+        let mut tabs: Vec<Uuid> = vec![];
+        for id in self.buffer_list.iter() {
+            let Some(buf) = self.buffers.get(id) else {
+                continue;
+            };
+
+            let same_document = tabs.iter().position(|tab| {
+                self.buffers
+                    .get(tab)
+                    .is_some_and(|other| other.shares_document(buf))
+            });
+            match same_document {
+                Some(i) if rank(id) > rank(&tabs[i]) => tabs[i] = *id,
+                Some(_) => (),
+                None => tabs.push(*id),
             }
         }
 
-        Ok(())
+        tabs
     }
 
-    fn save_buffer_as(&mut self, id: Uuid, file_name: String) -> io::Result<()> {
-        if let Some(buf) = self.buffers.get_mut(&id) {
-            let contents: String = buf.data.to_string();
+    /// Activates the buffer `offset` tabs away from the active one, wrapping around
+    fn cycle_buffer(&mut self, offset: isize) {
+        let tabs = self.tabs();
+        let count = tabs.len() as isize;
+        let current = match self
+            .active_buffer_id()
+            .and_then(|id| tabs.iter().position(|i| *i == id))
+        {
+            Some(current) => current as isize,
+            None => return,
+        };
 
-            match &mut buf.file_path {
-                Some(path) => path.set_file_name(file_name),
-                None => buf.set_path(PathBuf::from(file_name)).unwrap(),
-            }
+        let next = (current + offset).rem_euclid(count) as usize;
+        self.activate_buffer(tabs[next]);
+    }
 
-            if let Some(file_path) = &buf.file_path {
-                fs::write(file_path, contents)?;
-            }
+    /// Activates the buffer shown in the nth tab (1-indexed)
+    fn goto_buffer(&mut self, n: usize) {
+        if let Some(id) = n.checked_sub(1).and_then(|i| self.tabs().get(i).copied()) {
+            self.activate_buffer(id);
         }
+    }
+
+    fn open_buffer_switcher(&mut self) -> io::Result<()> {
+        // Most recently used first
+        let tabs = self.tabs();
+        let mut ids: Vec<Uuid> = self
+            .focus_stack
+            .iter()
+            .rev()
+            .filter(|id| tabs.contains(id))
+            .copied()
+            .collect();
+        ids.extend(tabs.iter().filter(|id| !self.focus_stack.contains(id)));
+
+        let items: Vec<SelectorItem> = ids
+            .iter()
+            .filter_map(|id| self.buffers.get(id))
+            .map(|buf| {
+                let mut label = buf.display_name();
+                if buf.is_modified() {
+                    label.push_str(" ●");
+                }
+                let detail = buf
+                    .file_path()
+                    .map(|p| self.relative(&p).display().to_string());
+                SelectorItem::new(label, detail).with_value(buf.id.to_string())
+            })
+            .collect();
+
+        let height = selector_height(items.len());
+        let mut selector = Selector::new(items);
+        // Preselect the previous buffer, so Enter toggles between the last two
+        selector.select_next(1);
+
+        self.open_selector(
+            "Buffers",
+            selector,
+            height,
+            EditorEvent::Command(Command::SwitchBuffer),
+        )?;
 
         Ok(())
     }
+
+    /// Returns the id of the focused buffer, if it is not an overlay
+    fn focused_buffer_id(&self) -> Option<Uuid> {
+        self.focus_stack
+            .last()
+            .filter(|id| self.buffers.contains_key(id))
+            .copied()
+    }
+
+    pub fn run_command(&mut self, command: Command) -> io::Result<()> {
+        // Word completions are only listed while typing
+        if command != Command::CompleteWord {
+            if let Some(buf) = self.get_focused_editor_buffer_mut() {
+                buf.completion = None;
+            }
+        }
+
+        match command {
+            Command::Save => {
+                if let Some(id) = self.focused_buffer_id() {
+                    self.save_buffer(id)?;
+                }
+            }
+            Command::SaveAs => {
+                if let Some(id) = self.focused_buffer_id() {
+                    self.handle_buffer_event(BufferEvent::SaveAs { id })?;
+                }
+            }
+            Command::ReloadFile => {
+                if let Some(id) = self.focused_buffer_id() {
+                    self.request_reload(id)?;
+                }
+            }
+            Command::Undo => match self.get_focused_editor_buffer_mut() {
+                Some(buf) if buf.is_read_only() => {
+                    let message = buf.read_only_message();
+                    self.show_message(message);
+                }
+                Some(buf) => buf.undo(),
+                None => (),
+            },
+            Command::Redo => match self.get_focused_editor_buffer_mut() {
+                Some(buf) if buf.is_read_only() => {
+                    let message = buf.read_only_message();
+                    self.show_message(message);
+                }
+                Some(buf) => buf.redo(),
+                None => (),
+            },
+            Command::ExpandSelection => {
+                if let Some(buf) = self.get_focused_editor_buffer_mut() {
+                    if !buf.expand_selection() {
+                        self.show_no_syntax_tree_message();
+                    }
+                }
+            }
+            Command::ShrinkSelection => {
+                if let Some(buf) = self.get_focused_editor_buffer_mut() {
+                    buf.shrink_selection();
+                }
+            }
+            Command::GotoParentNode => {
+                if let Some(buf) = self.get_focused_editor_buffer_mut() {
+                    if !buf.goto_parent_node() {
+                        self.show_no_syntax_tree_message();
+                    }
+                }
+            }
+            Command::GotoNextSibling | Command::GotoPrevSibling => {
+                let forward = command == Command::GotoNextSibling;
+                if let Some(buf) = self.get_focused_editor_buffer_mut() {
+                    if !buf.goto_sibling_node(forward) {
+                        self.show_no_syntax_tree_message();
+                    }
+                }
+            }
+            Command::Fold => {
+                if let Some(buf) = self.get_focused_editor_buffer_mut() {
+                    if !buf.fold() {
+                        self.show_message("Nothing to fold here");
+                    }
+                }
+            }
+            Command::Unfold => {
+                if let Some(buf) = self.get_focused_editor_buffer_mut() {
+                    buf.unfold();
+                }
+            }
+            Command::FoldToLevel => {
+                if let Some(id) = self.get_focused_editor_buffer_mut().map(|buf| buf.id) {
+                    self.ask_fold_level(id)?;
+                }
+            }
+            Command::UnfoldAll => {
+                if let Some(buf) = self.get_focused_editor_buffer_mut() {
+                    buf.unfold_all();
+                }
+            }
+            Command::Hover
+            | Command::GotoDefinition
+            | Command::FindReferences
+            | Command::ShowCompletions => {
+                let Some(buf) = self.get_focused_editor_buffer_mut() else {
+                    return Ok(());
+                };
+                let id = buf.id;
+                let (request, method, params) = match command {
+                    Command::Hover => (Request::Hover, "textDocument/hover", json!({})),
+                    Command::GotoDefinition => {
+                        (Request::Definition, "textDocument/definition", json!({}))
+                    }
+                    Command::FindReferences => (
+                        Request::References,
+                        "textDocument/references",
+                        json!({ "context": { "includeDeclaration": true } }),
+                    ),
+                    _ => {
+                        let request = Request::Completion {
+                            id,
+                            word_start: buf.word_start(),
+                            revision: buf.data.revision(),
+                        };
+                        (request, "textDocument/completion", json!({}))
+                    }
+                };
+                self.request_at_cursor(id, request, method, params);
+            }
+            Command::RenameSymbol => {
+                if let Some(id) = self.get_focused_editor_buffer_mut().map(|buf| buf.id) {
+                    self.ask_new_name(id)?;
+                }
+            }
+            Command::FormatDocument => {
+                if let Some(id) = self.get_focused_editor_buffer_mut().map(|buf| buf.id) {
+                    self.format_document(id);
+                }
+            }
+            Command::CompleteWord => {
+                let Some(buf) = self.get_focused_editor_buffer_mut() else {
+                    return Ok(());
+                };
+                if buf.is_read_only() {
+                    let message = buf.read_only_message();
+                    self.show_message(message);
+                    return Ok(());
+                }
+
+                let id = buf.id;
+                self.complete_word(id);
+                let listed = self
+                    .buffers
+                    .get(&id)
+                    .is_some_and(|buf| buf.completion.is_some());
+                if !listed {
+                    self.show_message("No words complete this one");
+                }
+            }
+            Command::NextDiagnostic | Command::PrevDiagnostic => {
+                let Some(buf) = self.get_focused_editor_buffer_mut() else {
+                    return Ok(());
+                };
+                match buf.goto_diagnostic(command == Command::NextDiagnostic) {
+                    Some(diagnostic) => {
+                        let message =
+                            format!("{}: {}", diagnostic.severity.name(), diagnostic.summary());
+                        self.show_message(message);
+                    }
+                    None => self.show_message("No diagnostics in this buffer"),
+                }
+            }
+            Command::ListDiagnostics => self.list_diagnostics()?,
+            Command::ToggleReadOnly => {
+                if let Some(buf) = self.get_focused_editor_buffer_mut() {
+                    let read_only = !buf.is_read_only();
+                    buf.set_read_only(read_only);
+
+                    let state = if read_only { "read-only" } else { "editable" };
+                    let message = format!("{} is now {state}", buf.display_name());
+                    self.show_message(message);
+                }
+            }
+            Command::CloseBuffer => {
+                if let Some(id) = self.focused_buffer_id() {
+                    self.handle_buffer_event(BufferEvent::RequestClose { id })?;
+                }
+            }
+            Command::OpenCommandPalette => self.open_command_palette()?,
+            Command::FindFile => self.open_file_finder()?,
+            Command::NextBuffer => self.cycle_buffer(1),
+            Command::PrevBuffer => self.cycle_buffer(-1),
+            Command::GotoBuffer(n) => self.goto_buffer(n as usize),
+            Command::SwitchBuffer => self.open_buffer_switcher()?,
+            Command::ToggleExplorer => self.toggle_explorer()?,
+            Command::FocusExplorer => self.focus_explorer()?,
+            Command::SplitRight => self.split_pane(SplitDirection::Horizontal),
+            Command::SplitDown => self.split_pane(SplitDirection::Vertical),
+            Command::ClosePane => self.close_pane(),
+            Command::FocusPaneLeft => self.focus_pane(FocusDirection::Left),
+            Command::FocusPaneRight => self.focus_pane(FocusDirection::Right),
+            Command::FocusPaneUp => self.focus_pane(FocusDirection::Up),
+            Command::FocusPaneDown => self.focus_pane(FocusDirection::Down),
+            Command::GrowPaneWidth => {
+                self.resize_pane(SplitDirection::Horizontal, PANE_RESIZE_STEP)
+            }
+            Command::ShrinkPaneWidth => {
+                self.resize_pane(SplitDirection::Horizontal, -PANE_RESIZE_STEP)
+            }
+            Command::GrowPaneHeight => self.resize_pane(SplitDirection::Vertical, PANE_RESIZE_STEP),
+            Command::ShrinkPaneHeight => {
+                self.resize_pane(SplitDirection::Vertical, -PANE_RESIZE_STEP)
+            }
+            Command::EqualizePanes => self.equalize_panes(),
+            Command::Quit => self.request_quit()?,
+            Command::ForceQuit => self.should_quit = true,
+        }
+
+        Ok(())
+    }
+
+    /// Opens a file in a new buffer, optionally placing the cursor at a 1-indexed (line, column).
+    ///
+    /// Files we can't write to are opened read-only, like the ones opened with `read_only` set.
+    fn open_file(&mut self, path: PathBuf, position: Option<(usize, usize)>, read_only: bool) {
+        let path = self.resolve(&path);
+        self.add_recent_file(&path);
+
+        // Switch to the buffer if the file is already open
+        if let Some(id) = self.find_buffer_by_path(&path) {
+            if let (Some((line, column)), Some(buf)) = (position, self.buffers.get_mut(&id)) {
+                buf.goto(line.saturating_sub(1), column.saturating_sub(1));
+            }
+            if let (true, Some(buf)) = (read_only, self.buffers.get_mut(&id)) {
+                buf.set_read_only(true);
+            }
+            self.activate_buffer(id);
+
+            // A swap file that was looked at but not resolved yet is offered again
+            if self.buffers.get(&id).is_some_and(|b| !b.is_modified()) {
+                self.sender_copy
+                    .send(EditorEvent::RecoverSwap { id })
+                    .expect("Failed to send a msg to the editor");
+            }
+            return;
+        }
+
+        let id = match self.load_file(path.clone(), read_only) {
+            Ok(id) => id,
+            Err(e) => {
+                self.show_message(format!("Failed to open {} : {e}", path.display()));
+                return;
+            }
+        };
+        self.activate_buffer(id);
+
+        // Placed after activating, so the cursor is centered in the pane's final size
+        let remembered = self.positions.get(&path);
+        match (position, remembered, self.buffers.get_mut(&id)) {
+            (Some((line, column)), _, Some(buf)) => {
+                buf.goto(line.saturating_sub(1), column.saturating_sub(1))
+            }
+            (None, Some(p), Some(buf)) => {
+                buf.restore_position(p.line, p.column, (p.scroll_x, p.scroll_y))
+            }
+            _ => (),
+        }
+    }
+
+    /// Remembers where the cursor of buffer `id` is, for the next time its file is opened
+    fn remember_position(&mut self, id: Uuid) {
+        let Some(buf) = self.buffers.get(&id) else {
+            return;
+        };
+        let Some(path) = buf.file_path().filter(|_| buf.logic == BufferLogic::Editor) else {
+            return;
+        };
+
+        let (column, line) = buf.cursor_xy_relative();
+        let position = FilePosition {
+            line,
+            column,
+            scroll_x: buf.scroll_x,
+            scroll_y: buf.scroll_y,
+        };
+        self.positions.record(&path, position);
+    }
+
+    /// Adds a buffer for the file at `path` without showing it, and watches the file and offers
+    /// its swap file like `open_file` does
+    fn load_file(&mut self, path: PathBuf, read_only: bool) -> io::Result<Uuid> {
+        let (x, y, width, height) = self.editor_area();
+
+        let mut buf = Buffer::new(
+            path.clone(),
+            x,
+            y,
+            width,
+            height,
+            true,
+            false,
+            BufferLogic::Editor,
+            "",
+            self.sender_copy.clone(),
+        )?;
+
+        let write_protected = path.exists() && !save::is_writable(&path);
+        buf.set_read_only(read_only || write_protected);
+
+        let id = self.add_buffer(buf);
+        self.update_language(id);
+        self.update_watched_files();
+
+        self.sender_copy
+            .send(EditorEvent::RecoverSwap { id })
+            .expect("Failed to send a msg to the editor");
+
+        Ok(id)
+    }
+
+    /// Picks the language of buffer `id` from its file name, or its `#!` line
+    fn update_language(&mut self, id: Uuid) {
+        let Some(buf) = self.buffers.get_mut(&id) else {
+            return;
+        };
+        let Some(path) = buf.file_path() else {
+            return;
+        };
+
+        let first_line = buf.line_text(0);
+        buf.set_language(language::detect(&self.languages, &path, &first_line));
+    }
+
+    /// Saves the open files with their cursors, the panes and the active buffer, for the root
+    /// folder
+    fn save_session(&self) {
+        if !self.session_restored {
+            return;
+        }
+
+        let mut session = Session::default();
+        let mut index_of = HashMap::new();
+        for id in self.buffer_list.iter() {
+            let Some(buf) = self.buffers.get(id) else {
+                continue;
+            };
+            // Scratch buffers can't be reopened
+            let Some(path) = buf.file_path() else {
+                continue;
+            };
+
+            let (column, line) = buf.cursor_xy_relative();
+            index_of.insert(*id, session.buffers.len());
+            session.buffers.push(SessionBuffer {
+                path,
+                line,
+                column,
+                scroll_x: buf.scroll_x,
+                scroll_y: buf.scroll_y,
+            });
+        }
+
+        session.layout = self
+            .layout
+            .as_ref()
+            .and_then(|layout| SessionLayout::from_layout(layout, &index_of));
+        session.active = self
+            .active_buffer_id()
+            .and_then(|id| index_of.get(&id))
+            .copied();
+
+        // The editor is closing, there is nobody left to tell
+        let _ = session::save(&self.settings.sessions_dir, &self.root, &session);
+    }
+
+    /// Reopens the files of the last session in the root folder, in the panes they were
+    /// shown in. Files that no longer exist are skipped.
+    fn restore_session(&mut self) {
+        // Even without a saved session, the files opened from now on make up the next one
+        self.session_restored = true;
+        let Some(session) = session::load(&self.settings.sessions_dir, &self.root) else {
+            return;
+        };
+
+        // Ids of the restored buffers, by their index in the session
+        let mut ids: Vec<Option<Uuid>> = vec![];
+        let mut missing = vec![];
+        for entry in session.buffers.iter() {
+            if !entry.path.is_file() {
+                missing.push(entry.path.display().to_string());
+                ids.push(None);
+                continue;
+            }
+
+            // Several entries of the same file are views of one document
+            let same_document = ids.iter().flatten().copied().find(|id| {
+                self.buffers
+                    .get(id)
+                    .and_then(|buf| buf.file_path())
+                    .is_some_and(|path| path == entry.path)
+            });
+            let id = match same_document {
+                Some(other) => {
+                    let view = self.buffers[&other].new_view();
+                    self.add_buffer(view)
+                }
+                None => match self.load_file(entry.path.clone(), false) {
+                    Ok(id) => id,
+                    Err(_) => {
+                        missing.push(entry.path.display().to_string());
+                        ids.push(None);
+                        continue;
+                    }
+                },
+            };
+
+            if let Some(buf) = self.buffers.get_mut(&id) {
+                buf.restore_position(entry.line, entry.column, (entry.scroll_x, entry.scroll_y));
+            }
+            ids.push(Some(id));
+        }
+
+        let layout = session
+            .layout
+            .and_then(|layout| layout.to_layout(&ids))
+            .or_else(|| ids.iter().flatten().next().map(|id| LayoutNode::Pane(*id)));
+
+        if let Some(layout) = layout {
+            // Extra views of a document are only kept while they are shown
+            let hidden_views: Vec<Uuid> = ids
+                .iter()
+                .flatten()
+                .copied()
+                .filter(|id| !layout.contains(*id))
+                .collect();
+            self.layout = Some(layout);
+            for id in hidden_views {
+                if self.has_other_views(id) {
+                    self.drop_view(id);
+                }
+            }
+            self.layout_buffers();
+
+            // Tabs not shown in a pane come back in the order they were opened, the active one is
+            // focused last
+            let active = session
+                .active
+                .and_then(|i| ids.get(i).copied().flatten())
+                .filter(|id| self.buffers.contains_key(id));
+            for id in ids.iter().flatten().chain(active.iter()) {
+                if self.buffers.contains_key(id) {
+                    self.remove_focus(*id);
+                    self.focus_stack.push(*id);
+                }
+            }
+        }
+
+        if !missing.is_empty() {
+            self.show_message(format!(
+                "Skipped missing files from the last session: {}",
+                missing.join(", ")
+            ));
+        }
+    }
+
+    /// Opens a buffer that is not backed by a file, like a diff, named after `title`
+    fn open_scratch_buffer(&mut self, title: &str, contents: &str) -> io::Result<Uuid> {
+        let (x, y, width, height) = self.editor_area();
+
+        let mut buf = Buffer::new(
+            PathBuf::new(),
+            x,
+            y,
+            width,
+            height,
+            true,
+            false,
+            BufferLogic::Editor,
+            title,
+            self.sender_copy.clone(),
+        )?;
+        buf.set_contents(contents);
+
+        let id = self.add_buffer(buf);
+        self.activate_buffer(id);
+        if let Some(buf) = self.buffers.get_mut(&id) {
+            buf.goto(0, 0);
+        }
+
+        Ok(id)
+    }
+
+    fn find_buffer_by_path(&self, path: &std::path::Path) -> Option<Uuid> {
+        let path = path.canonicalize().ok()?;
+
+        self.buffer_list
+            .iter()
+            .find(|id| {
+                self.buffers
+                    .get(id)
+                    .and_then(|b| b.file_path())
+                    .and_then(|p| p.canonicalize().ok())
+                    .is_some_and(|p| p == path)
+            })
+            .copied()
+    }
+
+    /// Resolves a relative path from the root folder
+    fn resolve(&self, path: &Path) -> PathBuf {
+        self.root.join(path)
+    }
+
+    /// Shortens a path inside the root folder to be relative to it, for display
+    fn relative<'a>(&self, path: &'a Path) -> &'a Path {
+        path.strip_prefix(&self.root).unwrap_or(path)
+    }
+
+    fn open_folder(&mut self, path: PathBuf) -> io::Result<()> {
+        // A folder that can't be read keeps the current root
+        let root = match self
+            .resolve(&path)
+            .canonicalize()
+            .and_then(|root| fs::read_dir(&root).map(|_| root))
+        {
+            Ok(root) => root,
+            Err(e) => {
+                self.show_message(format!("Failed to open {} : {e}", path.display()));
+                return Ok(());
+            }
+        };
+        // Relative paths typed in the editor (file finder, prompts) are resolved from the opened folder
+        self.root = root.clone();
+        // The language servers work in it too
+        for language in self.language_servers.set_root(root.clone()) {
+            self.clear_server_diagnostics(&language);
+        }
+
+        if let Some(id) = self.explorer {
+            if let Some(buf) = self.buffers.get_mut(&id) {
+                buf.set_explorer(Explorer::new(root));
+            }
+            self.activate_buffer(id);
+            return Ok(());
+        }
+
+        let mut buf = Buffer::new(
+            PathBuf::new(),
+            0,
+            0,
+            self.explorer_width(),
+            self.terminal.height,
+            false,
+            false,
+            BufferLogic::Explorer,
+            "",
+            self.sender_copy.clone(),
+        )?;
+        buf.set_file_path(None);
+        buf.set_explorer(Explorer::new(root));
+
+        let id = self.add_buffer(buf);
+        self.explorer = Some(id);
+        self.layout_buffers();
+        self.activate_buffer(id);
+
+        Ok(())
+    }
+
+    fn toggle_explorer(&mut self) -> io::Result<()> {
+        match self.explorer.take() {
+            Some(id) => {
+                self.remove_focus(id);
+                self.buffers.remove(&id);
+                self.layout_buffers();
+                Ok(())
+            }
+            None => self.open_folder(self.root.clone()),
+        }
+    }
+
+    /// Moves the focus to the explorer, or back to the last editor buffer if it already has focus
+    fn focus_explorer(&mut self) -> io::Result<()> {
+        let id = match self.explorer {
+            Some(id) => id,
+            None => return self.open_folder(self.root.clone()),
+        };
+
+        if self.focused_buffer_id() == Some(id) {
+            let previous = self
+                .focus_stack
+                .iter()
+                .rev()
+                .find(|i| **i != id && self.buffers.contains_key(i))
+                .copied();
+            if let Some(previous) = previous {
+                self.activate_buffer(previous);
+            }
+        } else {
+            self.activate_buffer(id);
+        }
+
+        Ok(())
+    }
+
+    fn handle_explorer_event(&mut self, id: Uuid, event: ExplorerEvent) -> io::Result<()> {
+        let paused = |event| EditorEvent::Explorer { id, event };
+
+        match event {
+            ExplorerEvent::NewFile { .. } => {
+                self.open_input_box("New File", "", 48, paused(event))?;
+            }
+            ExplorerEvent::NewFolder { .. } => {
+                self.open_input_box("New Folder", "", 48, paused(event))?;
+            }
+            ExplorerEvent::Rename { ref path } => {
+                let name = path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                self.open_input_box("Rename", &name, 48, paused(event))?;
+            }
+            ExplorerEvent::Delete { ref path } => {
+                let name = path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let items = vec![
+                    SelectorItem::new("Cancel", None),
+                    SelectorItem::new(format!("Delete {name}"), None).with_value("delete"),
+                ];
+                self.open_selector("Delete?", Selector::new(items), 5, paused(event))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Runs a file operation from the explorer once the user answered its prompt
+    fn run_explorer_event(&mut self, id: Uuid, event: ExplorerEvent, answer: String) {
+        let answer = answer.trim();
+        if answer.is_empty() {
+            return;
+        }
+
+        let result = match event {
+            ExplorerEvent::NewFile { dir } => {
+                let path = dir.join(answer);
+                let created = match path.parent() {
+                    Some(parent) => fs::create_dir_all(parent),
+                    None => Ok(()),
+                }
+                .and_then(|_| {
+                    fs::OpenOptions::new()
+                        .write(true)
+                        .create_new(true)
+                        .open(&path)
+                });
+
+                created.map(|_| {
+                    self.sender_copy
+                        .send(EditorEvent::OpenFile(path.clone()))
+                        .expect("Failed to send a msg to the editor");
+                    path
+                })
+            }
+            ExplorerEvent::NewFolder { dir } => {
+                let path = dir.join(answer);
+                fs::create_dir_all(&path).map(|_| path)
+            }
+            ExplorerEvent::Rename { path } => {
+                let new_path = path.with_file_name(answer);
+                if new_path.exists() {
+                    Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!("{} already exists", new_path.display()),
+                    ))
+                } else {
+                    fs::rename(&path, &new_path).map(|_| {
+                        // Keep open buffers pointing at the renamed files
+                        for buf in self.buffers.values_mut() {
+                            if let Some(file_path) = buf.file_path() {
+                                if let Ok(rest) = file_path.strip_prefix(&path) {
+                                    buf.set_file_path(Some(new_path.join(rest)));
+                                }
+                            }
+                        }
+                        new_path
+                    })
+                }
+            }
+            ExplorerEvent::Delete { path } => {
+                if answer != "delete" {
+                    return;
+                }
+                if path.is_dir() {
+                    fs::remove_dir_all(&path).map(|_| path)
+                } else {
+                    fs::remove_file(&path).map(|_| path)
+                }
+            }
+        };
+
+        match result {
+            Ok(path) => {
+                if let Some(buf) = self.buffers.get_mut(&id) {
+                    buf.refresh_explorer(Some(path));
+                }
+                self.update_watched_files();
+            }
+            Err(e) => self.show_message(format!("Error: {e}")),
+        }
+    }
+
+    /// Quits, or asks what to do with unsaved changes first
+    fn request_quit(&mut self) -> io::Result<()> {
+        let tabs = self.tabs();
+        let dirty: Vec<&Buffer> = tabs
+            .iter()
+            .filter_map(|id| self.buffers.get(id))
+            .filter(|b| b.is_modified())
+            .collect();
+
+        if dirty.is_empty() {
+            self.should_quit = true;
+            return Ok(());
+        }
+
+        // The prompt is already open
+        if self
+            .paused_events
+            .iter()
+            .any(|e| matches!(e.event, EditorEvent::Command(Command::Quit)))
+        {
+            return Ok(());
+        }
+
+        let count = format!("{} unsaved", dirty.len());
+        let mut items = vec![
+            SelectorItem::new("Save all and quit", Some(count.clone())).with_value("save"),
+            SelectorItem::new("Discard all and quit", Some(count)).with_value("discard"),
+            SelectorItem::new("Cancel", None).with_value("cancel"),
+        ];
+        // Choosing one of the buffers cancels the quit and shows it
+        items.extend(dirty.iter().map(|buf| {
+            let detail = buf.file_path().map(|p| p.display().to_string());
+            SelectorItem::new(format!("● {}", buf.display_name()), detail)
+                .with_value(buf.id.to_string())
+        }));
+
+        let height = selector_height(items.len());
+        self.open_selector(
+            "Unsaved changes",
+            Selector::new(items),
+            height,
+            EditorEvent::Command(Command::Quit),
+        )?;
+
+        Ok(())
+    }
+
+    fn open_command_palette(&mut self) -> io::Result<()> {
+        let items: Vec<SelectorItem> = Command::ALL
+            .iter()
+            .map(|c| SelectorItem::new(c.name(), c.key_binding().map(|k| k.to_string())))
+            .collect();
+
+        let height = selector_height(items.len());
+
+        self.open_selector(
+            "Commands",
+            Selector::new(items),
+            height,
+            EditorEvent::Command(Command::OpenCommandPalette),
+        )?;
+
+        Ok(())
+    }
+
+    fn open_file_finder(&mut self) -> io::Result<()> {
+        self.stop_file_finder();
+
+        let root = self.root.clone();
+
+        // Recently used files are listed first, the rest stream in from the indexer
+        let recent: Vec<PathBuf> = self
+            .recent_files
+            .iter()
+            .filter(|p| root.join(p).is_file())
+            .cloned()
+            .collect();
+        let items = recent.iter().map(|p| Self::file_item(p)).collect();
+
+        let selector_id = self.open_selector(
+            "Files",
+            Selector::new(items),
+            u16::MAX,
+            EditorEvent::Command(Command::FindFile),
+        )?;
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        file_finder::spawn_indexer(
+            root,
+            &self.settings.file_finder_exclude,
+            selector_id,
+            self.sender_copy.clone(),
+            cancel.clone(),
+        );
+
+        self.file_finder = Some(FileFinder {
+            selector_id,
+            cancel,
+            listed: recent.into_iter().collect(),
+        });
+
+        Ok(())
+    }
+
+    fn stop_file_finder(&mut self) {
+        if let Some(finder) = self.file_finder.take() {
+            finder.cancel.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn add_found_files(&mut self, selector_id: Uuid, files: Vec<PathBuf>) {
+        let finder = match &mut self.file_finder {
+            Some(finder) if finder.selector_id == selector_id => finder,
+            // The finder was closed while the indexer was still running
+            _ => return,
+        };
+
+        let items = files
+            .into_iter()
+            .filter(|p| finder.listed.insert(p.clone()))
+            .map(|p| Self::file_item(&p))
+            .collect();
+
+        if let Some(selector) = self
+            .overlays
+            .get_mut(&selector_id)
+            .and_then(|b| b.selector.as_mut())
+        {
+            selector.extend(items);
+        }
+    }
+
+    fn file_item(path: &std::path::Path) -> SelectorItem {
+        let path = path.to_string_lossy();
+        SelectorItem::new(path.clone(), None).with_value(path)
+    }
+
+    fn add_recent_file(&mut self, path: &std::path::Path) {
+        // Paths from the finder are relative to the root, so compare them as such
+        let path = self.relative(path).to_path_buf();
+
+        self.recent_files.retain(|p| *p != path);
+        self.recent_files.insert(0, path);
+    }
+
+    /// Opens a centered input box overlay with `initial` text, at most `width` columns wide, and
+    /// pauses `event` until the input is submitted. Returns the id of the overlay.
+    fn open_input_box(
+        &mut self,
+        title: &str,
+        initial: &str,
+        width: u16,
+        event: EditorEvent,
+    ) -> io::Result<Uuid> {
+        // Pause this event
+        let paused_event_id = Uuid::new_v4();
+        self.paused_events.push(PausedEvent {
+            id: paused_event_id,
+            event,
+        });
+
+        // Open up a new overlay
+        let placement = OverlayPlacement::InputBox { width };
+        let (x, y, width, height) = self.overlay_rect(placement);
+
+        let mut overlay = Buffer::new(
+            PathBuf::from(format!("{title}:")),
+            x,
+            y,
+            width,
+            height,
+            false,
+            true,
+            BufferLogic::InputBox,
+            title,
+            self.sender_copy.clone(),
+        )?;
+        overlay.set_paused_event_id(paused_event_id);
+        overlay.set_contents(initial);
+        let id = self.add_overlay(overlay);
+        self.overlay_placements.insert(id, placement);
+
+        // Activate that overlay
+        self.activate_overlay(id);
+
+        Ok(id)
+    }
+
+    /// Returns the x, y, width and height of an overlay placed in the current terminal
+    fn overlay_rect(&self, placement: OverlayPlacement) -> (u16, u16, u16, u16) {
+        let (terminal_width, terminal_height) = (self.terminal.width, self.terminal.height);
+
+        match placement {
+            OverlayPlacement::InputBox { width } => {
+                let width = std::cmp::min(width, terminal_width);
+                let height = std::cmp::min(3, terminal_height);
+                let x = (terminal_width / 2).saturating_sub(width / 2);
+                let y = (terminal_height / 2).saturating_sub(height);
+                (x, y, width, height)
+            }
+            OverlayPlacement::Selector { height } => {
+                let width = std::cmp::min(80, terminal_width.saturating_sub(4));
+                let height = std::cmp::min(height, 20);
+                let height = std::cmp::min(height, terminal_height.saturating_sub(4));
+                let x = (terminal_width / 2).saturating_sub(width / 2);
+                let y = (terminal_height / 4).saturating_sub(height / 4);
+                (x, y, width, height)
+            }
+        }
+    }
+
+    /// Moves overlays back into place after the terminal was resized
+    fn layout_overlays(&mut self) {
+        for (id, placement) in self.overlay_placements.iter() {
+            let (x, y, width, height) = self.overlay_rect(*placement);
+            if let Some(overlay) = self.overlays.get_mut(id) {
+                overlay.move_to(x, y);
+                overlay.resize(width, height);
+            }
+        }
+    }
+
+    /// Opens a centered selector overlay, at most `height` rows tall, and pauses `event` until an
+    /// item is chosen. Returns the id of the overlay.
+    fn open_selector(
+        &mut self,
+        title: &str,
+        selector: Selector,
+        height: u16,
+        event: EditorEvent,
+    ) -> io::Result<Uuid> {
+        // Pause this event
+        let paused_event_id = Uuid::new_v4();
+        self.paused_events.push(PausedEvent {
+            id: paused_event_id,
+            event,
+        });
+
+        // Open up a new overlay
+        let placement = OverlayPlacement::Selector { height };
+        let (x, y, width, height) = self.overlay_rect(placement);
+
+        let mut overlay = Buffer::new(
+            PathBuf::from(format!("{title}:")),
+            x,
+            y,
+            width,
+            height,
+            false,
+            true,
+            BufferLogic::Selector,
+            title,
+            self.sender_copy.clone(),
+        )?;
+        overlay.set_paused_event_id(paused_event_id);
+        overlay.set_selector(selector);
+        let id = self.add_overlay(overlay);
+        self.overlay_placements.insert(id, placement);
+
+        // Activate that overlay
+        self.activate_overlay(id);
+
+        Ok(id)
+    }
+
+    pub fn handle_buffer_event(&mut self, event: BufferEvent) -> io::Result<()> {
+        match event {
+            BufferEvent::Save { id } => {
+                self.save_buffer(id)?;
+            }
+            BufferEvent::SaveAs { id } => {
+                // Start from the current path, so saving next to it only takes a new name
+                let initial = self
+                    .buffers
+                    .get(&id)
+                    .and_then(|b| b.file_path())
+                    .map(|p| self.relative(&p).display().to_string())
+                    .unwrap_or_default();
+
+                let overlay =
+                    self.open_input_box("Save As", &initial, 80, EditorEvent::Buffer(event))?;
+                if let Some(overlay) = self.overlays.get_mut(&overlay) {
+                    overlay.set_path_completion(Some(self.root.clone()));
+                }
+            }
+            BufferEvent::RequestClose { id } => {
+                let buf = match self.buffers.get(&id) {
+                    Some(buf) => buf,
+                    None => return Ok(()),
+                };
+
+                // Other views keep the document open, so nothing is lost
+                if buf.is_modified() && !self.has_other_views(id) {
+                    let title = format!("Unsaved changes in {}", buf.display_name());
+                    let items = vec![
+                        SelectorItem::new("Save", None).with_value("save"),
+                        SelectorItem::new("Discard", None).with_value("discard"),
+                        SelectorItem::new("Cancel", None).with_value("cancel"),
+                    ];
+                    self.open_selector(&title, Selector::new(items), 6, EditorEvent::Buffer(event))?;
+                } else {
+                    self.close_buffer(id);
+                }
+            }
+            BufferEvent::Close { id, is_overlay } => {
+                if is_overlay {
+                    self.close_overlay(id);
+                } else {
+                    self.close_buffer(id);
+                }
+            }
+            BufferEvent::ResumeEvent {
+                paused_event_id,
+                result,
+            } => {
+                // Look through our paused events and match this id
+                if let Some(i) = self
+                    .paused_events
+                    .iter()
+                    .position(|e| e.id == paused_event_id)
+                {
+                    let event = self.paused_events.remove(i);
+                    match event.event {
+                        EditorEvent::Buffer(BufferEvent::SaveAs { id }) => {
+                            self.save_buffer_as(id, result)?;
+                        }
+                        EditorEvent::Buffer(BufferEvent::RequestClose { id }) => {
+                            match result.as_str() {
+                                "save" => {
+                                    self.save_buffer(id)?;
+                                    // Saving can fail or need a name first, in which case we keep it
+                                    if self.buffers.get(&id).is_some_and(|b| !b.is_modified()) {
+                                        self.close_buffer(id);
+                                    }
+                                }
+                                "discard" => self.close_buffer(id),
+                                _ => (),
+                            }
+                        }
+                        EditorEvent::Command(Command::OpenCommandPalette) => {
+                            // Run the command after the palette has closed, so it acts on the buffer
+                            // that was focused before the palette was opened
+                            if let Some(command) = Command::from_name(&result) {
+                                self.sender_copy
+                                    .send(EditorEvent::Command(command))
+                                    .expect("Failed to send a msg to the editor");
+                            }
+                        }
+                        EditorEvent::Explorer { id, event } => {
+                            self.run_explorer_event(id, event, result);
+                        }
+                        EditorEvent::RecoverSwap { id } => self.resolve_swap(id, &result)?,
+                        EditorEvent::FoldToLevel { id } => self.fold_to_level(id, &result),
+                        EditorEvent::RenameSymbol { id } => self.rename_symbol(id, &result),
+                        EditorEvent::Complete { id, word_start } => {
+                            if let Some(buf) = self.buffers.get_mut(&id) {
+                                buf.complete_word(word_start, &result);
+                            }
+                        }
+                        EditorEvent::Command(
+                            Command::FindReferences | Command::ListDiagnostics,
+                        ) => self.open_location(&result),
+                        EditorEvent::FileChanged(path) => self.resolve_file_change(path, &result)?,
+                        EditorEvent::SaveTo { id, path } => match result.as_str() {
+                            "create" => {
+                                let parent = path.parent().map(|p| p.to_path_buf());
+                                match parent.map(fs::create_dir_all).unwrap_or(Ok(())) {
+                                    Ok(()) => self.save_to(id, path)?,
+                                    Err(e) => self.show_message(format!("Error: {e}")),
+                                }
+                            }
+                            "overwrite" => self.write_to(id, path)?,
+                            _ => (),
+                        },
+                        EditorEvent::Buffer(BufferEvent::Save { id }) if result == "overwrite" => {
+                            self.write_buffer(id);
+                        }
+                        EditorEvent::Command(Command::ReloadFile) => {
+                            if let Ok(id) = Uuid::parse_str(&result) {
+                                self.reload_buffer(id);
+                            }
+                        }
+                        EditorEvent::Command(Command::Quit) => match result.as_str() {
+                            "save" => {
+                                let dirty: Vec<Uuid> = self
+                                    .tabs()
+                                    .iter()
+                                    .filter(|id| {
+                                        self.buffers.get(id).is_some_and(|b| b.is_modified())
+                                    })
+                                    .copied()
+                                    .collect();
+                                for id in dirty {
+                                    self.save_buffer(id)?;
+                                }
+
+                                // Saving can fail or need a name first, in which case we stay open
+                                if self.buffers.values().any(|b| b.is_modified()) {
+                                    self.show_message(
+                                        "Some buffers could not be saved, not quitting",
+                                    );
+                                } else {
+                                    self.should_quit = true;
+                                }
+                            }
+                            "discard" => self.should_quit = true,
+                            "cancel" => (),
+                            id => {
+                                if let Ok(id) = Uuid::parse_str(id) {
+                                    self.activate_buffer(id);
+                                }
+                            }
+                        },
+                        EditorEvent::Command(Command::SwitchBuffer) => {
+                            if let Ok(id) = Uuid::parse_str(&result) {
+                                if self.buffers.contains_key(&id) {
+                                    self.activate_buffer(id);
+                                }
+                            }
+                        }
+                        EditorEvent::Command(Command::FindFile) => {
+                            self.sender_copy
+                                .send(EditorEvent::OpenFile(PathBuf::from(result)))
+                                .expect("Failed to send a msg to the editor");
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            BufferEvent::CancelEvent { paused_event_id } => {
+                // Look through our paused events and match this id
+                if let Some(i) = self
+                    .paused_events
+                    .iter()
+                    .position(|e| e.id == paused_event_id)
+                {
+                    self.paused_events.remove(i);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn save_buffer(&mut self, id: Uuid) -> io::Result<()> {
+        let Some(buf) = self.buffers.get(&id) else {
+            return Ok(());
+        };
+
+        if buf.is_read_only() {
+            let message = buf.read_only_message();
+            self.show_message(message);
+            return Ok(());
+        }
+
+        if buf.file_path().is_none() {
+            // If we try to save a buffer without a name...
+            return self.handle_buffer_event(BufferEvent::SaveAs { id });
+        }
+
+        // Don't silently overwrite what another program wrote since we loaded the file
+        if buf.changed_on_disk() {
+            let title = format!("{} changed on disk", buf.display_name());
+            let items = vec![
+                SelectorItem::new("Overwrite", Some("Replace it with your version".to_string()))
+                    .with_value("overwrite"),
+                SelectorItem::new("Cancel", None).with_value("cancel"),
+            ];
+            self.open_selector(
+                &title,
+                Selector::new(items),
+                5,
+                EditorEvent::Buffer(BufferEvent::Save { id }),
+            )?;
+            return Ok(());
+        }
+
+        self.write_buffer(id);
+        Ok(())
+    }
+
+    /// Writes buffer `id` to its file, without checking if it changed on disk
+    fn write_buffer(&mut self, id: Uuid) {
+        let Some((file_path, contents)) = self
+            .buffers
+            .get(&id)
+            .and_then(|buf| Some((buf.file_path()?, buf.data.to_string())))
+        else {
+            return;
+        };
+
+        // A failed save keeps the buffer dirty, and the editor running
+        match save::write_atomic(&file_path, &contents, &self.settings.backup) {
+            Ok(()) => {
+                if let Some(buf) = self.buffers.get_mut(&id) {
+                    buf.mark_saved();
+                    buf.update_disk_modified();
+                }
+                self.language_servers.saved(&file_path);
+                self.remove_stale_swap_files();
+                self.update_watched_files();
+            }
+            Err(e) => self.show_message(format!("Failed to save {} : {e}", file_path.display())),
+        }
+    }
+
+    /// Saves buffer `id` under the path typed in the Save As dialog. Relative paths start from the
+    /// root folder.
+    fn save_buffer_as(&mut self, id: Uuid, input: String) -> io::Result<()> {
+        let input = input.trim();
+        if input.is_empty() {
+            return Ok(());
+        }
+
+        let path = self.resolve(Path::new(input));
+        if input.ends_with('/') || path.is_dir() {
+            self.show_message(format!("{input} is a folder"));
+            return Ok(());
+        }
+
+        self.save_to(id, path)
+    }
+
+    /// Saves buffer `id` to `path`, asking before creating missing folders or overwriting another
+    /// file
+    fn save_to(&mut self, id: Uuid, path: PathBuf) -> io::Result<()> {
+        let Some(buf) = self.buffers.get(&id) else {
+            return Ok(());
+        };
+
+        // Two documents saving to the same file would overwrite each other
+        if let Some(other) = self.find_buffer_by_path(&path) {
+            if self.buffers.get(&other).is_some_and(|o| !o.shares_document(buf)) {
+                self.show_message(format!("{} is open in another buffer", path.display()));
+                return Ok(());
+            }
+        }
+
+        let paused = EditorEvent::SaveTo {
+            id,
+            path: path.clone(),
+        };
+
+        if let Some(parent) = path.parent().filter(|p| !p.exists()) {
+            let title = format!("Create {}?", parent.display());
+            let items = vec![
+                SelectorItem::new("Create folder", None).with_value("create"),
+                SelectorItem::new("Cancel", None).with_value("cancel"),
+            ];
+            self.open_selector(&title, Selector::new(items), 5, paused)?;
+            return Ok(());
+        }
+
+        let is_own_file = buf.file_path().is_some_and(|p| Self::same_file(&p, &path));
+        if path.exists() && !is_own_file {
+            let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            let title = format!("{name} already exists");
+            let items = vec![
+                SelectorItem::new("Overwrite", Some(path.display().to_string()))
+                    .with_value("overwrite"),
+                SelectorItem::new("Cancel", None).with_value("cancel"),
+            ];
+            self.open_selector(&title, Selector::new(items), 5, paused)?;
+            return Ok(());
+        }
+
+        self.write_to(id, path)
+    }
+
+    /// Points buffer `id` at `path` and saves it there
+    fn write_to(&mut self, id: Uuid, path: PathBuf) -> io::Result<()> {
+        if let Some(buf) = self.buffers.get_mut(&id) {
+            let is_own_file = buf.file_path().is_some_and(|p| Self::same_file(&p, &path));
+
+            if buf.set_path(path.clone()).is_err() {
+                self.show_message(format!("Can't save to {}", path.display()));
+                return Ok(());
+            }
+            // Another file was chosen on purpose, so it is written without checking it
+            if !is_own_file {
+                buf.forget_disk_modified();
+            }
+        }
+        self.update_language(id);
+
+        self.save_buffer(id)
+    }
+
+    fn same_file(a: &std::path::Path, b: &std::path::Path) -> bool {
+        a == b
+            || matches!(
+                (a.canonicalize(), b.canonicalize()),
+                (Ok(a), Ok(b)) if a == b
+            )
+    }
+
+    /// Writes the unsaved changes of every document to its swap file, unless they were already
+    /// written
+    fn write_swap_files(&mut self) {
+        self.remove_stale_swap_files();
+
+        for id in self.tabs() {
+            let Some(buf) = self.buffers.get(&id).filter(|b| b.is_modified()) else {
+                continue;
+            };
+            // There is no file to recover a buffer without a name into
+            let Some(path) = buf.file_path() else {
+                continue;
+            };
+
+            let revision = buf.data.revision();
+            if self.swapped.get(&path) == Some(&revision) {
+                continue;
+            }
+
+            let (column, line) = buf.cursor_xy_relative();
+            self.swap_files.write(SwapFile {
+                path: path.clone(),
+                line,
+                column,
+                contents: buf.data.to_string(),
+            });
+            self.swapped.insert(path, revision);
+        }
+    }
+
+    /// Removes the swap files of documents that were saved, closed or renamed since they were
+    /// written
+    fn remove_stale_swap_files(&mut self) {
+        let dirty: HashSet<PathBuf> = self
+            .buffers
+            .values()
+            .filter(|b| b.is_modified())
+            .filter_map(|b| b.file_path())
+            .collect();
+
+        let swap_files = &self.swap_files;
+        self.swapped.retain(|path, _| {
+            let keep = dirty.contains(path);
+            if !keep {
+                swap_files.remove(path);
+            }
+            keep
+        });
+    }
+
+    /// Asks what to do with the swap file left for buffer `id`'s file by a session that didn't exit
+    /// cleanly, if it is newer than the file
+    fn offer_swap_recovery(&mut self, id: Uuid) -> io::Result<()> {
+        let Some(buf) = self.buffers.get(&id) else {
+            return Ok(());
+        };
+        let Some(path) = buf.file_path() else {
+            return Ok(());
+        };
+        // Written by this session, so nothing was lost
+        if self.swapped.contains_key(&path) {
+            return Ok(());
+        }
+        let Some(swap) = self.swap_files.read_newer(&path) else {
+            return Ok(());
+        };
+
+        if swap.contents == buf.data.to_string() {
+            self.swap_files.remove(&path);
+            return Ok(());
+        }
+
+        let title = format!("Unsaved changes found for {}", buf.display_name());
+        let items = vec![
+            SelectorItem::new("Recover", Some("Restore the unsaved changes".to_string()))
+                .with_value("recover"),
+            SelectorItem::new("Discard", Some("Delete the swap file".to_string()))
+                .with_value("discard"),
+            SelectorItem::new("Diff", Some("Compare them with the file".to_string()))
+                .with_value("diff"),
+        ];
+        self.open_selector(&title, Selector::new(items), 6, EditorEvent::RecoverSwap { id })?;
+
+        Ok(())
+    }
+
+    /// Recovers, discards or shows the swap file of buffer `id`'s file once the user answered
+    fn resolve_swap(&mut self, id: Uuid, answer: &str) -> io::Result<()> {
+        let Some(buf) = self.buffers.get(&id) else {
+            return Ok(());
+        };
+        let Some(path) = buf.file_path() else {
+            return Ok(());
+        };
+
+        if answer == "discard" {
+            self.swap_files.remove(&path);
+            return Ok(());
+        }
+        if answer != "recover" && answer != "diff" {
+            return Ok(());
+        }
+
+        let Some(swap) = self.swap_files.read_newer(&path) else {
+            self.show_message(format!("The swap file of {} is gone", path.display()));
+            return Ok(());
+        };
+
+        if answer == "recover" {
+            if let Some(buf) = self.buffers.get_mut(&id) {
+                buf.replace_text(swap.contents, true);
+                buf.goto(swap.line, swap.column);
+                // The swap file now matches the buffer, and is removed with it
+                self.swapped.insert(path, buf.data.revision());
+            }
+            self.activate_buffer(id);
+        } else {
+            let name = buf.display_name();
+            let diff = diff::unified(
+                &buf.data.to_string(),
+                &swap.contents,
+                &name,
+                &format!("{name} (unsaved changes)"),
+            );
+            self.open_scratch_buffer(&format!("{name}.diff"), &diff)?;
+            self.show_message(format!("Open {name} again to recover or discard the changes"));
+        }
+
+        Ok(())
+    }
+
+    /// Watches the files of all open documents for changes made by other programs
+    fn update_watched_files(&mut self) {
+        let files: HashSet<PathBuf> = self
+            .buffers
+            .values()
+            .filter(|b| b.logic == BufferLogic::Editor)
+            .filter_map(|b| b.file_path())
+            .collect();
+
+        for path in self.watched.difference(&files) {
+            self.watcher.unwatch(path.clone());
+        }
+        for path in files.difference(&self.watched) {
+            self.watcher.watch(path.clone());
+        }
+        self.watched = files;
+    }
+
+    /// Reloads a buffer whose file was changed by another program, or asks first if it has unsaved
+    /// changes
+    fn handle_file_changed(&mut self, path: PathBuf) -> io::Result<()> {
+        let Some(id) = self.find_buffer_by_path(&path) else {
+            return Ok(());
+        };
+        let Some(buf) = self.buffers.get(&id) else {
+            return Ok(());
+        };
+
+        // Our own saves, and changes that were already handled
+        if !buf.changed_on_disk() {
+            return Ok(());
+        }
+
+        if !buf.is_modified() {
+            self.reload_buffer(id);
+            return Ok(());
+        }
+
+        // The prompt is already open
+        if self
+            .paused_events
+            .iter()
+            .any(|e| matches!(&e.event, EditorEvent::FileChanged(p) if *p == path))
+        {
+            return Ok(());
+        }
+
+        let title = format!("{} changed on disk", buf.display_name());
+        let items = vec![
+            SelectorItem::new("Reload", Some("Discard your changes".to_string()))
+                .with_value("reload"),
+            SelectorItem::new("Keep mine", Some("Keep editing your version".to_string()))
+                .with_value("keep"),
+            SelectorItem::new("Diff", Some("Compare your version with the file".to_string()))
+                .with_value("diff"),
+        ];
+        self.open_selector(&title, Selector::new(items), 6, EditorEvent::FileChanged(path))?;
+
+        Ok(())
+    }
+
+    /// Reloads, keeps or compares a buffer whose file changed on disk once the user answered
+    fn resolve_file_change(&mut self, path: PathBuf, answer: &str) -> io::Result<()> {
+        let Some(id) = self.find_buffer_by_path(&path) else {
+            return Ok(());
+        };
+
+        match answer {
+            "reload" => self.reload_buffer(id),
+            "keep" => {
+                // The version on disk was seen, so saving overwrites it without asking again
+                if let Some(buf) = self.buffers.get_mut(&id) {
+                    buf.update_disk_modified();
+                }
+            }
+            "diff" => {
+                let Some(buf) = self.buffers.get(&id) else {
+                    return Ok(());
+                };
+                let name = buf.display_name();
+                let mine = buf.data.to_string();
+
+                match fs::read_to_string(&path) {
+                    Ok(theirs) => {
+                        let diff = diff::unified(
+                            &theirs,
+                            &mine,
+                            &format!("{name} (on disk)"),
+                            &format!("{name} (yours)"),
+                        );
+                        self.open_scratch_buffer(&format!("{name}.diff"), &diff)?;
+                        self.show_message("Run Reload File to take the version on disk");
+                    }
+                    Err(e) => self.show_message(format!("Failed to read {} : {e}", path.display())),
+                }
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// Reloads buffer `id` from its file, asking first if that would lose unsaved changes
+    fn request_reload(&mut self, id: Uuid) -> io::Result<()> {
+        let Some(buf) = self.buffers.get(&id).filter(|b| b.logic == BufferLogic::Editor) else {
+            return Ok(());
+        };
+        if buf.file_path().is_none() {
+            return Ok(());
+        }
+
+        if !buf.is_modified() {
+            self.reload_buffer(id);
+            return Ok(());
+        }
+
+        let title = format!("Discard unsaved changes in {}?", buf.display_name());
+        let items = vec![
+            SelectorItem::new("Reload", Some("Discard your changes".to_string()))
+                .with_value(id.to_string()),
+            SelectorItem::new("Cancel", None).with_value("cancel"),
+        ];
+        self.open_selector(
+            &title,
+            Selector::new(items),
+            5,
+            EditorEvent::Command(Command::ReloadFile),
+        )?;
+
+        Ok(())
+    }
+
+    /// Replaces the text of buffer `id` with its file on disk, keeping the cursor where it was
+    fn reload_buffer(&mut self, id: Uuid) {
+        let Some(buf) = self.buffers.get_mut(&id) else {
+            return;
+        };
+        let Some(path) = buf.file_path() else {
+            return;
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                buf.replace_text(text, false);
+                buf.update_disk_modified();
+                self.remove_stale_swap_files();
+            }
+            Err(e) => self.show_message(format!("Failed to reload {} : {e}", path.display())),
+        }
+    }
+
+    /// Asks for the level to fold buffer `id` to, see `Buffer::fold_to_level`
+    fn ask_fold_level(&mut self, id: Uuid) -> io::Result<()> {
+        self.open_input_box("Fold to Level", "1", 32, EditorEvent::FoldToLevel { id })?;
+        Ok(())
+    }
+
+    fn fold_to_level(&mut self, id: Uuid, level: &str) {
+        let Some(buf) = self.buffers.get_mut(&id) else {
+            return;
+        };
+
+        match level.trim().parse::<usize>() {
+            Ok(level) if level > 0 => buf.fold_to_level(level),
+            _ => self.show_message(format!("Not a fold level: {level}, levels start at 1")),
+        }
+    }
+
+    /// Sends the edits of the open documents to their language servers, and closes the documents
+    /// that are not open anymore
+    fn sync_language_servers(&mut self) {
+        let mut open = HashSet::new();
+        let mut error = None;
+
+        for buf in self
+            .buffers
+            .values()
+            .filter(|b| b.logic == BufferLogic::Editor)
+        {
+            let Some(path) = buf.file_path() else {
+                continue;
+            };
+            // Views of the same document
+            if !open.insert(path.clone()) {
+                continue;
+            }
+            if let Err(e) = self.language_servers.sync(&path, &buf.data.document()) {
+                error = Some(e);
+            }
+        }
+        self.language_servers.close_others(&open);
+
+        if let Some(error) = error {
+            self.show_message(error);
+        }
+    }
+
+    /// Sends a request about the cursor position of buffer `id` to its language server
+    fn request_at_cursor(&mut self, id: Uuid, request: Request, method: &str, params: Value) {
+        self.sync_language_servers();
+        let Some(buf) = self.buffers.get_mut(&id) else {
+            return;
+        };
+        buf.sync();
+        let (path, pos) = (buf.file_path().unwrap_or_default(), buf.data.cursor());
+
+        let result = self
+            .language_servers
+            .request(&path, Some(pos), request, method, params);
+        if let Err(e) = result {
+            self.show_message(e);
+        }
+    }
+
+    fn format_document(&mut self, id: Uuid) {
+        self.sync_language_servers();
+        let Some(buf) = self.buffers.get(&id) else {
+            return;
+        };
+        let path = buf.file_path().unwrap_or_default();
+        let request = Request::Formatting {
+            path: path.clone(),
+            revision: buf.data.revision(),
+        };
+
+        let params = json!({ "options": { "tabSize": 4, "insertSpaces": true } });
+        let result =
+            self.language_servers
+                .request(&path, None, request, "textDocument/formatting", params);
+        if let Err(e) = result {
+            self.show_message(e);
+        }
+    }
+
+    fn ask_new_name(&mut self, id: Uuid) -> io::Result<()> {
+        let Some(buf) = self.buffers.get(&id) else {
+            return Ok(());
+        };
+        if buf.is_read_only() {
+            let message = buf.read_only_message();
+            self.show_message(message);
+            return Ok(());
+        }
+
+        let word = buf.word_at_cursor();
+        self.open_input_box("Rename Symbol", &word, 40, EditorEvent::RenameSymbol { id })?;
+        Ok(())
+    }
+
+    fn rename_symbol(&mut self, id: Uuid, name: &str) {
+        let name = name.trim();
+        if name.is_empty() {
+            return;
+        }
+
+        let Some(buf) = self.buffers.get(&id) else {
+            return;
+        };
+        let request = Request::Rename {
+            path: buf.file_path().unwrap_or_default(),
+            revision: buf.data.revision(),
+        };
+
+        let params = json!({ "newName": name });
+        self.request_at_cursor(id, request, "textDocument/rename", params);
+    }
+
+    /// Removes the diagnostics the server of `language` reported
+    fn clear_server_diagnostics(&mut self, language: &str) {
+        let source = lsp::diagnostic_source(language);
+        for buf in self.buffers.values() {
+            buf.data.document_mut().set_diagnostics(&source, vec![]);
+        }
+    }
+
+    fn handle_server_message(
+        &mut self,
+        language: &str,
+        generation: usize,
+        message: Value,
+    ) -> io::Result<()> {
+        match self
+            .language_servers
+            .handle_message(language, generation, message)
+        {
+            Some(ServerEvent::Message(message)) => self.show_message(message),
+            Some(ServerEvent::Diagnostics {
+                path,
+                source,
+                diagnostics,
+            }) => {
+                if let Some(buf) = self
+                    .find_buffer_by_path(&path)
+                    .and_then(|id| self.buffers.get(&id))
+                {
+                    buf.data
+                        .document_mut()
+                        .set_diagnostics(&source, diagnostics);
+                }
+            }
+            Some(ServerEvent::Response {
+                request,
+                result,
+                encoding,
+            }) => match request {
+                Request::Hover => self.show_hover(&result),
+                Request::Definition | Request::References => {
+                    self.show_locations(request, &result, encoding)?
+                }
+                Request::Rename { path, revision } => {
+                    if self.edited_since(&path, revision) {
+                        self.show_message("The buffer was edited while the symbol was renamed");
+                    } else {
+                        self.apply_workspace_edit(&result, encoding);
+                    }
+                }
+                Request::Formatting { path, revision } => {
+                    if self.edited_since(&path, revision) {
+                        self.show_message("The buffer was edited while it was being formatted");
+                    } else {
+                        self.apply_text_edits(&path, &result, encoding);
+                    }
+                }
+                Request::Completion {
+                    id,
+                    word_start,
+                    revision,
+                } => {
+                    let edited = self
+                        .buffers
+                        .get(&id)
+                        .is_some_and(|buf| buf.data.revision() != revision);
+                    // The word being completed may have moved or be gone
+                    if edited {
+                        self.show_message("The buffer was edited while completions were found");
+                    } else {
+                        self.show_completions(id, word_start, &result)?
+                    }
+                }
+                Request::Initialize | Request::Shutdown => (),
+            },
+            None => (),
+        }
+
+        Ok(())
+    }
+
+    /// Returns true if the open document of `path` is no longer at `revision`, the edits of a
+    /// language server for it would land in the wrong places
+    fn edited_since(&self, path: &Path, revision: usize) -> bool {
+        self.find_buffer_by_path(path)
+            .and_then(|id| self.buffers.get(&id))
+            .is_some_and(|buf| buf.data.revision() != revision)
+    }
+
+    /// Shows the hover information of a language server on one line, without the code fences of
+    /// Markdown
+    fn show_hover(&mut self, result: &Value) {
+        fn text(contents: &Value) -> String {
+            match contents {
+                Value::String(text) => text.clone(),
+                Value::Array(items) => items.iter().map(text).collect::<Vec<_>>().join("\n"),
+                _ => contents["value"].as_str().unwrap_or_default().to_string(),
+            }
+        }
+
+        let text = text(&result["contents"]);
+        let lines: Vec<&str> = text
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with("```"))
+            .collect();
+
+        match lines.is_empty() {
+            true => self.show_message("No information here"),
+            false => self.show_message(lines.join("  ")),
+        }
+    }
+
+    /// Goes to the location of a definition, or lists the locations when there are several
+    fn show_locations(
+        &mut self,
+        request: Request,
+        result: &Value,
+        encoding: Encoding,
+    ) -> io::Result<()> {
+        let items = match result {
+            Value::Array(items) => items.clone(),
+            Value::Null => vec![],
+            location => vec![location.clone()],
+        };
+
+        // Plain locations, or links to them
+        let mut locations = vec![];
+        for item in items.iter() {
+            let uri = item.get("uri").or_else(|| item.get("targetUri"));
+            let range = item
+                .get("range")
+                .or_else(|| item.get("targetSelectionRange"));
+            let (Some(path), Some(range)) = (uri.and_then(|u| u.as_str()), range) else {
+                continue;
+            };
+            let Some(path) = lsp::uri_to_path(path) else {
+                continue;
+            };
+
+            let text = self.file_text(&path);
+            let Some((line, column)) = lsp::line_column(&text, &range["start"], encoding) else {
+                continue;
+            };
+            let line_text: String = text
+                .split(|c| *c == '\n')
+                .nth(line)
+                .unwrap_or(&[])
+                .iter()
+                .collect();
+            locations.push((path, line, column, line_text.trim().to_string()));
+        }
+
+        let is_definition = matches!(request, Request::Definition);
+        match locations.as_slice() {
+            [] if is_definition => self.show_message("No definition found"),
+            [] => self.show_message("No references found"),
+            [(path, line, column, _)] if is_definition => {
+                self.open_file(path.clone(), Some((line + 1, column + 1)), false)
+            }
+            _ => {
+                let items: Vec<SelectorItem> = locations
+                    .iter()
+                    .map(|(path, line, column, line_text)| {
+                        let name = self.relative(path).display();
+                        SelectorItem::new(format!("{name}:{}", line + 1), Some(line_text.clone()))
+                            .with_value(format!("{}:{}:{}", line + 1, column + 1, path.display()))
+                    })
+                    .collect();
+
+                let title = if is_definition {
+                    "Definitions"
+                } else {
+                    "References"
+                };
+                let height = selector_height(items.len());
+                self.open_selector(
+                    title,
+                    Selector::new(items),
+                    height,
+                    EditorEvent::Command(Command::FindReferences),
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Lists the diagnostics of all open files, the most severe first, to go to one of them
+    fn list_diagnostics(&mut self) -> io::Result<()> {
+        let mut seen = HashSet::new();
+        let mut diagnostics = Vec::new();
+        for buf in self.buffers.values() {
+            let Some(path) = buf.file_path() else {
+                continue;
+            };
+            if !seen.insert(path.clone()) {
+                continue;
+            }
+
+            let document = buf.data.document();
+            for diagnostic in document.diagnostics() {
+                let line = document.line_of(diagnostic.start);
+                let column = diagnostic.start - document.lines()[line].start;
+                diagnostics.push((diagnostic.clone(), path.clone(), line, column));
+            }
+        }
+
+        if diagnostics.is_empty() {
+            self.show_message("No diagnostics in the open files");
+            return Ok(());
+        }
+
+        diagnostics.sort_by(|a, b| (a.0.severity, &a.1, a.2).cmp(&(b.0.severity, &b.1, b.2)));
+        let items: Vec<SelectorItem> = diagnostics
+            .iter()
+            .map(|(diagnostic, path, line, column)| {
+                let name = self.relative(path).display();
+                let label = format!("{}: {}", diagnostic.severity.name(), diagnostic.summary());
+                let location = format!("{}:{}:{}", line + 1, column + 1, path.display());
+                SelectorItem::new(label, Some(format!("{name}:{}", line + 1))).with_value(location)
+            })
+            .collect();
+
+        let height = selector_height(items.len());
+        self.open_selector(
+            "Diagnostics",
+            Selector::new(items),
+            height,
+            EditorEvent::Command(Command::ListDiagnostics),
+        )?;
+        Ok(())
+    }
+
+    /// Opens a location chosen from a list, given as `line:column:path`
+    fn open_location(&mut self, location: &str) {
+        let mut parts = location.splitn(3, ':');
+        let (Some(line), Some(column), Some(path)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return;
+        };
+        if let (Ok(line), Ok(column)) = (line.parse(), column.parse()) {
+            self.open_file(PathBuf::from(path), Some((line, column)), false);
+        }
+    }
+
+    /// Returns the text of the file at `path`, from its open document if it has one
+    fn file_text(&self, path: &Path) -> Vec<char> {
+        match self
+            .find_buffer_by_path(path)
+            .and_then(|id| self.buffers.get(&id))
+        {
+            Some(buf) => buf.data.document().chars().to_vec(),
+            None => fs::read_to_string(path)
+                .unwrap_or_default()
+                .chars()
+                .filter(|c| *c != '\r')
+                .collect(),
+        }
+    }
+
+    /// Applies the edits of a `WorkspaceEdit` to the files they are for, opening the files that
+    /// are not open yet
+    fn apply_workspace_edit(&mut self, edit: &Value, encoding: Encoding) {
+        let mut files = vec![];
+        if let Some(changes) = edit["changes"].as_object() {
+            files.extend(changes.iter().map(|(uri, edits)| (uri.as_str(), edits)));
+        }
+        // Creating, renaming and deleting files is not supported, only edits of text documents
+        if let Some(document_changes) = edit["documentChanges"].as_array() {
+            files.extend(document_changes.iter().filter_map(|change| {
+                Some((change["textDocument"]["uri"].as_str()?, &change["edits"]))
+            }));
+
+            // Edits of a version of a document the server no longer has would land in the wrong
+            // places, none of the files are renamed then
+            let stale = document_changes.iter().any(|change| {
+                let document = &change["textDocument"];
+                let (Some(uri), Some(version)) =
+                    (document["uri"].as_str(), document["version"].as_i64())
+                else {
+                    return false;
+                };
+                lsp::uri_to_path(uri)
+                    .and_then(|path| self.language_servers.version(&path))
+                    .is_some_and(|synced| synced != version)
+            });
+            if stale {
+                self.show_message("A file was edited while the symbol was renamed");
+                return;
+            }
+        }
+
+        if files.is_empty() {
+            self.show_message("Nothing to rename here");
+            return;
+        }
+
+        let count = files.len();
+        for (uri, edits) in files {
+            match lsp::uri_to_path(uri) {
+                Some(path) => self.apply_text_edits(&path, edits, encoding),
+                None => self.show_message(format!("Can't edit {uri}, it's not a file")),
+            }
+        }
+        if self.message.is_none() {
+            let files = if count == 1 { "file" } else { "files" };
+            self.show_message(format!("Renamed in {count} {files}"));
+        }
+    }
+
+    /// Applies `TextEdit`s of a language server to the file at `path`, opening it if needed
+    fn apply_text_edits(&mut self, path: &Path, edits: &Value, encoding: Encoding) {
+        let id = match self.find_buffer_by_path(path) {
+            Some(id) => id,
+            None => match self.load_file(path.to_path_buf(), false) {
+                Ok(id) => id,
+                Err(e) => {
+                    self.show_message(format!("Failed to open {} : {e}", path.display()));
+                    return;
+                }
+            },
+        };
+        let Some(buf) = self.buffers.get_mut(&id) else {
+            return;
+        };
+        if buf.is_read_only() {
+            let message = buf.read_only_message();
+            self.show_message(message);
+            return;
+        }
+
+        let edits: Vec<(usize, usize, String)> = {
+            let document = buf.data.document();
+            let text = document.chars();
+            edits
+                .as_array()
+                .map(|edits| edits.as_slice())
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|edit| {
+                    let start = lsp::text_position(text, &edit["range"]["start"], encoding)?;
+                    let end = lsp::text_position(text, &edit["range"]["end"], encoding)?;
+                    Some((start, end, edit["newText"].as_str()?.to_string()))
+                })
+                .collect()
+        };
+        buf.apply_edits(edits);
+    }
+
+    /// Lists the completions of a language server for the word of buffer `id` at `word_start`
+    fn show_completions(&mut self, id: Uuid, word_start: usize, result: &Value) -> io::Result<()> {
+        let completions = result
+            .as_array()
+            .or_else(|| result["items"].as_array())
+            .map(|items| items.as_slice())
+            .unwrap_or_default();
+        if completions.is_empty() || self.focused_buffer_id() != Some(id) {
+            self.show_message("No completions");
+            return Ok(());
+        }
+
+        let items: Vec<SelectorItem> = completions
+            .iter()
+            .filter_map(|item| {
+                let label = item["label"].as_str()?;
+                let text = item["textEdit"]["newText"]
+                    .as_str()
+                    .or_else(|| item["insertText"].as_str())
+                    .unwrap_or(label);
+                // Servers can send snippets even to clients that don't take them
+                let text = match item["insertTextFormat"].as_u64() {
+                    Some(2) => lsp::snippet_text(text),
+                    _ => text.to_string(),
+                };
+                let detail = item["detail"].as_str().map(|d| d.to_string());
+                Some(SelectorItem::new(label, detail).with_value(text))
+            })
+            .collect();
+
+        let height = selector_height(items.len());
+        self.open_selector(
+            "Completions",
+            Selector::new(items),
+            height,
+            EditorEvent::Complete { id, word_start },
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    use crate::{save::Backup, theme::Theme};
+
+    /// Editor drawing to memory, keeping its sessions, swap files and positions in `dir`
+    fn editor(dir: &Path) -> Editor<Vec<u8>> {
+        let settings = Settings {
+            theme: Theme::default(),
+            file_finder_exclude: vec![],
+            backup: Backup::None,
+            swap_dir: dir.join("swap"),
+            swap_interval: 0,
+            sessions_dir: dir.join("sessions"),
+            positions_file: dir.join("positions.json"),
+            languages_dir: dir.join("languages"),
+            language_servers: HashMap::new(),
+            word_completion_trigger: 0,
+        };
+        let (sender, receiver) = mpsc::channel();
+        let terminal = Terminal::with_size(vec![], 80, 24);
+        Editor::with_terminal(settings, terminal, sender, receiver).unwrap()
+    }
+
+    #[test]
+    fn draws_panes_and_overlays_in_tiny_terminals() {
+        let dir = std::env::temp_dir().join(format!("revo-tiny-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.txt"), "some text\nmore text\n").unwrap();
+
+        let mut editor = editor(&dir);
+
+        editor.open_folder(dir.clone()).unwrap();
+        editor.open_file(dir.join("a.txt"), Some((2, 3)), false);
+        editor.split_pane(SplitDirection::Horizontal);
+        editor.split_pane(SplitDirection::Vertical);
+        editor.open_command_palette().unwrap();
+        assert_eq!(editor.buffer_list.len(), 3);
+        assert_eq!(editor.overlays.len(), 1);
+
+        for (width, height) in [(1, 1), (3, 2), (0, 10), (10, 0), (0, 0), (80, 24)] {
+            editor.resize(width, height);
+            editor.draw().unwrap();
+
+            let (panes, _) = editor.layout.as_ref().unwrap().compute(editor.pane_area());
+            assert_eq!(panes.len(), 3);
+        }
+
+        drop(editor);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn restores_sessions_without_deleted_files() {
+        let dir = std::env::temp_dir().join(format!("revo-restore-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let text = "line\n".repeat(50);
+        fs::write(dir.join("kept.txt"), &text).unwrap();
+        fs::write(dir.join("deleted.txt"), &text).unwrap();
+
+        let mut first = editor(&dir);
+        first.open_folder(dir.clone()).unwrap();
+        first.restore_session();
+        first.open_file(dir.join("deleted.txt"), Some((3, 1)), false);
+        first.open_file(dir.join("kept.txt"), Some((40, 3)), false);
+        first.split_pane(SplitDirection::Vertical);
+        first.save_session();
+        drop(first);
+
+        fs::remove_file(dir.join("deleted.txt")).unwrap();
+
+        let mut second = editor(&dir);
+        second.open_folder(dir.clone()).unwrap();
+        second.restore_session();
+
+        let paths: Vec<PathBuf> = second
+            .tabs()
+            .iter()
+            .filter_map(|id| second.buffers[id].file_path())
+            .collect();
+        assert_eq!(paths, vec![second.root.join("kept.txt")]);
+        assert!(second.message.as_ref().unwrap().contains("deleted.txt"));
+
+        // Both panes show the kept file, at its cursor
+        let panes = second.layout.as_ref().unwrap().panes();
+        assert_eq!(panes.len(), 2);
+        for id in panes {
+            assert_eq!(second.buffers[&id].cursor_xy_relative(), (2, 39));
+        }
+
+        drop(second);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn clamps_remembered_positions_to_shorter_files() {
+        let dir = std::env::temp_dir().join(format!("revo-clamp-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.txt");
+        fs::write(&path, "a long line of text\n".repeat(50)).unwrap();
+
+        let mut first = editor(&dir);
+        first.open_folder(dir.clone()).unwrap();
+        first.open_file(path.clone(), Some((45, 12)), false);
+        let id = first.active_buffer_id().unwrap();
+        first.remember_position(id);
+        first.positions.save().unwrap();
+        drop(first);
+
+        // The same place comes back while the file is unchanged
+        let mut second = editor(&dir);
+        second.open_folder(dir.clone()).unwrap();
+        second.open_file(path.clone(), None, false);
+        let id = second.active_buffer_id().unwrap();
+        assert_eq!(second.buffers[&id].cursor_xy_relative(), (11, 44));
+        drop(second);
+
+        fs::write(&path, "short\n".repeat(10)).unwrap();
+
+        let mut third = editor(&dir);
+        third.open_folder(dir.clone()).unwrap();
+        third.open_file(path.clone(), None, false);
+        let id = third.active_buffer_id().unwrap();
+        let buf = &third.buffers[&id];
+        assert_eq!(buf.cursor_xy_relative(), (0, 10));
+        assert!(buf.scroll_y <= 10);
+
+        drop(third);
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,195 @@
+#![allow(dead_code)]
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+pub struct ExplorerEntry {
+    pub path: PathBuf,
+    pub depth: usize,
+    pub is_dir: bool,
+}
+
+/// Tree of files and folders shown by a `BufferLogic::Explorer` buffer.
+///
+/// Only the entries of expanded folders are kept, flattened in the order they are displayed.
+pub struct Explorer {
+    pub root: PathBuf,
+    entries: Vec<ExplorerEntry>,
+    expanded: HashSet<PathBuf>,
+}
+
+impl Explorer {
+    pub fn new(root: PathBuf) -> Self {
+        let mut explorer = Self {
+            expanded: HashSet::from([root.clone()]),
+            root,
+            entries: vec![],
+        };
+        explorer.refresh();
+        explorer
+    }
+
+    /// Re-reads the expanded folders from disk
+    pub fn refresh(&mut self) {
+        self.entries.clear();
+        self.entries.push(ExplorerEntry {
+            path: self.root.clone(),
+            depth: 0,
+            is_dir: true,
+        });
+
+        let root = self.root.clone();
+        self.read_dir(&root, 1);
+
+        // Forget expanded folders that were deleted or renamed. Folders under a collapsed one are
+        // not listed but stay expanded.
+        self.expanded.retain(|p| p.is_dir());
+    }
+
+    fn read_dir(&mut self, dir: &Path, depth: usize) {
+        if !self.expanded.contains(dir) {
+            return;
+        }
+
+        let mut children: Vec<(PathBuf, bool)> = match fs::read_dir(dir) {
+            Ok(read_dir) => read_dir
+                .filter_map(|e| e.ok())
+                .map(|e| {
+                    let path = e.path();
+                    let is_dir = path.is_dir();
+                    (path, is_dir)
+                })
+                .collect(),
+            Err(_) => return,
+        };
+
+        // Folders first, then alphabetical
+        children.sort_by_key(|(path, is_dir)| {
+            let name = path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            (!is_dir, name)
+        });
+
+        for (path, is_dir) in children {
+            self.entries.push(ExplorerEntry {
+                path: path.clone(),
+                depth,
+                is_dir,
+            });
+
+            if is_dir {
+                self.read_dir(&path, depth + 1);
+            }
+        }
+    }
+
+    pub fn entries(&self) -> &[ExplorerEntry] {
+        &self.entries
+    }
+
+    pub fn get(&self, index: usize) -> Option<&ExplorerEntry> {
+        self.entries.get(index)
+    }
+
+    pub fn index_of(&self, path: &Path) -> Option<usize> {
+        self.entries.iter().position(|e| e.path == path)
+    }
+
+    pub fn is_expanded(&self, path: &Path) -> bool {
+        self.expanded.contains(path)
+    }
+
+    pub fn expand(&mut self, index: usize) {
+        if let Some(entry) = self.entries.get(index) {
+            if entry.is_dir && self.expanded.insert(entry.path.clone()) {
+                self.refresh();
+            }
+        }
+    }
+
+    pub fn collapse(&mut self, index: usize) {
+        if let Some(entry) = self.entries.get(index) {
+            if self.expanded.remove(&entry.path) {
+                self.refresh();
+            }
+        }
+    }
+
+    pub fn toggle(&mut self, index: usize) {
+        match self.entries.get(index) {
+            Some(entry) if self.expanded.contains(&entry.path) => self.collapse(index),
+            Some(_) => self.expand(index),
+            None => (),
+        }
+    }
+
+    /// Returns the index of the folder containing the entry at `index`
+    pub fn parent_index(&self, index: usize) -> Option<usize> {
+        let depth = self.entries.get(index)?.depth;
+        self.entries[..index].iter().rposition(|e| e.depth < depth)
+    }
+
+    /// Returns the folder new entries should be created in, when the entry at `index` is selected
+    pub fn target_dir(&self, index: usize) -> PathBuf {
+        match self.entries.get(index) {
+            Some(entry) if entry.is_dir => entry.path.clone(),
+            Some(entry) => entry
+                .path
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| self.root.clone()),
+            None => self.root.clone(),
+        }
+    }
+
+    /// Renders one line per visible entry
+    pub fn render(&self) -> String {
+        self.entries
+            .iter()
+            .map(|entry| {
+                let name = match entry.path.file_name() {
+                    Some(name) => name.to_string_lossy().to_string(),
+                    None => entry.path.to_string_lossy().to_string(),
+                };
+
+                let glyph = if entry.is_dir {
+                    if self.expanded.contains(&entry.path) {
+                        '▾'
+                    } else {
+                        '▸'
+                    }
+                } else {
+                    file_glyph(&entry.path)
+                };
+
+                let indent = "  ".repeat(entry.depth);
+                if entry.is_dir {
+                    format!("{indent}{glyph} {name}/")
+                } else {
+                    format!("{indent}{glyph} {name}")
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Returns a text glyph used as the icon of a file, based on its extension
+pub fn file_glyph(path: &Path) -> char {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "rs" | "c" | "h" | "cpp" | "hpp" | "py" | "js" | "ts" | "go" | "java" | "sh" => 'λ',
+        "toml" | "json" | "yaml" | "yml" | "ini" | "cfg" | "lock" => '⚙',
+        "md" | "txt" | "rst" => '¶',
+        "png" | "jpg" | "jpeg" | "gif" | "svg" | "bmp" | "ico" => '▣',
+        _ => '·',
+    }
+}
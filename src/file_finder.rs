@@ -0,0 +1,97 @@
+#![allow(dead_code)]
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use ignore::{overrides::OverrideBuilder, WalkBuilder};
+use uuid::Uuid;
+
+use crate::editor::EditorEvent;
+
+/// Files found are sent in batches, so the editor doesn't redraw for every single file
+const BATCH_SIZE: usize = 512;
+const BATCH_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Walks `root` on a background thread and streams the files found to the editor as
+/// `EditorEvent::FilesFound`, until the walk ends or `cancel` is set.
+///
+/// Files ignored by `.gitignore` (and `.ignore`) are skipped, as are the paths matching any of the
+/// glob patterns in `exclude`.
+pub fn spawn_indexer(
+    root: PathBuf,
+    exclude: &[String],
+    selector_id: Uuid,
+    msg_sender: Sender<EditorEvent>,
+    cancel: Arc<AtomicBool>,
+) {
+    let mut overrides = OverrideBuilder::new(&root);
+    for pattern in exclude {
+        // Overrides act as a whitelist, unless the glob starts with `!`
+        // An invalid pattern should not stop the finder from working, so we just skip it
+        let _ = overrides.add(&format!("!{pattern}"));
+    }
+
+    let mut walker = WalkBuilder::new(&root);
+    walker.hidden(false).require_git(false);
+    if let Ok(overrides) = overrides.build() {
+        walker.overrides(overrides);
+    }
+    let walker = walker.build();
+
+    thread::spawn(move || {
+        let mut batch = vec![];
+        let mut last_send = Instant::now();
+
+        for entry in walker {
+            if cancel.load(Ordering::Relaxed) {
+                return;
+            }
+
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(_) => continue,
+            };
+
+            if !entry.file_type().is_some_and(|t| t.is_file()) {
+                continue;
+            }
+
+            batch.push(relative_path(&root, entry.path()));
+
+            if batch.len() >= BATCH_SIZE || last_send.elapsed() >= BATCH_INTERVAL {
+                let files = std::mem::take(&mut batch);
+                if send_files(&msg_sender, selector_id, files).is_err() {
+                    return;
+                }
+                last_send = Instant::now();
+            }
+        }
+
+        let _ = send_files(&msg_sender, selector_id, batch);
+    });
+}
+
+fn send_files(
+    msg_sender: &Sender<EditorEvent>,
+    selector_id: Uuid,
+    files: Vec<PathBuf>,
+) -> Result<(), ()> {
+    if files.is_empty() {
+        return Ok(());
+    }
+
+    msg_sender
+        .send(EditorEvent::FilesFound { selector_id, files })
+        .map_err(|_| ())
+}
+
+fn relative_path(root: &Path, path: &Path) -> PathBuf {
+    path.strip_prefix(root).unwrap_or(path).to_path_buf()
+}
@@ -0,0 +1,162 @@
+#![allow(dead_code)]
+use crate::{
+    document::Line,
+    syntax::{Node, NodeKind, SyntaxTree},
+};
+
+/// Lines that can be folded: the `header` line stays visible, the lines after it up to `last`
+/// are hidden
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FoldRegion {
+    pub header: usize,
+    pub last: usize,
+    /// 1 for regions that are not inside another one
+    pub level: usize,
+}
+
+/// Returns the regions of a text, sorted by their header line, one per header.
+///
+/// Brackets, strings and comments spanning lines make regions when there is a syntax tree. The
+/// lines after a bracket are folded up to the line closing it, which stays visible. Blocks of
+/// lines indented deeper than the line above them make regions too, like the bodies of Python
+/// functions, unless their header already starts a region of the syntax tree.
+pub fn regions(chars: &[char], lines: &[Line], tree: Option<&SyntaxTree>) -> Vec<FoldRegion> {
+    let mut regions = vec![];
+    if let Some(tree) = tree {
+        syntax_regions(&tree.root, chars, lines, &mut regions);
+    }
+    indentation_regions(chars, lines, &mut regions);
+
+    // Regions of the syntax tree come first, and the outer one of a tree comes before the ones
+    // inside it, so the stable sort keeps them for their header
+    regions.sort_by_key(|(header, _)| *header);
+    regions.dedup_by_key(|(header, _)| *header);
+
+    // Regions around this one, which may overlap it when the indentation doesn't follow the
+    // brackets
+    let mut outer: Vec<usize> = vec![];
+    regions
+        .into_iter()
+        .map(|(header, last)| {
+            while outer.last().is_some_and(|outer_last| *outer_last < last) {
+                outer.pop();
+            }
+            outer.push(last);
+            FoldRegion {
+                header,
+                last,
+                level: outer.len(),
+            }
+        })
+        .collect()
+}
+
+fn syntax_regions(node: &Node, chars: &[char], lines: &[Line], regions: &mut Vec<(usize, usize)>) {
+    for child in node.children.iter() {
+        let header = line_of(lines, child.start);
+        let end_line = line_of(lines, child.end.saturating_sub(1));
+
+        let last = match child.kind {
+            // The closing bracket stays visible, unless the group is never closed
+            NodeKind::Group { close, .. }
+                if chars.get(child.end.saturating_sub(1)) == Some(&close) =>
+            {
+                end_line.saturating_sub(1)
+            }
+            // Nodes of a grammar, like functions, fold through the brackets they contain
+            NodeKind::Named(_) => header,
+            _ => end_line,
+        };
+        if last > header {
+            regions.push((header, last));
+        }
+
+        syntax_regions(child, chars, lines, regions);
+    }
+}
+
+fn indentation_regions(chars: &[char], lines: &[Line], regions: &mut Vec<(usize, usize)>) {
+    // Lines whose blocks are still open, with their indentation
+    let mut open: Vec<(usize, usize)> = vec![];
+    let mut last_text_line = 0;
+
+    for (row, line) in lines.iter().enumerate() {
+        let text = chars.get(line.start..=line.end).unwrap_or(&[]);
+        let Some(indent) = text.iter().position(|c| !c.is_whitespace()) else {
+            // Blank lines belong to the block around them
+            continue;
+        };
+
+        while open
+            .last()
+            .is_some_and(|(open_indent, _)| *open_indent >= indent)
+        {
+            let (_, header) = open.pop().expect("Checked above");
+            if last_text_line > header {
+                regions.push((header, last_text_line));
+            }
+        }
+        open.push((indent, row));
+        last_text_line = row;
+    }
+
+    for (_, header) in open {
+        if last_text_line > header {
+            regions.push((header, last_text_line));
+        }
+    }
+}
+
+/// Returns the line containing the character at `pos`
+fn line_of(lines: &[Line], pos: usize) -> usize {
+    let line = lines.partition_point(|line| line.end < pos);
+    std::cmp::min(line, lines.len().saturating_sub(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{document::Document, language};
+    use std::path::Path;
+
+    fn regions_of(name: &str, text: &str) -> Vec<(usize, usize, usize)> {
+        let mut document = Document::from(text.to_string());
+        let languages = language::builtin();
+        document.set_language(language::detect(&languages, Path::new(name), ""));
+        let tree = document.syntax_tree();
+        regions(document.chars(), document.lines(), tree.as_deref())
+            .iter()
+            .map(|region| (region.header, region.last, region.level))
+            .collect()
+    }
+
+    #[test]
+    fn folds_brackets_up_to_the_closing_line() {
+        let text = "fn f() {\n    g(\n        1,\n    );\n}\n";
+        assert_eq!(regions_of("a.rs", text), [(0, 3, 1), (1, 2, 2)]);
+        // A bracket that is never closed folds to its last line
+        assert_eq!(regions_of("a.c", "f(\n  1,\n  2\n"), [(0, 2, 1)]);
+    }
+
+    #[test]
+    fn folds_strings_and_comments() {
+        assert_eq!(regions_of("a.c", "/* a\n   b */\n"), [(0, 1, 1)]);
+        assert_eq!(regions_of("a.py", "x = \"\"\"\n1\n\"\"\"\n"), [(0, 2, 1)]);
+        assert_eq!(regions_of("a.rs", "// a\n// b\nfn f() {}\n"), [(0, 1, 1)]);
+    }
+
+    #[test]
+    fn folds_indented_blocks() {
+        let text = "def f():\n    if x:\n        y\n\n    z\nw\n";
+        assert_eq!(regions_of("a.py", text), [(0, 4, 1), (1, 2, 2)]);
+        assert_eq!(regions_of("a.txt", "a\n  b\n  c\nd\n"), [(0, 2, 1)]);
+        assert_eq!(regions_of("a.txt", "a\nb\n"), []);
+    }
+
+    #[test]
+    fn prefers_brackets_to_indentation() {
+        // The body is indented past the closing bracket's line
+        let text = "f(\n    1\n    )\n";
+        assert_eq!(regions_of("a.c", text), [(0, 1, 1)]);
+    }
+}
@@ -0,0 +1,406 @@
+#![allow(dead_code)]
+use std::{collections::HashSet, fs, path::Path, rc::Rc};
+
+use fancy_regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::language::{self, Language, RegionRule, StringRule, TokenRule};
+
+/// Deepest chain of `include`s followed in a TextMate grammar
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Grammar written for revo, every field but `name` is optional
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct GrammarSchema {
+    name: String,
+    #[serde(default)]
+    extensions: Vec<String>,
+    #[serde(default)]
+    file_names: Vec<String>,
+    #[serde(default)]
+    shebangs: Vec<String>,
+    #[serde(default)]
+    first_line_match: Option<String>,
+    #[serde(default)]
+    line_comments: Vec<String>,
+    #[serde(default)]
+    block_comment: Option<(String, String)>,
+    #[serde(default)]
+    nested_comments: bool,
+    #[serde(default)]
+    strings: Vec<StringSchema>,
+    #[serde(default)]
+    numbers: bool,
+    #[serde(default)]
+    keywords: Vec<String>,
+    #[serde(default)]
+    brackets: Vec<(char, char)>,
+    #[serde(default)]
+    regions: Vec<RegionSchema>,
+    #[serde(default)]
+    rules: Vec<RuleSchema>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct StringSchema {
+    open: String,
+    close: String,
+    #[serde(default)]
+    escape: Option<char>,
+    #[serde(default)]
+    multiline: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RegionSchema {
+    begin: String,
+    end: String,
+    scope: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct RuleSchema {
+    #[serde(rename = "match")]
+    pattern: String,
+    scope: String,
+}
+
+/// Loads the languages defined in `dir`, `~/.revo/languages`.
+///
+/// Two kinds of JSON files are read:
+/// - revo grammars, see `GrammarSchema`
+/// - TextMate grammars (`.tmLanguage.json`), which VS Code uses and Sublime Text grammars can be
+///   converted to. Their patterns are flattened, so nested scopes are highlighted like the ones
+///   at the top, and an `end` can't refer to what its `begin` captured.
+///
+/// Sublime Text's `.sublime-syntax` files are YAML with their own rules, they are reported as not
+/// supported.
+///
+/// Returns the languages along with the problems found, which are reported instead of stopping
+/// the editor. A grammar that can't be read is skipped, and rules that don't compile are left out.
+pub fn load_dir(dir: &Path) -> (Vec<Rc<Language>>, Vec<String>) {
+    let mut languages = vec![];
+    let mut errors = vec![];
+
+    let Ok(entries) = fs::read_dir(dir) else {
+        return (languages, errors);
+    };
+    let mut paths: Vec<_> = entries.flatten().map(|entry| entry.path()).collect();
+    paths.sort();
+
+    for path in paths {
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        if file_name.ends_with(".sublime-syntax") {
+            errors.push(format!(
+                "{file_name}: .sublime-syntax files are not supported, convert it to a \
+                 .tmLanguage.json grammar"
+            ));
+            continue;
+        }
+        if !file_name.ends_with(".json") {
+            continue;
+        }
+
+        let mut warnings = vec![];
+        match load_file(&path, &mut warnings) {
+            Ok(language) => languages.push(Rc::new(language)),
+            Err(e) => errors.push(format!("{file_name}: {e}")),
+        }
+        if let Some(first) = warnings.first() {
+            let more = match warnings.len() {
+                1 => String::new(),
+                n => format!(" (and {} more)", n - 1),
+            };
+            errors.push(format!("{file_name}: {first}{more}"));
+        }
+    }
+
+    (languages, errors)
+}
+
+/// Reads a grammar, pushing problems that don't stop it from being used to `warnings`
+fn load_file(path: &Path, warnings: &mut Vec<String>) -> Result<Language, String> {
+    let json = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let grammar: Value = serde_json::from_str(&json).map_err(|e| e.to_string())?;
+
+    if grammar.get("patterns").is_some() || grammar.get("scopeName").is_some() {
+        textmate_language(&grammar, warnings)
+    } else {
+        let schema: GrammarSchema = serde_json::from_value(grammar).map_err(|e| {
+            // The list of every field would hide the problems of other grammars
+            let e = e.to_string();
+            e.split(", expected one of").next().unwrap_or(&e).to_string()
+        })?;
+        Ok(revo_language(schema, warnings))
+    }
+}
+
+fn revo_language(schema: GrammarSchema, warnings: &mut Vec<String>) -> Language {
+    let mut regions = vec![];
+    for region in schema.regions {
+        let Some(kind) = scope_kind(&region.scope, warnings) else {
+            continue;
+        };
+        if let (Some(begin), Some(end)) = (
+            compile(&region.begin, warnings),
+            compile(&region.end, warnings),
+        ) {
+            regions.push(RegionRule { begin, end, kind });
+        }
+    }
+
+    let mut rules = vec![];
+    for rule in schema.rules {
+        let Some(kind) = scope_kind(&rule.scope, warnings) else {
+            continue;
+        };
+        if let Some(regex) = compile(&rule.pattern, warnings) {
+            rules.push(TokenRule { regex, kind });
+        }
+    }
+
+    Language {
+        name: schema.name,
+        extensions: schema.extensions,
+        file_names: schema.file_names,
+        shebangs: schema.shebangs,
+        first_line_match: schema
+            .first_line_match
+            .and_then(|pattern| compile(&pattern, warnings)),
+        block_comment: schema.block_comment,
+        nested_comments: schema.nested_comments,
+        line_comments: schema.line_comments,
+        strings: schema
+            .strings
+            .into_iter()
+            .map(|string| StringRule {
+                open: string.open,
+                close: string.close,
+                escape: string.escape,
+                multiline: string.multiline,
+            })
+            .collect(),
+        numbers: schema.numbers,
+        keywords: schema.keywords,
+        brackets: schema.brackets,
+        regions,
+        rules,
+        ..Default::default()
+    }
+}
+
+fn textmate_language(grammar: &Value, warnings: &mut Vec<String>) -> Result<Language, String> {
+    let name = grammar
+        .get("name")
+        .or_else(|| grammar.get("scopeName"))
+        .and_then(Value::as_str)
+        .ok_or("the grammar has no name")?;
+
+    // File types are extensions, or whole names for files like `Makefile`
+    let file_types: Vec<String> = grammar
+        .get("fileTypes")
+        .and_then(Value::as_array)
+        .map(|types| {
+            types
+                .iter()
+                .filter_map(Value::as_str)
+                .map(|t| t.trim_start_matches('.').to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut language = Language {
+        name: name.to_string(),
+        extensions: file_types.clone(),
+        file_names: file_types,
+        first_line_match: grammar
+            .get("firstLineMatch")
+            .and_then(Value::as_str)
+            .and_then(|pattern| compile(pattern, warnings)),
+        brackets: vec![('(', ')'), ('[', ']'), ('{', '}')],
+        ..Default::default()
+    };
+
+    let empty = Map::new();
+    let repository = grammar
+        .get("repository")
+        .and_then(Value::as_object)
+        .unwrap_or(&empty);
+    let mut included = HashSet::new();
+    if let Some(patterns) = grammar.get("patterns") {
+        add_textmate_patterns(
+            patterns,
+            repository,
+            0,
+            &mut included,
+            &mut language,
+            warnings,
+        );
+    }
+
+    Ok(language)
+}
+
+/// Adds the rules of a TextMate `patterns` list, following the `#name` includes into the
+/// repository once each
+fn add_textmate_patterns(
+    patterns: &Value,
+    repository: &Map<String, Value>,
+    depth: usize,
+    included: &mut HashSet<String>,
+    language: &mut Language,
+    warnings: &mut Vec<String>,
+) {
+    let Some(patterns) = patterns.as_array() else {
+        return;
+    };
+    if depth > MAX_INCLUDE_DEPTH {
+        return;
+    }
+
+    for pattern in patterns {
+        if let Some(include) = pattern.get("include").and_then(Value::as_str) {
+            // `$self` and other grammars are not followed, the top level patterns are all there is
+            let Some(key) = include.strip_prefix('#') else {
+                continue;
+            };
+            if let (true, Some(entry)) = (included.insert(key.to_string()), repository.get(key)) {
+                let entry = Value::Array(vec![entry.clone()]);
+                add_textmate_patterns(&entry, repository, depth + 1, included, language, warnings);
+            }
+            continue;
+        }
+
+        let scope = ["name", "contentName"]
+            .iter()
+            .find_map(|key| pattern.get(*key).and_then(Value::as_str))
+            .or_else(|| {
+                let captures = pattern.get("captures")?;
+                ["0", "1"]
+                    .iter()
+                    .find_map(|i| captures.get(*i)?.get("name")?.as_str())
+            });
+        // Scopes without a color are not worth a rule
+        let kind = scope.and_then(language::scope_kind);
+
+        let string = |key: &str| pattern.get(key).and_then(Value::as_str);
+        match (string("match"), string("begin"), string("end"), kind) {
+            (Some(pattern), _, _, Some(kind)) => {
+                if let Some(regex) = compile(pattern, warnings) {
+                    language.rules.push(TokenRule { regex, kind });
+                }
+            }
+            (None, Some(begin), Some(end), Some(kind)) => {
+                if let (Some(begin), Some(end)) = (compile(begin, warnings), compile(end, warnings))
+                {
+                    language.regions.push(RegionRule { begin, end, kind });
+                }
+            }
+            // The nested patterns of a rule without a color, or of just a list, are used as if they
+            // were at the top, e.g. comments found by a look-ahead
+            (_, _, _, None) | (None, None, None, _) => {
+                if let Some(patterns) = pattern.get("patterns") {
+                    add_textmate_patterns(
+                        patterns,
+                        repository,
+                        depth + 1,
+                        included,
+                        language,
+                        warnings,
+                    );
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Compiles a pattern written for Oniguruma, the regex engine of TextMate and VS Code. Look-around,
+/// backreferences, atomic groups and possessive quantifiers are supported. Patterns are run on one
+/// line at a time, with its `\n`, so like in Oniguruma `^` and `$` match at the line ends.
+fn compile(pattern: &str, warnings: &mut Vec<String>) -> Option<Regex> {
+    let regex = RegexBuilder::new(pattern)
+        .oniguruma_mode(true)
+        .multi_line(true)
+        .build();
+    match regex {
+        Ok(regex) => Some(regex),
+        Err(_) => {
+            warnings.push(format!(
+                "skipped the pattern `{pattern}`, revo can't compile it"
+            ));
+            None
+        }
+    }
+}
+
+fn scope_kind(scope: &str, warnings: &mut Vec<String>) -> Option<language::TokenKind> {
+    let kind = language::scope_kind(scope);
+    if kind.is_none() {
+        warnings.push(format!(
+            "unknown scope `{scope}`, use comment, keyword, string or number"
+        ));
+    }
+    kind
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{document::Document, language::TokenKind};
+
+    fn kinds(document: &mut Document, row: usize) -> Vec<(usize, usize, TokenKind)> {
+        document.highlights(&[row])[0]
+            .iter()
+            .map(|span| (span.start, span.end, span.kind))
+            .collect()
+    }
+
+    #[test]
+    fn compiles_oniguruma_patterns() {
+        let mut warnings = vec![];
+        for (pattern, text, found) in [
+            (r"(?<=\.)\w+", "a.b", "b"),
+            (r"\w+(?=\()", "call(x)", "call"),
+            (r#"(['"]).*?\1"#, r#"'a"b' c"#, r#"'a"b'"#),
+            (r"(?>a+)b", "aab", "aab"),
+            (r"\h+", "0x1F", "0"),
+        ] {
+            let regex = compile(pattern, &mut warnings).unwrap();
+            let found_text = regex.find(text).unwrap().map(|m| m.as_str());
+            assert_eq!(found_text, Some(found), "{pattern}");
+        }
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn loads_a_textmate_grammar() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/grammars/ini.tmLanguage.json");
+        let mut warnings = vec![];
+        let language = load_file(&path, &mut warnings).unwrap();
+        assert_eq!(warnings, Vec::<String>::new());
+        assert_eq!(language.name, "Ini");
+        assert!(language.matches(Path::new("setup.conf"), ""));
+
+        let mut document = Document::from("; note\nname = \"x\" # end\n".to_string());
+        document.set_language(Some(Rc::new(language)));
+        assert_eq!(kinds(&mut document, 0), [(0, 6, TokenKind::Comment)]);
+        assert_eq!(
+            kinds(&mut document, 1),
+            [
+                (0, 6, TokenKind::Keyword),
+                (7, 10, TokenKind::String),
+                (11, 16, TokenKind::Comment)
+            ]
+        );
+    }
+}
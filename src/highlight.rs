@@ -0,0 +1,510 @@
+#![allow(dead_code)]
+use std::rc::Rc;
+
+use fancy_regex::Regex;
+
+use crate::{
+    document::Line,
+    language::{Language, TokenKind},
+};
+
+/// A highlighted part of a line, from column `start` up to `end` (exclusive)
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub kind: TokenKind,
+}
+
+/// What a line ends inside of, so the next line starts in it
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum LineState {
+    Normal,
+    BlockComment {
+        depth: usize,
+    },
+    /// Inside a string of `Language::strings[rule]`
+    String {
+        rule: usize,
+    },
+    /// Inside a region of `Language::regions[rule]`
+    Region {
+        rule: usize,
+    },
+}
+
+struct HighlightedLine {
+    spans: Vec<Span>,
+    end: LineState,
+}
+
+/// Highlights the lines of a document in `language`.
+///
+/// Lines are highlighted top to bottom, since a line can start inside a comment or string of the
+/// lines above it, and only as far as they are asked for. They are kept until an edit invalidates
+/// them, so after an edit only the lines from the edited one down to the ones being drawn are
+/// highlighted again.
+pub struct Highlighter {
+    language: Rc<Language>,
+    lines: Vec<HighlightedLine>,
+}
+
+impl Highlighter {
+    pub fn new(language: Rc<Language>) -> Self {
+        Self {
+            language,
+            lines: vec![],
+        }
+    }
+
+    pub fn language(&self) -> &Rc<Language> {
+        &self.language
+    }
+
+    /// Forgets the highlights of `line` and the lines after it
+    pub fn invalidate_from(&mut self, line: usize) {
+        self.lines.truncate(line);
+    }
+
+    /// Returns the spans of line `row`, highlighting the lines up to it first if needed
+    pub fn spans(&mut self, chars: &[char], lines: &[Line], row: usize) -> &[Span] {
+        while self.lines.len() <= row && self.lines.len() < lines.len() {
+            let Line { start, end } = &lines[self.lines.len()];
+            let text = chars
+                .get(*start..std::cmp::min(end + 1, chars.len()))
+                .unwrap_or(&[]);
+            let text = text.strip_suffix(&['\n']).unwrap_or(text);
+
+            let state = self.lines.last().map_or(LineState::Normal, |line| line.end);
+            let (spans, end) = highlight_line(&self.language, text, state);
+            self.lines.push(HighlightedLine { spans, end });
+        }
+
+        self.lines.get(row).map_or(&[], |line| &line.spans)
+    }
+}
+
+/// Highlights a line (without its '\n'), starting in `state`. Returns the spans and the state the
+/// next line starts in.
+fn highlight_line(
+    language: &Language,
+    text: &[char],
+    mut state: LineState,
+) -> (Vec<Span>, LineState) {
+    let mut spans = vec![];
+    let mut i = 0;
+    // Where the comment or string being scanned started
+    let mut token_start = 0;
+    let mut matcher = RegexMatcher::new(language, text);
+
+    loop {
+        match state {
+            LineState::BlockComment { depth } => {
+                let Some((open, close)) = &language.block_comment else {
+                    state = LineState::Normal;
+                    continue;
+                };
+
+                if i >= text.len() {
+                    push(&mut spans, token_start, text.len(), TokenKind::Comment);
+                    return (spans, state);
+                }
+
+                if starts_with(text, i, close) {
+                    i += close.chars().count();
+                    state = match depth {
+                        1 => {
+                            push(&mut spans, token_start, i, TokenKind::Comment);
+                            LineState::Normal
+                        }
+                        _ => LineState::BlockComment { depth: depth - 1 },
+                    };
+                } else if language.nested_comments && starts_with(text, i, open) {
+                    i += open.chars().count();
+                    state = LineState::BlockComment { depth: depth + 1 };
+                } else {
+                    i += 1;
+                }
+            }
+
+            LineState::String { rule } => {
+                let Some(rule) = language.strings.get(rule) else {
+                    state = LineState::Normal;
+                    continue;
+                };
+
+                if i >= text.len() {
+                    push(&mut spans, token_start, text.len(), TokenKind::String);
+                    if !rule.multiline {
+                        state = LineState::Normal;
+                    }
+                    return (spans, state);
+                }
+
+                if Some(text[i]) == rule.escape {
+                    i += 2;
+                } else if starts_with(text, i, &rule.close) {
+                    i += rule.close.chars().count();
+                    push(&mut spans, token_start, i, TokenKind::String);
+                    state = LineState::Normal;
+                } else {
+                    i += 1;
+                }
+            }
+
+            LineState::Region { rule } => {
+                let Some(rule) = language.regions.get(rule) else {
+                    state = LineState::Normal;
+                    continue;
+                };
+
+                match matcher.find(&rule.end, i) {
+                    Some((_, end)) => {
+                        i = std::cmp::max(end, i + 1).min(text.len());
+                        push(&mut spans, token_start, i, rule.kind);
+                        state = LineState::Normal;
+                    }
+                    None => {
+                        push(&mut spans, token_start, text.len(), rule.kind);
+                        return (spans, state);
+                    }
+                }
+            }
+
+            LineState::Normal => {
+                if i >= text.len() {
+                    return (spans, state);
+                }
+
+                if text[..i].iter().all(|c| c.is_whitespace()) {
+                    let line_rule = language
+                        .line_rules
+                        .iter()
+                        .find(|(prefix, _)| starts_with(text, i, prefix));
+                    if let Some((_, kind)) = line_rule {
+                        push(&mut spans, i, text.len(), *kind);
+                        return (spans, state);
+                    }
+                }
+
+                if let Some((open, _)) = &language.block_comment {
+                    if starts_with(text, i, open) {
+                        token_start = i;
+                        i += open.chars().count();
+                        state = LineState::BlockComment { depth: 1 };
+                        continue;
+                    }
+                }
+
+                let word_start = i == 0 || text[i - 1].is_whitespace();
+                if (word_start || !language.comments_after_space)
+                    && language
+                        .line_comments
+                        .iter()
+                        .any(|comment| starts_with(text, i, comment))
+                {
+                    push(&mut spans, i, text.len(), TokenKind::Comment);
+                    return (spans, state);
+                }
+
+                if let Some(rule) = language
+                    .strings
+                    .iter()
+                    .position(|rule| starts_with(text, i, &rule.open))
+                {
+                    token_start = i;
+                    i += language.strings[rule].open.chars().count();
+                    state = LineState::String { rule };
+                    continue;
+                }
+
+                let region = (0..language.regions.len())
+                    .find_map(|rule| Some((rule, matcher.match_at(rule, i)?)));
+                if let Some((rule, begin_end)) = region {
+                    token_start = i;
+                    i = begin_end;
+                    state = LineState::Region { rule };
+                    continue;
+                }
+
+                let token = (0..language.rules.len()).find_map(|rule| {
+                    let end = matcher.match_at(language.regions.len() + rule, i)?;
+                    Some((language.rules[rule].kind, end))
+                });
+                if let Some((kind, end)) = token {
+                    push(&mut spans, i, end, kind);
+                    i = end;
+                    continue;
+                }
+
+                let ch = text[i];
+                if language.char_literals && ch == '\'' {
+                    if let Some(end) = char_literal_end(text, i) {
+                        push(&mut spans, i, end, TokenKind::String);
+                        i = end;
+                        continue;
+                    }
+                }
+
+                if ch.is_alphabetic() || ch == '_' {
+                    let end = scan(text, i, |c, _| c.is_alphanumeric() || c == '_');
+                    let word: String = text[i..end].iter().collect();
+                    if language.keywords.contains(&word) {
+                        push(&mut spans, i, end, TokenKind::Keyword);
+                    }
+                    i = end;
+                } else if language.numbers && ch.is_ascii_digit() {
+                    // `1.5` is one number, while `1..5` is two
+                    let end = scan(text, i, |c, next| {
+                        c.is_alphanumeric()
+                            || c == '_'
+                            || (c == '.' && next.is_some_and(|n| n.is_ascii_digit()))
+                    });
+                    push(&mut spans, i, end, TokenKind::Number);
+                    i = end;
+                } else {
+                    i += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Runs the regular expressions of a language over a line.
+///
+/// The next match of every region and token rule is kept, so each one searches the line only
+/// once for every match, instead of at every character.
+struct RegexMatcher<'a> {
+    /// Regions begins, then token rules
+    regexes: Vec<&'a Regex>,
+    line: String,
+    /// Byte offset of every character in `line`, and of the end of the line
+    offsets: Vec<usize>,
+    /// Next match of every regex, in characters, `None` when it's not searched for yet
+    next: Vec<Option<Option<(usize, usize)>>>,
+}
+
+impl<'a> RegexMatcher<'a> {
+    fn new(language: &'a Language, text: &[char]) -> Self {
+        let regexes: Vec<&Regex> = language
+            .regions
+            .iter()
+            .map(|region| &region.begin)
+            .chain(language.rules.iter().map(|rule| &rule.regex))
+            .collect();
+
+        // Lines of languages without regexes are never searched
+        let (line, offsets) = if regexes.is_empty() {
+            (String::new(), vec![])
+        } else {
+            let mut line: String = text.iter().collect();
+            let mut offsets: Vec<usize> = line.char_indices().map(|(i, _)| i).collect();
+            offsets.push(line.len());
+            // Like in TextMate, patterns see the end of the line, e.g. a comment ending at `\n`
+            line.push('\n');
+            (line, offsets)
+        };
+
+        Self {
+            next: vec![None; regexes.len()],
+            regexes,
+            line,
+            offsets,
+        }
+    }
+
+    /// Returns the end of the match of regex `index` starting at character `i`, if there is one
+    /// that is not empty
+    fn match_at(&mut self, index: usize, i: usize) -> Option<usize> {
+        let next = match self.next[index] {
+            Some(Some((start, _))) if start < i => None,
+            next => next,
+        };
+        let next = match next {
+            Some(next) => next,
+            None => self.find(self.regexes[index], i),
+        };
+        self.next[index] = Some(next);
+
+        match next {
+            Some((start, end)) if start == i && end > start => Some(end),
+            _ => None,
+        }
+    }
+
+    /// Returns the first match of `regex` at or after character `i`, in characters. Matches of
+    /// the `\n` end at the end of the line.
+    fn find(&self, regex: &Regex, i: usize) -> Option<(usize, usize)> {
+        let from = *self.offsets.get(i)?;
+        // Patterns that backtrack too much give up, like the ones that don't match
+        let found = regex.find_from_pos(&self.line, from).ok()??;
+        let len = self.offsets.len() - 1;
+        let char_at = |byte| {
+            let i = self.offsets.partition_point(|offset| *offset < byte);
+            std::cmp::min(i, len)
+        };
+        Some((char_at(found.start()), char_at(found.end())))
+    }
+}
+
+fn push(spans: &mut Vec<Span>, start: usize, end: usize, kind: TokenKind) {
+    if start < end {
+        spans.push(Span { start, end, kind });
+    }
+}
+
+fn starts_with(text: &[char], i: usize, pattern: &str) -> bool {
+    !pattern.is_empty()
+        && pattern
+            .chars()
+            .enumerate()
+            .all(|(k, c)| text.get(i + k) == Some(&c))
+}
+
+/// Returns where the characters from `i` that `accept` takes end. `accept` gets every character
+/// and the one after it.
+fn scan(text: &[char], i: usize, accept: impl Fn(char, Option<char>) -> bool) -> usize {
+    let mut end = i;
+    while end < text.len() && accept(text[end], text.get(end + 1).copied()) {
+        end += 1;
+    }
+    end
+}
+
+/// Returns the end of the character literal at `i`, `None` if the quote doesn't start one
+fn char_literal_end(text: &[char], i: usize) -> Option<usize> {
+    match (text.get(i + 1), text.get(i + 2)) {
+        // Escapes like '\n', '\'' and '\u{1F600}'
+        (Some('\\'), Some(_)) => (i + 3..text.len())
+            .take(10)
+            .find(|k| text[*k] == '\'')
+            .map(|k| k + 1),
+        (Some(c), Some('\'')) if *c != '\'' => Some(i + 3),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{document::Document, language};
+
+    fn language(name: &str) -> Rc<Language> {
+        language::builtin()
+            .into_iter()
+            .find(|language| language.name == name)
+            .unwrap()
+    }
+
+    /// Highlights one line starting outside of any comment or string
+    fn line(language_name: &str, text: &str) -> Vec<(usize, usize, TokenKind)> {
+        let text: Vec<char> = text.chars().collect();
+        let (spans, _) = highlight_line(&language(language_name), &text, LineState::Normal);
+        spans.iter().map(|s| (s.start, s.end, s.kind)).collect()
+    }
+
+    /// Highlights every line of `text`, top to bottom
+    fn lines(language_name: &str, text: &str) -> Vec<Vec<(usize, usize, TokenKind)>> {
+        let document = Document::from(text.to_string());
+        let mut highlighter = Highlighter::new(language(language_name));
+        (0..document.line_count())
+            .map(|row| {
+                let spans = highlighter.spans(document.chars(), document.lines(), row);
+                spans.iter().map(|s| (s.start, s.end, s.kind)).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn nested_block_comments_end_at_the_outer_close() {
+        assert_eq!(
+            line("Rust", "/* a /* b */ c */ fn"),
+            vec![(0, 17, TokenKind::Comment), (18, 20, TokenKind::Keyword)]
+        );
+        assert_eq!(
+            lines("Rust", "/* a /* b */\nc */ fn"),
+            vec![
+                vec![(0, 12, TokenKind::Comment)],
+                vec![(0, 4, TokenKind::Comment), (5, 7, TokenKind::Keyword)],
+            ]
+        );
+    }
+
+    #[test]
+    fn multiline_strings_continue_on_the_next_line() {
+        assert_eq!(
+            lines("Rust", "let s = \"ab\ncd\" + 1;"),
+            vec![
+                vec![(0, 3, TokenKind::Keyword), (8, 11, TokenKind::String)],
+                vec![(0, 3, TokenKind::String), (6, 7, TokenKind::Number)],
+            ]
+        );
+        // JSON strings end with their line
+        assert_eq!(
+            lines("JSON", "\"ab\n1"),
+            vec![
+                vec![(0, 3, TokenKind::String)],
+                vec![(0, 1, TokenKind::Number)],
+            ]
+        );
+    }
+
+    #[test]
+    fn char_literals_are_not_lifetimes() {
+        assert_eq!(
+            line("Rust", "let c = 'a';"),
+            vec![(0, 3, TokenKind::Keyword), (8, 11, TokenKind::String)]
+        );
+        assert_eq!(
+            line("Rust", "let n = '\\n';"),
+            vec![(0, 3, TokenKind::Keyword), (8, 12, TokenKind::String)]
+        );
+        assert_eq!(
+            line("Rust", "fn f<'a>(x: &'a str)"),
+            vec![(0, 2, TokenKind::Keyword)]
+        );
+    }
+
+    #[test]
+    fn shell_comments_start_after_a_space() {
+        assert_eq!(
+            line("Shell", "echo $# # count"),
+            vec![(8, 15, TokenKind::Comment)]
+        );
+        assert_eq!(line("Shell", "# all"), vec![(0, 5, TokenKind::Comment)]);
+    }
+
+    #[test]
+    fn line_rules_match_at_the_start_of_lines() {
+        assert_eq!(
+            line("Markdown", "# Title"),
+            vec![(0, 7, TokenKind::Keyword)]
+        );
+        assert_eq!(
+            line("Markdown", "  > quote"),
+            vec![(2, 9, TokenKind::Comment)]
+        );
+        assert_eq!(line("Markdown", "a # b > c"), vec![]);
+    }
+
+    #[test]
+    fn edits_above_highlight_the_lines_below_again() {
+        let mut document = Document::from("a\nb\nc\nd".to_string());
+        document.set_language(Some(language("Markdown")));
+        assert_eq!(document.highlights(&[3])[0], vec![]);
+
+        // Only the last line was asked for, and the edit is above it
+        document.replace(0, 0, "<!-- ");
+        assert_eq!(
+            document.highlights(&[3])[0],
+            vec![Span {
+                start: 0,
+                end: 1,
+                kind: TokenKind::Comment
+            }]
+        );
+
+        document.replace(7, 7, "-->");
+        assert_eq!(document.highlights(&[3])[0], vec![]);
+    }
+}
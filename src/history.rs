@@ -0,0 +1,233 @@
+#![allow(dead_code)]
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EditKind {
+    Insert,
+    Delete,
+}
+
+/// A single character inserted or deleted at `pos`
+#[derive(Clone, Copy, Debug)]
+pub struct Edit {
+    pub kind: EditKind,
+    pub pos: usize,
+    pub ch: char,
+    /// Revision of the text before this edit was applied
+    pub revision_before: usize,
+    /// Revision of the text after this edit was applied
+    pub revision_after: usize,
+    /// Edits in the same group are undone and redone together
+    group: usize,
+}
+
+/// Undo and redo stacks of a `BufferData`.
+///
+/// Every state of the text has a revision number. Undoing back to a revision gives the same number
+/// again, so comparing revisions tells if the text is the same as when it was saved.
+pub struct History {
+    undo_stack: Vec<Edit>,
+    redo_stack: Vec<Edit>,
+    revision: usize,
+    next_revision: usize,
+    group: usize,
+    /// Set when the next edit has to start a new group, e.g. after the cursor moved
+    group_closed: bool,
+    /// Number of `begin_edit` calls not ended yet, their edits are all in the same group
+    edit_depth: usize,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self {
+            undo_stack: vec![],
+            redo_stack: vec![],
+            revision: 0,
+            next_revision: 1,
+            group: 0,
+            group_closed: true,
+            edit_depth: 0,
+        }
+    }
+
+    /// Revision of the current text
+    pub fn revision(&self) -> usize {
+        self.revision
+    }
+
+    /// Makes the next edit start a new undo group
+    pub fn break_group(&mut self) {
+        self.group_closed = true;
+    }
+
+    /// Makes the edits recorded until the matching `end_edit` one group, like the characters of
+    /// a deleted selection. Calls can be nested.
+    pub fn begin_edit(&mut self) {
+        if self.edit_depth == 0 {
+            self.group += 1;
+        }
+        self.edit_depth += 1;
+    }
+
+    pub fn end_edit(&mut self) {
+        self.edit_depth = self.edit_depth.saturating_sub(1);
+        if self.edit_depth == 0 {
+            self.group_closed = true;
+        }
+    }
+
+    /// Records an edit that was just applied to the text
+    pub fn record(&mut self, kind: EditKind, pos: usize, ch: char) {
+        // Typing a word, or deleting one, is undone in one go
+        let continues_group = match self.undo_stack.last() {
+            _ if self.edit_depth > 0 => true,
+            Some(last) if !self.group_closed && last.kind == kind && ch != '\n' => match kind {
+                EditKind::Insert => pos == last.pos + 1,
+                EditKind::Delete => pos == last.pos || pos + 1 == last.pos,
+            },
+            _ => false,
+        };
+
+        if !continues_group {
+            self.group += 1;
+        }
+        self.group_closed = ch.is_whitespace();
+
+        let edit = Edit {
+            kind,
+            pos,
+            ch,
+            revision_before: self.revision,
+            revision_after: self.next_revision,
+            group: self.group,
+        };
+        self.revision = self.next_revision;
+        self.next_revision += 1;
+
+        self.undo_stack.push(edit);
+        self.redo_stack.clear();
+    }
+
+    /// Pops the last group of edits, most recent first. They have to be reverted by the caller.
+    pub fn undo(&mut self) -> Vec<Edit> {
+        let edits = Self::pop_group(&mut self.undo_stack);
+
+        if let Some(first) = edits.last() {
+            self.revision = first.revision_before;
+        }
+        // Redo replays them oldest first
+        self.redo_stack.extend(edits.iter().copied());
+        self.group_closed = true;
+
+        edits
+    }
+
+    /// Pops the last undone group of edits, oldest first. They have to be re-applied by the caller.
+    pub fn redo(&mut self) -> Vec<Edit> {
+        let edits = Self::pop_group(&mut self.redo_stack);
+
+        if let Some(last) = edits.last() {
+            self.revision = last.revision_after;
+        }
+        self.undo_stack.extend(edits.iter().copied());
+        self.group_closed = true;
+
+        edits
+    }
+
+    fn pop_group(stack: &mut Vec<Edit>) -> Vec<Edit> {
+        let group = match stack.last() {
+            Some(edit) => edit.group,
+            None => return vec![],
+        };
+
+        let mut edits = vec![];
+        while stack.last().is_some_and(|e| e.group == group) {
+            edits.push(stack.pop().unwrap());
+        }
+        edits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_text(history: &mut History, start: usize, text: &str) {
+        for (i, ch) in text.chars().enumerate() {
+            history.record(EditKind::Insert, start + i, ch);
+        }
+    }
+
+    fn chars(edits: &[Edit]) -> String {
+        edits.iter().map(|e| e.ch).collect()
+    }
+
+    #[test]
+    fn typed_words_are_undone_one_at_a_time() {
+        let mut history = History::new();
+        type_text(&mut history, 0, "one two");
+
+        assert_eq!(chars(&history.undo()), "owt");
+        assert_eq!(chars(&history.undo()), " eno");
+        assert!(history.undo().is_empty());
+    }
+
+    #[test]
+    fn break_group_splits_typing() {
+        let mut history = History::new();
+        type_text(&mut history, 0, "ab");
+        history.break_group();
+        type_text(&mut history, 2, "cd");
+
+        assert_eq!(chars(&history.undo()), "dc");
+        assert_eq!(chars(&history.undo()), "ba");
+    }
+
+    #[test]
+    fn begin_edit_groups_everything_until_end_edit() {
+        let mut history = History::new();
+        history.begin_edit();
+        history.record(EditKind::Delete, 4, 'x');
+        history.begin_edit();
+        type_text(&mut history, 0, "a b\nc");
+        history.end_edit();
+        history.end_edit();
+
+        assert_eq!(history.undo().len(), 6);
+    }
+
+    #[test]
+    fn redo_replays_oldest_first() {
+        let mut history = History::new();
+        type_text(&mut history, 0, "abc");
+        history.undo();
+
+        let edits = history.redo();
+        assert_eq!(chars(&edits), "abc");
+        assert!(history.redo().is_empty());
+    }
+
+    #[test]
+    fn undoing_back_gives_the_same_revision() {
+        let mut history = History::new();
+        type_text(&mut history, 0, "ab");
+        let saved = history.revision();
+        history.break_group();
+        type_text(&mut history, 2, "c");
+
+        history.undo();
+        assert_eq!(history.revision(), saved);
+        history.redo();
+        assert_ne!(history.revision(), saved);
+    }
+
+    #[test]
+    fn new_edits_clear_the_redo_stack() {
+        let mut history = History::new();
+        type_text(&mut history, 0, "ab");
+        history.undo();
+        type_text(&mut history, 0, "c");
+
+        assert!(history.redo().is_empty());
+    }
+}
@@ -0,0 +1,335 @@
+#![allow(dead_code)]
+use std::{path::Path, rc::Rc};
+
+use fancy_regex::Regex;
+
+use crate::syntax::BundledParser;
+
+/// Kind of a highlighted piece of text, each has a color in `EditorColors`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TokenKind {
+    Comment,
+    Keyword,
+    String,
+    Number,
+}
+
+/// A string literal, from `open` to `close`
+#[derive(Clone, Debug)]
+pub struct StringRule {
+    pub open: String,
+    pub close: String,
+    /// Character that makes the next one part of the string, even a `close`
+    pub escape: Option<char>,
+    /// If false the string ends with its line, even when it's not closed
+    pub multiline: bool,
+}
+
+/// A token matched by a regular expression, within a line
+#[derive(Clone, Debug)]
+pub struct TokenRule {
+    pub regex: Regex,
+    pub kind: TokenKind,
+}
+
+/// A region from a match of `begin` to a match of `end`, which can span lines
+#[derive(Clone, Debug)]
+pub struct RegionRule {
+    pub begin: Regex,
+    pub end: Regex,
+    pub kind: TokenKind,
+}
+
+/// How the text of a language is highlighted.
+///
+/// Rules are tried in the order of the fields, so a block comment wins over a line comment
+/// starting with the same characters, and longer string delimiters should be listed first.
+#[derive(Clone, Debug, Default)]
+pub struct Language {
+    pub name: String,
+    /// Extensions of the files in this language, without the dot
+    pub extensions: Vec<String>,
+    /// Whole file names, for files without a telling extension like `Cargo.lock`
+    pub file_names: Vec<String>,
+    /// Interpreters named on a `#!` first line, like `python`
+    pub shebangs: Vec<String>,
+    /// Matches the first line of files in this language, like `<?xml`
+    pub first_line_match: Option<Regex>,
+    /// Lines whose first word starts with one of these are painted entirely, like Markdown
+    /// headings
+    pub line_rules: Vec<(String, TokenKind)>,
+    pub block_comment: Option<(String, String)>,
+    /// Block comments can be nested, like in Rust
+    pub nested_comments: bool,
+    pub line_comments: Vec<String>,
+    /// Line comments only start at the beginning of a word, like in shell scripts where `$#` is
+    /// not a comment
+    pub comments_after_space: bool,
+    pub strings: Vec<StringRule>,
+    /// `'a'` and `'\n'` are strings, while `'a` is not, like Rust lifetimes
+    pub char_literals: bool,
+    /// Regions of user grammars, tried after the strings
+    pub regions: Vec<RegionRule>,
+    /// Tokens of user grammars, tried after the regions
+    pub rules: Vec<TokenRule>,
+    pub numbers: bool,
+    pub keywords: Vec<String>,
+    /// Opening and closing brackets
+    pub brackets: Vec<(char, char)>,
+    /// Parser of the language's grammar. When there is one, the text is highlighted from its
+    /// syntax tree instead of with the rules above.
+    pub parser: Option<BundledParser>,
+}
+
+impl Language {
+    /// Returns true if this language is used for the file at `path`, with `first_line` as its
+    /// first line
+    pub fn matches(&self, path: &Path, first_line: &str) -> bool {
+        let file_name = path.file_name().map(|n| n.to_string_lossy().to_string());
+        let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+
+        if file_name.is_some_and(|name| self.file_names.contains(&name))
+            || extension.is_some_and(|ext| self.extensions.contains(&ext))
+        {
+            return true;
+        }
+
+        interpreter(first_line).is_some_and(|interpreter| self.shebangs.contains(&interpreter))
+            || self
+                .first_line_match
+                .as_ref()
+                .is_some_and(|regex| regex.is_match(first_line).unwrap_or(false))
+    }
+}
+
+/// Returns the interpreter named on a `#!` line, without a version, so `#!/usr/bin/env python3`
+/// gives `python`
+fn interpreter(first_line: &str) -> Option<String> {
+    let command = first_line.strip_prefix("#!")?;
+    let mut words = command.split_whitespace();
+
+    let mut program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        program = words.find(|word| !word.starts_with('-') && !word.contains('='))?;
+    }
+
+    let name = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    Some(name.to_string())
+}
+
+/// Returns the kind of token a grammar scope stands for, like `keyword.control` or the
+/// TextMate `constant.numeric.integer`. Scopes without a theme color give `None`.
+pub fn scope_kind(scope: &str) -> Option<TokenKind> {
+    scope.split_whitespace().find_map(|scope| {
+        let kind = match scope.split('.').next()? {
+            "comment" => TokenKind::Comment,
+            "string" => TokenKind::String,
+            "number" => TokenKind::Number,
+            "constant" if scope.starts_with("constant.numeric") => TokenKind::Number,
+            "constant" if scope.starts_with("constant.language") => TokenKind::Keyword,
+            "keyword" | "storage" => TokenKind::Keyword,
+            "variable" if scope.starts_with("variable.language") => TokenKind::Keyword,
+            _ => return None,
+        };
+        Some(kind)
+    })
+}
+
+/// Returns the language of the file at `path`, the first one of `languages` that matches
+pub fn detect(languages: &[Rc<Language>], path: &Path, first_line: &str) -> Option<Rc<Language>> {
+    languages
+        .iter()
+        .find(|language| language.matches(path, first_line))
+        .cloned()
+}
+
+fn words(words: &str) -> Vec<String> {
+    words.split_whitespace().map(str::to_string).collect()
+}
+
+/// `()`, `[]` and `{}`
+fn brackets() -> Vec<(char, char)> {
+    vec![('(', ')'), ('[', ']'), ('{', '}')]
+}
+
+fn string(open: &str, close: &str, escape: Option<char>, multiline: bool) -> StringRule {
+    StringRule {
+        open: open.to_string(),
+        close: close.to_string(),
+        escape,
+        multiline,
+    }
+}
+
+/// Languages highlighted without any configuration
+pub fn builtin() -> Vec<Rc<Language>> {
+    vec![
+        Rc::new(rust()),
+        Rc::new(json()),
+        Rc::new(markdown()),
+        Rc::new(toml()),
+        Rc::new(python()),
+        Rc::new(c()),
+        Rc::new(shell()),
+    ]
+}
+
+fn rust() -> Language {
+    Language {
+        name: "Rust".to_string(),
+        extensions: words("rs"),
+        block_comment: Some(("/*".to_string(), "*/".to_string())),
+        nested_comments: true,
+        line_comments: words("//"),
+        strings: vec![
+            string("r#\"", "\"#", None, true),
+            string("r\"", "\"", None, true),
+            string("\"", "\"", Some('\\'), true),
+        ],
+        char_literals: true,
+        numbers: true,
+        keywords: words(
+            "as async await break const continue crate dyn else enum extern false fn for if impl \
+             in let loop match mod move mut pub ref return self Self static struct super trait \
+             true type union unsafe use where while",
+        ),
+        brackets: brackets(),
+        parser: Some(BundledParser {
+            language: || tree_sitter_rust::LANGUAGE.into(),
+            highlights: &[tree_sitter_rust::HIGHLIGHTS_QUERY],
+        }),
+        ..Default::default()
+    }
+}
+
+fn json() -> Language {
+    Language {
+        name: "JSON".to_string(),
+        extensions: words("json"),
+        strings: vec![string("\"", "\"", Some('\\'), false)],
+        numbers: true,
+        keywords: words("true false null"),
+        brackets: brackets(),
+        parser: Some(BundledParser {
+            language: || tree_sitter_json::LANGUAGE.into(),
+            highlights: &[tree_sitter_json::HIGHLIGHTS_QUERY],
+        }),
+        ..Default::default()
+    }
+}
+
+fn markdown() -> Language {
+    Language {
+        name: "Markdown".to_string(),
+        extensions: words("md markdown"),
+        line_rules: vec![
+            ("#".to_string(), TokenKind::Keyword),
+            (">".to_string(), TokenKind::Comment),
+        ],
+        block_comment: Some(("<!--".to_string(), "-->".to_string())),
+        // Code blocks and inline code
+        strings: vec![
+            string("```", "```", None, true),
+            string("`", "`", None, false),
+        ],
+        ..Default::default()
+    }
+}
+
+/// Table headers are painted like keywords, the bundled query paints them like any key
+const TOML_TABLE_HEADERS: &str = r#"
+(table ["[" "]"] @keyword)
+(table [(bare_key) (dotted_key) (quoted_key)] @keyword)
+(table_array_element ["[[" "]]"] @keyword)
+(table_array_element [(bare_key) (dotted_key) (quoted_key)] @keyword)
+"#;
+
+fn toml() -> Language {
+    Language {
+        name: "TOML".to_string(),
+        extensions: words("toml"),
+        file_names: words("Cargo.lock"),
+        line_rules: vec![("[".to_string(), TokenKind::Keyword)],
+        line_comments: words("#"),
+        strings: vec![
+            string("\"\"\"", "\"\"\"", Some('\\'), true),
+            string("'''", "'''", None, true),
+            string("\"", "\"", Some('\\'), false),
+            string("'", "'", None, false),
+        ],
+        numbers: true,
+        keywords: words("true false"),
+        brackets: brackets(),
+        parser: Some(BundledParser {
+            language: || tree_sitter_toml_ng::LANGUAGE.into(),
+            highlights: &[TOML_TABLE_HEADERS, tree_sitter_toml_ng::HIGHLIGHTS_QUERY],
+        }),
+        ..Default::default()
+    }
+}
+
+fn python() -> Language {
+    Language {
+        name: "Python".to_string(),
+        extensions: words("py pyi"),
+        shebangs: words("python"),
+        line_comments: words("#"),
+        strings: vec![
+            string("\"\"\"", "\"\"\"", Some('\\'), true),
+            string("'''", "'''", Some('\\'), true),
+            string("\"", "\"", Some('\\'), false),
+            string("'", "'", Some('\\'), false),
+        ],
+        numbers: true,
+        keywords: words(
+            "and as assert async await break class continue def del elif else except False \
+             finally for from global if import in is lambda None nonlocal not or pass raise \
+             return True try while with yield",
+        ),
+        brackets: brackets(),
+        ..Default::default()
+    }
+}
+
+fn c() -> Language {
+    Language {
+        name: "C".to_string(),
+        extensions: words("c h"),
+        // Preprocessor directives
+        line_rules: vec![("#".to_string(), TokenKind::Keyword)],
+        block_comment: Some(("/*".to_string(), "*/".to_string())),
+        line_comments: words("//"),
+        strings: vec![string("\"", "\"", Some('\\'), false)],
+        char_literals: true,
+        numbers: true,
+        keywords: words(
+            "auto break case char const continue default do double else enum extern float for \
+             goto if inline int long register restrict return short signed sizeof static struct \
+             switch typedef union unsigned void volatile while bool true false NULL",
+        ),
+        brackets: brackets(),
+        ..Default::default()
+    }
+}
+
+fn shell() -> Language {
+    Language {
+        name: "Shell".to_string(),
+        extensions: words("sh bash zsh"),
+        file_names: words(".bashrc .bash_profile .zshrc .profile"),
+        shebangs: words("sh bash zsh dash ksh"),
+        line_comments: words("#"),
+        comments_after_space: true,
+        strings: vec![
+            string("\"", "\"", Some('\\'), true),
+            string("'", "'", None, true),
+        ],
+        numbers: true,
+        keywords: words(
+            "if then else elif fi case esac for select while until do done in function return \
+             local export readonly declare break continue exit",
+        ),
+        brackets: brackets(),
+        ..Default::default()
+    }
+}
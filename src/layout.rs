@@ -0,0 +1,420 @@
+#![allow(dead_code)]
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Rect {
+    pub fn new(x: u16, y: u16, width: u16, height: u16) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn right(&self) -> u16 {
+        self.x + self.width
+    }
+
+    pub fn bottom(&self) -> u16 {
+        self.y + self.height
+    }
+}
+
+/// A vertical line drawn between two side by side panes
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Separator {
+    pub x: u16,
+    pub y: u16,
+    pub height: u16,
+}
+
+/// How the two sides of a split are arranged
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum SplitDirection {
+    /// Side by side, with a separator column between them
+    Horizontal,
+    /// One above the other, the status line of the top pane separates them
+    Vertical,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FocusDirection {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// Tree of panes shown in the editor area, every pane shows one buffer
+pub enum LayoutNode {
+    Pane(Uuid),
+    Split {
+        direction: SplitDirection,
+        /// Share of the space given to `first`, between 0 and 1
+        ratio: f32,
+        first: Box<LayoutNode>,
+        second: Box<LayoutNode>,
+    },
+}
+
+const MIN_RATIO: f32 = 0.1;
+const MAX_RATIO: f32 = 0.9;
+
+impl LayoutNode {
+    /// Returns the buffers of all panes, from left to right and top to bottom
+    pub fn panes(&self) -> Vec<Uuid> {
+        match self {
+            LayoutNode::Pane(id) => vec![*id],
+            LayoutNode::Split { first, second, .. } => {
+                let mut panes = first.panes();
+                panes.extend(second.panes());
+                panes
+            }
+        }
+    }
+
+    pub fn contains(&self, id: Uuid) -> bool {
+        match self {
+            LayoutNode::Pane(pane) => *pane == id,
+            LayoutNode::Split { first, second, .. } => first.contains(id) || second.contains(id),
+        }
+    }
+
+    /// Replaces the buffer shown in the pane showing `old`
+    pub fn replace(&mut self, old: Uuid, new: Uuid) -> bool {
+        match self {
+            LayoutNode::Pane(id) if *id == old => {
+                *id = new;
+                true
+            }
+            LayoutNode::Pane(_) => false,
+            LayoutNode::Split { first, second, .. } => {
+                first.replace(old, new) || second.replace(old, new)
+            }
+        }
+    }
+
+    /// Splits the pane showing `target`, `new` is shown in the right or bottom half
+    pub fn split(&mut self, target: Uuid, new: Uuid, direction: SplitDirection) -> bool {
+        match self {
+            LayoutNode::Pane(id) if *id == target => {
+                *self = LayoutNode::Split {
+                    direction,
+                    ratio: 0.5,
+                    first: Box::new(LayoutNode::Pane(target)),
+                    second: Box::new(LayoutNode::Pane(new)),
+                };
+                true
+            }
+            LayoutNode::Pane(_) => false,
+            LayoutNode::Split { first, second, .. } => {
+                first.split(target, new, direction) || second.split(target, new, direction)
+            }
+        }
+    }
+
+    /// Removes the pane showing `target`, its sibling takes over the space.
+    ///
+    /// NOTE: The last pane can't be removed.
+    pub fn remove(&mut self, target: Uuid) -> bool {
+        let LayoutNode::Split { first, second, .. } = self else {
+            return false;
+        };
+
+        let remaining = if matches!(**first, LayoutNode::Pane(id) if id == target) {
+            std::mem::replace(&mut **second, LayoutNode::Pane(Uuid::nil()))
+        } else if matches!(**second, LayoutNode::Pane(id) if id == target) {
+            std::mem::replace(&mut **first, LayoutNode::Pane(Uuid::nil()))
+        } else {
+            return first.remove(target) || second.remove(target);
+        };
+
+        *self = remaining;
+        true
+    }
+
+    /// Grows (or shrinks, for a negative `delta`) the pane showing `target` along `direction`, by
+    /// moving the closest split of that direction
+    pub fn resize(&mut self, target: Uuid, direction: SplitDirection, delta: f32) -> bool {
+        match self {
+            LayoutNode::Pane(_) => false,
+            LayoutNode::Split {
+                direction: split_direction,
+                ratio,
+                first,
+                second,
+            } => {
+                let in_first = first.contains(target);
+                if !in_first && !second.contains(target) {
+                    return false;
+                }
+
+                // Prefer the innermost split, so nested panes resize locally
+                let child = if in_first { first } else { second };
+                if child.resize(target, direction, delta) {
+                    return true;
+                }
+
+                if *split_direction != direction {
+                    return false;
+                }
+
+                let delta = if in_first { delta } else { -delta };
+                *ratio = (*ratio + delta).clamp(MIN_RATIO, MAX_RATIO);
+                true
+            }
+        }
+    }
+
+    /// Gives every pane the same share of space along each direction
+    pub fn equalize(&mut self) {
+        if let LayoutNode::Split {
+            direction,
+            ratio,
+            first,
+            second,
+        } = self
+        {
+            let first_weight = first.weight(*direction) as f32;
+            let second_weight = second.weight(*direction) as f32;
+            *ratio = first_weight / (first_weight + second_weight);
+
+            first.equalize();
+            second.equalize();
+        }
+    }
+
+    /// Number of panes placed next to each other along `direction`
+    fn weight(&self, direction: SplitDirection) -> usize {
+        match self {
+            LayoutNode::Split {
+                direction: d,
+                first,
+                second,
+                ..
+            } if *d == direction => first.weight(direction) + second.weight(direction),
+            _ => 1,
+        }
+    }
+
+    /// Computes the rectangle of every pane inside `area`, along with the separators between them
+    pub fn compute(&self, area: Rect) -> (Vec<(Uuid, Rect)>, Vec<Separator>) {
+        let mut panes = vec![];
+        let mut separators = vec![];
+        self.compute_into(area, &mut panes, &mut separators);
+        (panes, separators)
+    }
+
+    fn compute_into(
+        &self,
+        area: Rect,
+        panes: &mut Vec<(Uuid, Rect)>,
+        separators: &mut Vec<Separator>,
+    ) {
+        match self {
+            LayoutNode::Pane(id) => panes.push((*id, area)),
+            LayoutNode::Split {
+                direction: SplitDirection::Horizontal,
+                ratio,
+                first,
+                second,
+            } => {
+                let available = area.width.saturating_sub(1);
+                let first_width = (available as f32 * ratio).round() as u16;
+                let second_width = available - first_width;
+
+                first.compute_into(
+                    Rect::new(area.x, area.y, first_width, area.height),
+                    panes,
+                    separators,
+                );
+                if area.width > 0 {
+                    separators.push(Separator {
+                        x: area.x + first_width,
+                        y: area.y,
+                        height: area.height,
+                    });
+                }
+                second.compute_into(
+                    Rect::new(area.x + first_width + 1, area.y, second_width, area.height),
+                    panes,
+                    separators,
+                );
+            }
+            LayoutNode::Split {
+                direction: SplitDirection::Vertical,
+                ratio,
+                first,
+                second,
+            } => {
+                let first_height = (area.height as f32 * ratio).round() as u16;
+                let second_height = area.height - first_height;
+
+                first.compute_into(
+                    Rect::new(area.x, area.y, area.width, first_height),
+                    panes,
+                    separators,
+                );
+                second.compute_into(
+                    Rect::new(area.x, area.y + first_height, area.width, second_height),
+                    panes,
+                    separators,
+                );
+            }
+        }
+    }
+}
+
+/// Finds the pane next to `from` in `direction`, preferring the one that overlaps it the most
+pub fn neighbour(panes: &[(Uuid, Rect)], from: Uuid, direction: FocusDirection) -> Option<Uuid> {
+    let (_, current) = panes.iter().find(|(id, _)| *id == from)?;
+
+    panes
+        .iter()
+        .filter(|(id, _)| *id != from)
+        .filter_map(|(id, rect)| {
+            // Distance along the direction, and overlap on the other axis
+            let (distance, overlap) = match direction {
+                FocusDirection::Left if rect.right() <= current.x => (
+                    current.x - rect.right(),
+                    overlap(rect.y, rect.bottom(), current.y, current.bottom()),
+                ),
+                FocusDirection::Right if rect.x >= current.right() => (
+                    rect.x - current.right(),
+                    overlap(rect.y, rect.bottom(), current.y, current.bottom()),
+                ),
+                FocusDirection::Up if rect.bottom() <= current.y => (
+                    current.y - rect.bottom(),
+                    overlap(rect.x, rect.right(), current.x, current.right()),
+                ),
+                FocusDirection::Down if rect.y >= current.bottom() => (
+                    rect.y - current.bottom(),
+                    overlap(rect.x, rect.right(), current.x, current.right()),
+                ),
+                _ => return None,
+            };
+
+            if overlap == 0 {
+                None
+            } else {
+                Some((*id, distance, overlap))
+            }
+        })
+        .min_by_key(|(_, distance, overlap)| (*distance, std::cmp::Reverse(*overlap)))
+        .map(|(id, _, _)| id)
+}
+
+fn overlap(a_start: u16, a_end: u16, b_start: u16, b_end: u16) -> u16 {
+    std::cmp::min(a_end, b_end).saturating_sub(std::cmp::max(a_start, b_start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(n: u128) -> Uuid {
+        Uuid::from_u128(n)
+    }
+
+    /// `1 | (2 over 3)`
+    fn three_panes() -> LayoutNode {
+        let mut layout = LayoutNode::Pane(id(1));
+        layout.split(id(1), id(2), SplitDirection::Horizontal);
+        layout.split(id(2), id(3), SplitDirection::Vertical);
+        layout
+    }
+
+    fn ratio(node: &LayoutNode) -> f32 {
+        match node {
+            LayoutNode::Split { ratio, .. } => *ratio,
+            LayoutNode::Pane(_) => panic!("not a split"),
+        }
+    }
+
+    #[test]
+    fn split_and_remove() {
+        let mut layout = three_panes();
+        assert_eq!(layout.panes(), [id(1), id(2), id(3)]);
+        assert!(!layout.split(id(4), id(5), SplitDirection::Vertical));
+
+        assert!(layout.remove(id(2)));
+        assert_eq!(layout.panes(), [id(1), id(3)]);
+        assert!(layout.remove(id(1)));
+        assert_eq!(layout.panes(), [id(3)]);
+        // The last pane stays
+        assert!(!layout.remove(id(3)));
+    }
+
+    #[test]
+    fn replace_keeps_the_position() {
+        let mut layout = three_panes();
+        assert!(layout.replace(id(2), id(4)));
+        assert_eq!(layout.panes(), [id(1), id(4), id(3)]);
+        assert!(!layout.contains(id(2)));
+    }
+
+    #[test]
+    fn compute_leaves_a_separator_column() {
+        let (panes, separators) = three_panes().compute(Rect::new(0, 0, 21, 10));
+
+        assert_eq!(
+            panes,
+            [
+                (id(1), Rect::new(0, 0, 10, 10)),
+                (id(2), Rect::new(11, 0, 10, 5)),
+                (id(3), Rect::new(11, 5, 10, 5)),
+            ]
+        );
+        assert_eq!(
+            separators,
+            [Separator {
+                x: 10,
+                y: 0,
+                height: 10
+            }]
+        );
+    }
+
+    #[test]
+    fn resize_moves_the_closest_split_of_the_direction() {
+        let mut layout = three_panes();
+        assert!(layout.resize(id(3), SplitDirection::Horizontal, 0.2));
+        assert!((ratio(&layout) - 0.3).abs() < 1e-6);
+
+        assert!(layout.resize(id(1), SplitDirection::Horizontal, 1.0));
+        assert_eq!(ratio(&layout), MAX_RATIO);
+
+        // The only pane can't be resized
+        assert!(!LayoutNode::Pane(id(1)).resize(id(1), SplitDirection::Vertical, 0.1));
+    }
+
+    #[test]
+    fn equalize_counts_panes_along_the_direction() {
+        let mut layout = three_panes();
+        layout.split(id(3), id(4), SplitDirection::Horizontal);
+        layout.resize(id(1), SplitDirection::Horizontal, 0.3);
+        layout.equalize();
+
+        // `1 | (2 over (3 | 4))`, the right side is two panes wide only at the bottom
+        assert_eq!(ratio(&layout), 0.5);
+    }
+
+    #[test]
+    fn neighbour_prefers_the_closest_overlapping_pane() {
+        let (panes, _) = three_panes().compute(Rect::new(0, 0, 21, 10));
+
+        assert_eq!(neighbour(&panes, id(1), FocusDirection::Right), Some(id(2)));
+        assert_eq!(neighbour(&panes, id(3), FocusDirection::Left), Some(id(1)));
+        assert_eq!(neighbour(&panes, id(2), FocusDirection::Down), Some(id(3)));
+        assert_eq!(neighbour(&panes, id(1), FocusDirection::Up), None);
+    }
+}
@@ -0,0 +1,1309 @@
+#![allow(dead_code)]
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::mpsc::Sender,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::{
+    diagnostics::{Diagnostic, Severity},
+    document::{ChangeReader, Document},
+    editor::EditorEvent,
+    history::EditKind,
+};
+
+/// How long a server gets to exit after being asked to, before it is killed
+const EXIT_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How to start the language server of a language, from the settings
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ServerConfig {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// Unit of the `character` of LSP positions, see `PositionEncodingKind`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Encoding {
+    Utf16,
+    /// Characters, like the positions of a `Document`
+    Utf32,
+}
+
+/// How a server wants documents to be synced, see `TextDocumentSyncKind`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum SyncKind {
+    None,
+    Full,
+    Incremental,
+}
+
+/// A request waiting for its response, with what the editor needs to act on it
+#[derive(Clone, Debug)]
+pub enum Request {
+    Initialize,
+    Shutdown,
+    /// Completions for the word of buffer `id` that starts at `word_start`, at `revision`
+    Completion {
+        id: Uuid,
+        word_start: usize,
+        revision: usize,
+    },
+    Hover,
+    Definition,
+    References,
+    /// Renaming of a symbol of the document of `path`, at `revision`
+    Rename {
+        path: PathBuf,
+        revision: usize,
+    },
+    /// Formatting of the document of `path`, at `revision`
+    Formatting {
+        path: PathBuf,
+        revision: usize,
+    },
+}
+
+/// Something from a language server that the editor has to act on
+pub enum ServerEvent {
+    /// The result of a request, `Value::Null` when the server found nothing
+    Response {
+        request: Request,
+        result: Value,
+        encoding: Encoding,
+    },
+    /// A message to show to the user
+    Message(String),
+    /// Problems found in the document of `path`, replacing the ones `source` reported before
+    Diagnostics {
+        path: PathBuf,
+        source: String,
+        diagnostics: Vec<Diagnostic>,
+    },
+}
+
+/// A running language server, spoken to with JSON-RPC over its stdin and stdout.
+///
+/// Messages from the server are read on a background thread, and sent to the editor as
+/// `EditorEvent::LanguageServer`.
+struct LanguageServer {
+    /// Taken when the server is stopped
+    child: Option<Child>,
+    stdin: ChildStdin,
+    reader: Option<JoinHandle<()>>,
+    next_id: u64,
+    pending: HashMap<u64, Request>,
+    /// Set once the server answered `initialize`, documents are only opened after that
+    initialized: bool,
+    encoding: Encoding,
+    sync_kind: SyncKind,
+}
+
+impl LanguageServer {
+    fn start(
+        language: &str,
+        config: &ServerConfig,
+        root: Option<&Path>,
+        generation: usize,
+        msg_sender: Sender<EditorEvent>,
+    ) -> io::Result<Self> {
+        let mut child = Command::new(&config.command)
+            .args(&config.args)
+            .current_dir(root.unwrap_or(Path::new(".")))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            // It would be drawn over the editor
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let language = language.to_string();
+        let reader = thread::spawn(move || read_messages(stdout, language, generation, msg_sender));
+
+        let mut server = Self {
+            child: Some(child),
+            stdin,
+            reader: Some(reader),
+            next_id: 0,
+            pending: HashMap::new(),
+            initialized: false,
+            encoding: Encoding::Utf16,
+            sync_kind: SyncKind::Incremental,
+        };
+
+        let root_uri = root.map(path_to_uri);
+        let params = json!({
+            "processId": std::process::id(),
+            "clientInfo": { "name": "revo" },
+            "rootUri": root_uri,
+            "workspaceFolders": root.map(|root| vec![json!({
+                "uri": path_to_uri(root),
+                "name": root.file_name().unwrap_or_default().to_string_lossy(),
+            })]),
+            "capabilities": {
+                "general": { "positionEncodings": ["utf-32", "utf-16"] },
+                "textDocument": {
+                    "synchronization": { "didSave": true },
+                    "completion": { "completionItem": { "snippetSupport": false } },
+                    "hover": { "contentFormat": ["plaintext", "markdown"] },
+                    "definition": { "linkSupport": true },
+                    "references": {},
+                    "rename": {},
+                    "formatting": {},
+                    "publishDiagnostics": {},
+                },
+                "workspace": { "workspaceEdit": { "documentChanges": true } },
+            },
+        });
+        server.request(Request::Initialize, "initialize", params)?;
+
+        Ok(server)
+    }
+
+    fn request(&mut self, request: Request, method: &str, params: Value) -> io::Result<()> {
+        self.next_id += 1;
+        self.pending.insert(self.next_id, request);
+        self.send(json!({
+            "jsonrpc": "2.0",
+            "id": self.next_id,
+            "method": method,
+            "params": params,
+        }))
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> io::Result<()> {
+        self.send(json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+        }))
+    }
+
+    fn respond(&mut self, id: Value, result: Value) -> io::Result<()> {
+        self.send(json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "result": result,
+        }))
+    }
+
+    fn send(&mut self, message: Value) -> io::Result<()> {
+        write_message(&mut self.stdin, &message)
+    }
+
+    /// Reads the settings the server answered `initialize` with
+    fn initialize(&mut self, result: &Value) -> io::Result<()> {
+        let capabilities = &result["capabilities"];
+        self.encoding = match capabilities["positionEncoding"].as_str() {
+            Some("utf-32") => Encoding::Utf32,
+            _ => Encoding::Utf16,
+        };
+
+        let sync = &capabilities["textDocumentSync"];
+        self.sync_kind = match sync.as_u64().or_else(|| sync["change"].as_u64()) {
+            Some(0) => SyncKind::None,
+            Some(1) => SyncKind::Full,
+            _ => SyncKind::Incremental,
+        };
+
+        self.initialized = true;
+        self.notify("initialized", json!({}))
+    }
+}
+
+impl Drop for LanguageServer {
+    fn drop(&mut self) {
+        // The server is not waited on to answer, it has a moment to exit before it is killed
+        let _ = self.request(Request::Shutdown, "shutdown", Value::Null);
+        let _ = self.notify("exit", Value::Null);
+
+        // Waiting for it would hold up the editor, when it quits or opens another folder
+        let Some(mut child) = self.child.take() else {
+            return;
+        };
+        let reader = self.reader.take();
+        thread::spawn(move || {
+            let deadline = Instant::now() + EXIT_TIMEOUT;
+            while matches!(child.try_wait(), Ok(None)) && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(10));
+            }
+            let _ = child.kill();
+            let _ = child.wait();
+
+            // The reader stops once the server's stdout is closed
+            if let Some(reader) = reader {
+                let _ = reader.join();
+            }
+        });
+    }
+}
+
+/// What a server knows about an open document
+struct SyncedDocument {
+    language: String,
+    version: i64,
+    /// The text as the server has it
+    text: Vec<char>,
+    /// Number of changes of the document sent to the server
+    synced_changes: ChangeReader,
+    /// See `Document::replacements`
+    replacements: usize,
+}
+
+/// The language servers of the open documents.
+///
+/// A server is started for the first document in its language, and every document in that
+/// language is opened in it. Edits are sent to the servers when the editor syncs them, as ranges
+/// of the text that changed unless a server only takes whole texts.
+pub struct LanguageServers {
+    configs: HashMap<String, ServerConfig>,
+    /// Running servers, by the lower case name of their language
+    servers: HashMap<String, LanguageServer>,
+    /// Languages whose server could not be started, they are not tried again
+    failed: HashSet<String>,
+    documents: HashMap<PathBuf, SyncedDocument>,
+    /// Folder the servers work in
+    root: Option<PathBuf>,
+    /// Number of times the servers were stopped to work in another folder. The messages of the
+    /// servers that were stopped may still be on their way, they are told apart by it.
+    generation: usize,
+    msg_sender: Sender<EditorEvent>,
+}
+
+impl LanguageServers {
+    pub fn new(
+        configs: &HashMap<String, ServerConfig>,
+        root: Option<PathBuf>,
+        msg_sender: Sender<EditorEvent>,
+    ) -> Self {
+        let configs = configs
+            .iter()
+            .map(|(language, config)| (language.to_lowercase(), config.clone()))
+            .collect();
+
+        Self {
+            configs,
+            servers: HashMap::new(),
+            failed: HashSet::new(),
+            documents: HashMap::new(),
+            root,
+            generation: 0,
+            msg_sender,
+        }
+    }
+
+    /// Makes the servers work in `root`. The running servers are stopped, and started again in
+    /// it for the next sync, which opens the documents again.
+    /// Returns the languages of the stopped servers.
+    pub fn set_root(&mut self, root: PathBuf) -> Vec<String> {
+        if self.root.as_ref() == Some(&root) {
+            return vec![];
+        }
+
+        self.root = Some(root);
+        self.generation += 1;
+        self.documents.clear();
+        self.failed.clear();
+        self.servers.drain().map(|(language, _)| language).collect()
+    }
+
+    /// Sends the edits made to `document` since the last sync to its language server, opening
+    /// it there first, and starting the server if it's not running yet.
+    /// Returns an error the first time a server fails to start.
+    pub fn sync(&mut self, path: &Path, document: &Document) -> Result<(), String> {
+        let Some(language) = document.language().map(|l| l.name.to_lowercase()) else {
+            self.close(path);
+            return Ok(());
+        };
+
+        // The document changed language
+        if self
+            .documents
+            .get(path)
+            .is_some_and(|d| d.language != language)
+        {
+            self.close(path);
+        }
+
+        if !self.start_server(&language)? {
+            return Ok(());
+        }
+        let server = self.servers.get_mut(&language).expect("Started above");
+        if !server.initialized {
+            return Ok(());
+        }
+
+        let uri = path_to_uri(path);
+        let Some(synced) = self.documents.get_mut(path) else {
+            let text: Vec<char> = document.chars().to_vec();
+            let params = json!({
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": language,
+                    "version": 1,
+                    "text": text.iter().collect::<String>(),
+                },
+            });
+            self.documents.insert(
+                path.to_path_buf(),
+                SyncedDocument {
+                    language,
+                    version: 1,
+                    text,
+                    synced_changes: document.reader(),
+                    replacements: document.replacements(),
+                },
+            );
+            return server
+                .notify("textDocument/didOpen", params)
+                .map_err(|e| format!("Failed to open {} in its language server: {e}", uri));
+        };
+
+        let replaced = synced.replacements != document.replacements();
+        let changes = document.changes_since(synced.synced_changes.get());
+        if changes.is_empty() && !replaced {
+            return Ok(());
+        }
+
+        let content_changes = match (replaced, server.sync_kind) {
+            (_, SyncKind::None) => vec![],
+            (false, SyncKind::Incremental) => {
+                let mut content_changes = vec![];
+                for run in runs(changes) {
+                    let range = lsp_range(&synced.text, run.start, run.end, server.encoding);
+                    synced.text.splice(run.start..run.end, run.text.chars());
+                    content_changes.push(json!({ "range": range, "text": run.text }));
+                }
+                content_changes
+            }
+            _ => vec![json!({ "text": document.to_string() })],
+        };
+
+        synced.text = document.chars().to_vec();
+        synced.synced_changes.set(document.change_count());
+        synced.replacements = document.replacements();
+        if content_changes.is_empty() {
+            return Ok(());
+        }
+
+        synced.version += 1;
+        let params = json!({
+            "textDocument": { "uri": uri, "version": synced.version },
+            "contentChanges": content_changes,
+        });
+        server
+            .notify("textDocument/didChange", params)
+            .map_err(|e| format!("Failed to send changes to the language server: {e}"))
+    }
+
+    /// Tells the language servers about documents that are no longer open
+    pub fn close_others(&mut self, open: &HashSet<PathBuf>) {
+        let closed: Vec<PathBuf> = self
+            .documents
+            .keys()
+            .filter(|path| !open.contains(*path))
+            .cloned()
+            .collect();
+
+        for path in closed {
+            self.close(&path);
+        }
+    }
+
+    fn close(&mut self, path: &Path) {
+        let Some(synced) = self.documents.remove(path) else {
+            return;
+        };
+        if let Some(server) = self.servers.get_mut(&synced.language) {
+            let params = json!({ "textDocument": { "uri": path_to_uri(path) } });
+            let _ = server.notify("textDocument/didClose", params);
+        }
+    }
+
+    /// Returns the version the language server has of the document at `path`
+    pub fn version(&self, path: &Path) -> Option<i64> {
+        self.documents.get(path).map(|synced| synced.version)
+    }
+
+    /// Tells the language server of the document at `path` that it was saved
+    pub fn saved(&mut self, path: &Path) {
+        let Some(synced) = self.documents.get(path) else {
+            return;
+        };
+        if let Some(server) = self.servers.get_mut(&synced.language) {
+            let params = json!({ "textDocument": { "uri": path_to_uri(path) } });
+            let _ = server.notify("textDocument/didSave", params);
+        }
+    }
+
+    /// Starts the server of `language` if it's configured and not running yet. Returns true if
+    /// it's running.
+    fn start_server(&mut self, language: &str) -> Result<bool, String> {
+        if self.servers.contains_key(language) {
+            return Ok(true);
+        }
+        let Some(config) = self.configs.get(language) else {
+            return Ok(false);
+        };
+        if self.failed.contains(language) {
+            return Ok(false);
+        }
+
+        let sender = self.msg_sender.clone();
+        let root = self.root.as_deref();
+        match LanguageServer::start(language, config, root, self.generation, sender) {
+            Ok(server) => {
+                self.servers.insert(language.to_string(), server);
+                Ok(true)
+            }
+            Err(e) => {
+                self.failed.insert(language.to_string());
+                Err(format!("Failed to start {} : {e}", config.command))
+            }
+        }
+    }
+
+    /// Sends a request about the document at `path`, which must have been synced. `params` get the
+    /// document added as `textDocument`, and `pos` as `position` when there is one.
+    pub fn request(
+        &mut self,
+        path: &Path,
+        pos: Option<usize>,
+        request: Request,
+        method: &str,
+        mut params: Value,
+    ) -> Result<(), String> {
+        let Some(synced) = self.documents.get(path) else {
+            return Err("No language server is running for this file".to_string());
+        };
+        let Some(server) = self.servers.get_mut(&synced.language) else {
+            return Err("No language server is running for this file".to_string());
+        };
+
+        params["textDocument"] = json!({ "uri": path_to_uri(path) });
+        if let Some(pos) = pos {
+            params["position"] = lsp_position(&synced.text, pos, server.encoding);
+        }
+        server
+            .request(request, method, params)
+            .map_err(|e| format!("Failed to send a request to the language server: {e}"))
+    }
+
+    /// Handles a message from the server of `language`, answering the requests it makes itself
+    pub fn handle_message(
+        &mut self,
+        language: &str,
+        generation: usize,
+        message: Value,
+    ) -> Option<ServerEvent> {
+        if generation != self.generation {
+            return None;
+        }
+        let server = self.servers.get_mut(language)?;
+        let id = message.get("id").cloned();
+
+        let Some(method) = message["method"].as_str() else {
+            // A response
+            let request = server.pending.remove(&id?.as_u64()?)?;
+            if let Some(error) = message.get("error") {
+                let text = error["message"].as_str().unwrap_or("Unknown error");
+                return Some(ServerEvent::Message(format!(
+                    "Language server error: {text}"
+                )));
+            }
+
+            let result = message.get("result").cloned().unwrap_or(Value::Null);
+            if let Request::Initialize = request {
+                if let Err(e) = server.initialize(&result) {
+                    let message = format!("Failed to initialize the language server: {e}");
+                    return Some(ServerEvent::Message(message));
+                }
+                return None;
+            }
+            return Some(ServerEvent::Response {
+                request,
+                result,
+                encoding: server.encoding,
+            });
+        };
+
+        let params = &message["params"];
+        if let Some(id) = id {
+            // Requests from the server, only the settings it asks for get more than an empty answer
+            let result = match method {
+                "workspace/configuration" => {
+                    let count = params["items"].as_array().map_or(0, |items| items.len());
+                    Value::Array(vec![Value::Null; count])
+                }
+                _ => Value::Null,
+            };
+            let _ = server.respond(id, result);
+            return None;
+        }
+
+        match method {
+            "textDocument/publishDiagnostics" => {
+                let encoding = server.encoding;
+                let path = uri_to_path(params["uri"].as_str()?)?;
+                let text = &self.documents.get(&path)?.text;
+                let source = diagnostic_source(language);
+                let diagnostics = params["diagnostics"]
+                    .as_array()?
+                    .iter()
+                    .filter_map(|d| diagnostic(text, d, encoding, &source))
+                    .collect();
+                Some(ServerEvent::Diagnostics {
+                    path,
+                    source,
+                    diagnostics,
+                })
+            }
+            "window/showMessage" => {
+                let text = params["message"].as_str()?;
+                Some(ServerEvent::Message(text.to_string()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Forgets the server of `language` after it exited, it is started again for the next edit.
+    /// Returns false if it was stopped already, or by `set_root`.
+    pub fn stopped(&mut self, language: &str, generation: usize) -> bool {
+        if generation != self.generation {
+            return false;
+        }
+        let Some(server) = self.servers.remove(language) else {
+            return false;
+        };
+        // Crashing again and again would keep restarting it
+        if !server.initialized {
+            self.failed.insert(language.to_string());
+        }
+
+        let paths: Vec<PathBuf> = self
+            .documents
+            .iter()
+            .filter(|(_, d)| d.language == language)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in paths {
+            self.documents.remove(&path);
+        }
+        true
+    }
+}
+
+/// Source of the diagnostics of the language server of `language`, see `Diagnostic::source`
+pub fn diagnostic_source(language: &str) -> String {
+    format!("{language} language server")
+}
+
+/// Reads a diagnostic of a publishDiagnostics notification, with its range in `text`
+fn diagnostic(
+    text: &[char],
+    value: &Value,
+    encoding: Encoding,
+    source: &str,
+) -> Option<Diagnostic> {
+    let range = &value["range"];
+    let severity = match value["severity"].as_u64() {
+        Some(2) => Severity::Warning,
+        Some(3) => Severity::Information,
+        Some(4) => Severity::Hint,
+        _ => Severity::Error,
+    };
+
+    Some(Diagnostic {
+        start: text_position(text, &range["start"], encoding)?,
+        end: text_position(text, &range["end"], encoding)?,
+        severity,
+        message: value["message"].as_str()?.to_string(),
+        source: source.to_string(),
+    })
+}
+
+/// Consecutive changes at the same place, sent to a server as one change
+struct Run {
+    /// Range of the text before the run that it replaces
+    start: usize,
+    end: usize,
+    text: String,
+}
+
+/// Groups typed, pasted and deleted characters into runs, so one keystroke or paste makes one
+/// change
+fn runs(changes: &[crate::document::Change]) -> Vec<Run> {
+    let mut runs: Vec<Run> = vec![];
+
+    for change in changes {
+        let last = runs.last_mut();
+        match (change.kind, last) {
+            // Typed after the inserted text
+            (EditKind::Insert, Some(run)) if change.pos == run.start + run.text.chars().count() => {
+                run.text.push(change.ch)
+            }
+            // Deleted with Delete, the next character comes to the same place
+            (EditKind::Delete, Some(run)) if run.text.is_empty() && change.pos == run.start => {
+                run.end += 1
+            }
+            // Deleted with Backspace
+            (EditKind::Delete, Some(run)) if run.text.is_empty() && change.pos + 1 == run.start => {
+                run.start -= 1
+            }
+            (EditKind::Insert, _) => runs.push(Run {
+                start: change.pos,
+                end: change.pos,
+                text: change.ch.to_string(),
+            }),
+            (EditKind::Delete, _) => runs.push(Run {
+                start: change.pos,
+                end: change.pos + 1,
+                text: String::new(),
+            }),
+        }
+    }
+
+    runs
+}
+
+/// Returns the LSP position of the character at `pos` of `text`
+pub fn lsp_position(text: &[char], pos: usize, encoding: Encoding) -> Value {
+    let pos = std::cmp::min(pos, text.len());
+    let line_start = text[..pos]
+        .iter()
+        .rposition(|c| *c == '\n')
+        .map_or(0, |i| i + 1);
+    let line = text[..line_start].iter().filter(|c| **c == '\n').count();
+
+    json!({
+        "line": line,
+        "character": units(&text[line_start..pos], encoding),
+    })
+}
+
+fn lsp_range(text: &[char], start: usize, end: usize, encoding: Encoding) -> Value {
+    json!({
+        "start": lsp_position(text, start, encoding),
+        "end": lsp_position(text, end, encoding),
+    })
+}
+
+/// Returns the 0-indexed line and column (in characters) of an LSP position in `text`, columns
+/// past the end of the line are clamped to it
+pub fn line_column(text: &[char], position: &Value, encoding: Encoding) -> Option<(usize, usize)> {
+    let line = position["line"].as_u64()? as usize;
+    let character = position["character"].as_u64()? as usize;
+
+    let line_text = text.split(|c| *c == '\n').nth(line).unwrap_or(&[]);
+    Some((line, column_of(line_text, character, encoding)))
+}
+
+/// Returns the position in `text` of an LSP position, clamped to the text
+pub fn text_position(text: &[char], position: &Value, encoding: Encoding) -> Option<usize> {
+    let (line, column) = line_column(text, position, encoding)?;
+
+    let mut start = 0;
+    for _ in 0..line {
+        match text[start..].iter().position(|c| *c == '\n') {
+            Some(i) => start += i + 1,
+            None => return Some(text.len()),
+        }
+    }
+    Some(start + column)
+}
+
+/// Returns the column of `line_text` that is `character` units into it
+pub fn column_of(line_text: &[char], character: usize, encoding: Encoding) -> usize {
+    let mut count = 0;
+    for (column, ch) in line_text.iter().enumerate() {
+        if count >= character {
+            return column;
+        }
+        count += match encoding {
+            Encoding::Utf16 => ch.len_utf16(),
+            Encoding::Utf32 => 1,
+        };
+    }
+    line_text.len()
+}
+
+fn units(chars: &[char], encoding: Encoding) -> usize {
+    match encoding {
+        Encoding::Utf16 => chars.iter().map(|c| c.len_utf16()).sum(),
+        Encoding::Utf32 => chars.len(),
+    }
+}
+
+/// Returns the `file://` URI of a path, with the characters URIs can't hold percent-encoded
+pub fn path_to_uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::from("file://");
+    if !path.starts_with('/') {
+        // Windows paths start with a drive letter
+        uri.push('/');
+    }
+
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' | b':' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    uri
+}
+
+/// Returns the path of a `file://` URI, `None` for other schemes
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    // Windows paths start with a drive letter, after the slash
+    let path = match path.as_bytes() {
+        [b'/', _, b':', ..] => &path[1..],
+        _ => path,
+    };
+
+    let mut bytes = vec![];
+    let mut iter = path.bytes();
+    while let Some(byte) = iter.next() {
+        if byte != b'%' {
+            bytes.push(byte);
+            continue;
+        }
+        let hex: Vec<u8> = iter.by_ref().take(2).collect();
+        let decoded = std::str::from_utf8(&hex)
+            .ok()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match decoded {
+            Some(byte) => bytes.push(byte),
+            None => {
+                bytes.push(b'%');
+                bytes.extend(hex);
+            }
+        }
+    }
+
+    Some(PathBuf::from(String::from_utf8_lossy(&bytes).to_string()))
+}
+
+/// Returns the text a snippet inserts, like `foo(x)` for `foo(${1:x})$0`.
+///
+/// Tab stops are left out, and placeholders are replaced by their default text or first choice.
+/// Variables are left out too, except for their default text.
+pub fn snippet_text(snippet: &str) -> String {
+    let chars: Vec<char> = snippet.chars().collect();
+    let mut text = String::new();
+    snippet_part(&chars, &mut 0, false, &mut text);
+    text
+}
+
+/// Adds the text of the snippet from `*i` to `text`, up to the `}` that closes it if `nested`
+fn snippet_part(chars: &[char], i: &mut usize, nested: bool, text: &mut String) {
+    while let Some(&c) = chars.get(*i) {
+        *i += 1;
+        match c {
+            '\\' if matches!(chars.get(*i), Some('$' | '}' | '\\')) => {
+                text.push(chars[*i]);
+                *i += 1;
+            }
+            '}' if nested => return,
+            '$' => match chars.get(*i) {
+                Some('{') => {
+                    *i += 1;
+                    snippet_placeholder(chars, i, text);
+                }
+                // A tab stop or variable without default text
+                Some(c) if c.is_alphanumeric() || *c == '_' => skip_snippet_name(chars, i),
+                _ => text.push('$'),
+            },
+            c => text.push(c),
+        }
+    }
+}
+
+/// Adds the text of the `${...}` at `*i`, after its `${`, and moves past its `}`
+fn snippet_placeholder(chars: &[char], i: &mut usize, text: &mut String) {
+    skip_snippet_name(chars, i);
+
+    match chars.get(*i) {
+        Some(':') => {
+            *i += 1;
+            snippet_part(chars, i, true, text);
+            return;
+        }
+        Some('|') => {
+            *i += 1;
+            while let Some(&c) = chars.get(*i) {
+                if c == ',' || c == '|' {
+                    break;
+                }
+                if c == '\\' && chars.get(*i + 1).is_some() {
+                    *i += 1;
+                }
+                text.push(chars[*i]);
+                *i += 1;
+            }
+        }
+        _ => (),
+    }
+
+    // The other choices, or a transform of a variable, are skipped
+    let mut depth = 1;
+    while let Some(&c) = chars.get(*i) {
+        *i += 1;
+        match c {
+            '\\' => *i += 1,
+            '{' => depth += 1,
+            '}' if depth == 1 => return,
+            '}' => depth -= 1,
+            _ => (),
+        }
+    }
+}
+
+/// Moves `*i` past the number of a tab stop or the name of a variable
+fn skip_snippet_name(chars: &[char], i: &mut usize) {
+    while chars
+        .get(*i)
+        .is_some_and(|c| c.is_alphanumeric() || *c == '_')
+    {
+        *i += 1;
+    }
+}
+
+/// Writes a message with the `Content-Length` header of the base protocol
+fn write_message(out: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(out, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    out.flush()
+}
+
+/// Reads the next message, `None` once the stream is closed
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let Some(length) = length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Message without a Content-Length",
+        ));
+    };
+
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Sends the messages of a server to the editor until its output is closed
+fn read_messages(
+    stdout: impl Read,
+    language: String,
+    generation: usize,
+    msg_sender: Sender<EditorEvent>,
+) {
+    let mut input = BufReader::new(stdout);
+
+    while let Ok(Some(message)) = read_message(&mut input) {
+        let event = EditorEvent::LanguageServer {
+            language: language.clone(),
+            generation,
+            message,
+        };
+        if msg_sender.send(event).is_err() {
+            return;
+        }
+    }
+
+    let _ = msg_sender.send(EditorEvent::LanguageServerStopped {
+        language,
+        generation,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{document::Change, language};
+    use std::sync::mpsc::{self, Receiver};
+
+    fn chars(text: &str) -> Vec<char> {
+        text.chars().collect()
+    }
+
+    fn change(kind: EditKind, pos: usize, ch: char) -> Change {
+        Change { kind, pos, ch }
+    }
+
+    fn run_ranges(changes: &[Change]) -> Vec<(usize, usize, String)> {
+        runs(changes)
+            .into_iter()
+            .map(|run| (run.start, run.end, run.text))
+            .collect()
+    }
+
+    #[test]
+    fn groups_changes_into_runs() {
+        use EditKind::{Delete, Insert};
+
+        let typed = [change(Insert, 3, 'a'), change(Insert, 4, 'b')];
+        assert_eq!(run_ranges(&typed), [(3, 3, "ab".to_string())]);
+
+        let deleted = [change(Delete, 3, 'a'), change(Delete, 3, 'b')];
+        assert_eq!(run_ranges(&deleted), [(3, 5, String::new())]);
+
+        let backspaced = [change(Delete, 4, 'b'), change(Delete, 3, 'a')];
+        assert_eq!(run_ranges(&backspaced), [(3, 5, String::new())]);
+
+        // Typing somewhere else starts a new run, typing over deleted text replaces it
+        let apart = [
+            change(Insert, 3, 'a'),
+            change(Insert, 0, 'b'),
+            change(Delete, 5, 'c'),
+            change(Insert, 5, 'd'),
+            change(Delete, 5, 'd'),
+        ];
+        assert_eq!(
+            run_ranges(&apart),
+            [
+                (3, 3, "a".to_string()),
+                (0, 0, "b".to_string()),
+                (5, 6, "d".to_string()),
+                (5, 6, String::new())
+            ]
+        );
+    }
+
+    #[test]
+    fn converts_positions() {
+        let text = chars("ab\n😀c\n");
+        for (pos, line, utf16, utf32) in [(0, 0, 0, 0), (2, 0, 2, 2), (4, 1, 2, 1), (5, 1, 3, 2)] {
+            let position = lsp_position(&text, pos, Encoding::Utf16);
+            assert_eq!(position, json!({ "line": line, "character": utf16 }));
+            assert_eq!(text_position(&text, &position, Encoding::Utf16), Some(pos));
+
+            let position = lsp_position(&text, pos, Encoding::Utf32);
+            assert_eq!(position, json!({ "line": line, "character": utf32 }));
+            assert_eq!(text_position(&text, &position, Encoding::Utf32), Some(pos));
+        }
+
+        // Positions past the end of a line or of the text are clamped
+        let past_line = json!({ "line": 0, "character": 9 });
+        assert_eq!(text_position(&text, &past_line, Encoding::Utf16), Some(2));
+        let past_text = json!({ "line": 5, "character": 0 });
+        assert_eq!(text_position(&text, &past_text, Encoding::Utf16), Some(6));
+        assert_eq!(
+            lsp_position(&text, 99, Encoding::Utf16),
+            json!({ "line": 2, "character": 0 })
+        );
+        assert_eq!(text_position(&text, &json!({}), Encoding::Utf16), None);
+    }
+
+    #[test]
+    fn strips_snippets_down_to_their_text() {
+        assert_eq!(snippet_text("foo(${1:x}, ${2})$0"), "foo(x, )");
+        assert_eq!(snippet_text("if ${1:cond} {\n\t$0\n}"), "if cond {\n\t\n}");
+        assert_eq!(snippet_text("${1:a ${2:b} c}d"), "a b cd");
+        assert_eq!(snippet_text("${1|one,two|} ${2|a\\,b,c|}"), "one a,b");
+        assert_eq!(snippet_text("\\$1 costs \\} $"), "$1 costs } $");
+        assert_eq!(snippet_text("$TM_FILENAME ${TM_SELECTED_TEXT:sel}"), " sel");
+        assert_eq!(snippet_text("${TM_FILENAME/(.*)/${1:/upcase}/}."), ".");
+    }
+
+    #[test]
+    fn converts_uris() {
+        let path = Path::new("/home/a b/ü%.rs");
+        let uri = path_to_uri(path);
+        assert_eq!(uri, "file:///home/a%20b/%C3%BC%25.rs");
+        assert_eq!(uri_to_path(&uri).as_deref(), Some(path));
+
+        assert_eq!(
+            uri_to_path("file:///C:/src/a.rs"),
+            Some(PathBuf::from("C:/src/a.rs"))
+        );
+        // Broken escapes are kept as they are
+        assert_eq!(uri_to_path("file:///a%zz"), Some(PathBuf::from("/a%zz")));
+        assert_eq!(uri_to_path("https://example.com/a"), None);
+    }
+
+    /// The server of `tests/support/fake_language_server.rs`, built by `cargo test` as an example
+    fn fake_server(args: &[&str]) -> ServerConfig {
+        let mut dir = std::env::current_exe().unwrap();
+        dir.pop();
+        if dir.ends_with("deps") {
+            dir.pop();
+        }
+        let command = dir.join("examples/fake_language_server");
+        assert!(
+            command.exists(),
+            "{} is built by `cargo test`",
+            command.display()
+        );
+        ServerConfig {
+            command: command.display().to_string(),
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+        }
+    }
+
+    /// A document open in the fake server, with the messages of the server read like the editor
+    /// does
+    struct Client {
+        servers: LanguageServers,
+        events: Receiver<EditorEvent>,
+        path: PathBuf,
+        document: Document,
+    }
+
+    impl Client {
+        fn open(args: &[&str], text: &str) -> Self {
+            let (sender, events) = mpsc::channel();
+            let configs = HashMap::from([("Rust".to_string(), fake_server(args))]);
+            let path = PathBuf::from("/revo-test/a b.rs");
+            let mut document = Document::from(text.to_string());
+            document.set_language(language::detect(&language::builtin(), &path, ""));
+
+            let mut client = Self {
+                servers: LanguageServers::new(&configs, None, sender),
+                events,
+                path,
+                document,
+            };
+            client.start();
+            client
+        }
+
+        /// Starts the server, and opens the document in it
+        fn start(&mut self) {
+            // Documents are opened once the server is initialized
+            self.sync();
+            while !self.servers.servers["rust"].initialized {
+                assert!(self.handle_message().is_none());
+            }
+            self.sync();
+        }
+
+        fn sync(&mut self) {
+            self.servers.sync(&self.path, &self.document).unwrap();
+        }
+
+        fn handle_message(&mut self) -> Option<ServerEvent> {
+            let timeout = Duration::from_secs(10);
+            match self
+                .events
+                .recv_timeout(timeout)
+                .expect("The server answers")
+            {
+                EditorEvent::LanguageServer {
+                    language,
+                    generation,
+                    message,
+                } => self.servers.handle_message(&language, generation, message),
+                EditorEvent::LanguageServerStopped {
+                    language,
+                    generation,
+                } => {
+                    assert!(!self.servers.stopped(&language, generation));
+                    None
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        fn next_event(&mut self) -> ServerEvent {
+            loop {
+                if let Some(event) = self.handle_message() {
+                    return event;
+                }
+            }
+        }
+
+        /// Returns the ranges of the next diagnostics of the server
+        fn diagnostics(&mut self) -> Vec<(usize, usize)> {
+            match self.next_event() {
+                ServerEvent::Diagnostics { diagnostics, .. } => diagnostics
+                    .iter()
+                    .map(|diagnostic| (diagnostic.start, diagnostic.end))
+                    .collect(),
+                _ => panic!("Expected diagnostics"),
+            }
+        }
+
+        fn request(&mut self, pos: Option<usize>, request: Request, method: &str) -> Value {
+            self.request_with(pos, request, method, json!({}))
+        }
+
+        fn request_with(
+            &mut self,
+            pos: Option<usize>,
+            request: Request,
+            method: &str,
+            params: Value,
+        ) -> Value {
+            let sent = format!("{request:?}");
+            self.servers
+                .request(&self.path, pos, request, method, params)
+                .unwrap();
+            match self.next_event() {
+                ServerEvent::Response {
+                    request, result, ..
+                } => {
+                    assert_eq!(format!("{request:?}"), sent);
+                    result
+                }
+                _ => panic!("Expected a response"),
+            }
+        }
+
+        /// Returns the range of an LSP range in the document
+        fn range(&self, range: &Value) -> (usize, usize) {
+            let text = self.document.chars();
+            let encoding = self.servers.servers["rust"].encoding;
+            let start = text_position(text, &range["start"], encoding).unwrap();
+            let end = text_position(text, &range["end"], encoding).unwrap();
+            (start, end)
+        }
+    }
+
+    #[test]
+    fn syncs_edits_with_the_server() {
+        for args in [&[][..], &["--utf-32"], &["--full"]] {
+            let mut client = Client::open(args, "😀 bad\n");
+            assert_eq!(client.diagnostics(), [(2, 5)], "{args:?}");
+
+            for (i, ch) in "bad ".chars().enumerate() {
+                client.document.insert(i, ch);
+            }
+            assert_eq!(client.servers.version(&client.path), Some(1));
+            client.sync();
+            assert_eq!(client.diagnostics(), [(0, 3), (6, 9)], "{args:?}");
+            assert_eq!(client.servers.version(&client.path), Some(2));
+
+            // Deleted with Backspace, then with Delete
+            client.document.remove(2);
+            client.document.remove(1);
+            client.document.remove(0);
+            client.document.remove(4);
+            client.document.remove(4);
+            client.sync();
+            assert_eq!(client.diagnostics(), [], "{args:?}");
+
+            // Several edits apart, sent at once
+            client.document.replace(1, 2, "bad");
+            client.document.insert(client.document.len() - 1, 'd');
+            client.sync();
+            assert_eq!(client.document.to_string(), " bad bd\n");
+            assert_eq!(client.diagnostics(), [(1, 4)], "{args:?}");
+        }
+    }
+
+    #[test]
+    fn answers_requests() {
+        let text = "fn foo() {}   \nfn main() { 😀foo(); }\n";
+        let mut client = Client::open(&[], text);
+        assert_eq!(client.diagnostics(), []);
+        let call = text.chars().position(|c| c == '😀').unwrap() + 1;
+
+        let hover = client.request(Some(call + 1), Request::Hover, "textDocument/hover");
+        assert_eq!(hover["contents"]["value"], "foo");
+
+        let definition = client.request(Some(call), Request::Definition, "textDocument/definition");
+        assert_eq!(
+            uri_to_path(definition["uri"].as_str().unwrap()),
+            Some(client.path.clone())
+        );
+        assert_eq!(client.range(&definition["range"]), (3, 6));
+
+        let params = json!({ "newName": "bar" });
+        let request = Request::Rename {
+            path: client.path.clone(),
+            revision: 0,
+        };
+        let rename = client.request_with(Some(call), request, "textDocument/rename", params);
+        let edits = rename["changes"][path_to_uri(&client.path)]
+            .as_array()
+            .unwrap()
+            .clone();
+        let ranges: Vec<(usize, usize)> = edits
+            .iter()
+            .map(|edit| client.range(&edit["range"]))
+            .collect();
+        assert_eq!(ranges, [(3, 6), (call, call + 3)]);
+        assert!(edits.iter().all(|edit| edit["newText"] == "bar"));
+
+        let request = Request::Formatting {
+            path: client.path.clone(),
+            revision: 0,
+        };
+        let formatting = client.request(None, request, "textDocument/formatting");
+        let edits = formatting.as_array().unwrap();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(client.range(&edits[0]["range"]), (11, 14));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stops_servers_without_waiting_for_them() {
+        // A server that never answers, nor exits when asked to
+        let config = ServerConfig {
+            command: "sleep".to_string(),
+            args: vec!["5".to_string()],
+        };
+        let (sender, _events) = mpsc::channel();
+        let configs = HashMap::from([("Rust".to_string(), config)]);
+        let mut servers = LanguageServers::new(&configs, None, sender);
+        let path = PathBuf::from("/revo-test/a.rs");
+        let mut document = Document::from("fn main() {}\n".to_string());
+        document.set_language(language::detect(&language::builtin(), &path, ""));
+        servers.sync(&path, &document).unwrap();
+
+        let start = Instant::now();
+        assert_eq!(servers.set_root(std::env::temp_dir()), ["rust"]);
+        drop(servers);
+        assert!(start.elapsed() < EXIT_TIMEOUT);
+    }
+
+    #[test]
+    fn restarts_servers_in_the_new_root() {
+        let mut client = Client::open(&[], "bad\n");
+        assert_eq!(client.diagnostics(), [(0, 3)]);
+
+        let root = std::env::temp_dir();
+        assert_eq!(client.servers.set_root(root.clone()), ["rust"]);
+        assert!(client.servers.set_root(root).is_empty());
+
+        // The messages of the stopped server are left out
+        client.start();
+        assert_eq!(client.diagnostics(), [(0, 3)]);
+        client.document.insert(0, 'a');
+        client.sync();
+        assert_eq!(client.diagnostics(), []);
+    }
+}
@@ -2,14 +2,20 @@ use std::{
     env,
     io::{self, stdout},
     panic,
-    path::PathBuf,
+    path::{Path, PathBuf},
     process::exit,
-    sync::mpsc::{self, Sender},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Sender},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
 use crossterm::{
     cursor::{SetCursorStyle, Show},
-    event::{read, Event, KeyCode, KeyEvent, KeyModifiers},
+    event::{poll, read},
     execute,
     style::ResetColor,
     terminal::{disable_raw_mode, EnableLineWrap, LeaveAlternateScreen},
@@ -17,11 +23,33 @@ use crossterm::{
 use editor::{Editor, EditorEvent};
 
 mod buffer;
+mod command;
+mod diagnostics;
+mod diff;
+mod document;
 mod editor;
+mod explorer;
+mod file_finder;
+mod fold;
+mod grammar;
+mod highlight;
+mod history;
+mod language;
+mod layout;
+mod lsp;
+mod path_completion;
+mod positions;
+mod save;
+mod selector;
+mod session;
 mod settings;
+mod swap;
+mod syntax;
 mod terminal;
 mod theme;
 mod vec_ext;
+mod watcher;
+mod word_completion;
 
 fn main() -> io::Result<()> {
     setup_panic_handler();
@@ -32,66 +60,174 @@ fn main() -> io::Result<()> {
     let (send, recv) = mpsc::channel();
     let input_send = send.clone();
 
-    parse_args(send.clone())?;
+    // The last session is restored when there is nothing else to open, and only then saved again
+    let use_session = !env::args().any(|arg| arg == "--no-session");
+    let args = env::args().skip(1).collect();
+    let opened_any = parse_args(args, &env::current_dir()?, send.clone())?;
+    if use_session && !opened_any {
+        send.send(EditorEvent::RestoreSession).unwrap();
+    }
 
     // Start input handeling thread
-    let input_thread = std::thread::spawn(move || input(input_send));
+    let running = Arc::new(AtomicBool::new(true));
+    let input_running = running.clone();
+    let input_thread = thread::spawn(move || input(input_send, input_running));
+
+    // Start the swap file timer, unless swap files are turned off
+    let timer_thread = (settings.swap_interval > 0).then(|| {
+        let timer_send = send.clone();
+        let timer_running = running.clone();
+        let interval = Duration::from_secs(settings.swap_interval);
+        thread::spawn(move || swap_timer(timer_send, timer_running, interval))
+    });
 
     {
         let mut editor = Editor::new(settings, stdout(), send, recv)?;
 
-        editor.start()?;
+        let result = editor.start();
+        running.store(false, Ordering::Relaxed);
+        result?;
     }
 
-    // NOTE: Editor needs to be dropped before we try to join input_thread
+    // NOTE: Editor needs to be dropped before we try to join input_thread and timer_thread
     input_thread
         .join()
         .expect("Failed while joining Input Thread");
+    if let Some(timer_thread) = timer_thread {
+        timer_thread
+            .join()
+            .expect("Failed while joining Swap Timer Thread");
+    }
 
     Ok(())
 }
 
-/// If a file or folder was passed as argument sends `EditorEvent::OpenFile` to the editor
-fn parse_args(msg_sender: Sender<EditorEvent>) -> io::Result<()> {
-    // Get the arguments passed to the program
-    let args: Vec<String> = env::args().collect();
+/// Sends `EditorEvent::OpenFile`, `EditorEvent::OpenFileAt` or `EditorEvent::OpenFolder` to the editor for every file or folder
+/// passed as argument.
+///
+/// Files can be followed by a position as `file:line` or `file:line:col`, or be preceded by `+line`.
+/// With `-R` all files are opened read-only, with `EditorEvent::OpenFileReadOnly`.
+/// `--no-session` is skipped, it's handled by `main`.
+///
+/// Relative paths are resolved from `start_dir`, the folder the editor was started from, so they
+/// still point to the same files after a folder argument changed the root of the editor.
+///
+/// Returns true if any file or folder was passed.
+fn parse_args(
+    mut args: Vec<String>,
+    start_dir: &Path,
+    msg_sender: Sender<EditorEvent>,
+) -> io::Result<bool> {
+    let read_only = args.iter().any(|arg| arg == "-R");
+    args.retain(|arg| arg != "-R" && arg != "--no-session");
+    let opened_any = args.iter().any(|arg| !arg.starts_with('+'));
+
+    let mut line_arg = None;
+
+    for arg in args {
+        if let Some(line) = arg.strip_prefix('+') {
+            match line.parse::<usize>() {
+                Ok(line) => line_arg = Some(line),
+                Err(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Invalid line number: {arg}"),
+                    ))
+                }
+            }
+            continue;
+        }
+
+        let (path, position) = parse_file_position(&arg);
+        let path = start_dir.join(path);
+
+        if path.file_name().is_none() || (path.exists() && path.is_dir()) {
+            // Opening a folder
+            msg_sender.send(EditorEvent::OpenFolder(path)).unwrap();
+        } else {
+            // Opening a file
+            let position = match (line_arg.take(), position) {
+                (_, Some(position)) => Some(position),
+                (Some(line), None) => Some((line, 1)),
+                (None, None) => None,
+            };
+            let event = match position {
+                _ if read_only => EditorEvent::OpenFileReadOnly { path, position },
+                Some((line, column)) => EditorEvent::OpenFileAt { path, line, column },
+                None => EditorEvent::OpenFile(path),
+            };
+            msg_sender.send(event).unwrap();
+        }
+    }
 
-    if args.len() < 2 {
-        return Ok(());
-    } else if args.len() > 2 {
+    if line_arg.is_some() {
         return Err(io::Error::new(
             io::ErrorKind::InvalidInput,
-            "Revo currently only supports opening one file at a time",
+            "Expected a file after +line",
         ));
     }
 
-    let path = PathBuf::from(&args[1]);
+    Ok(opened_any)
+}
+
+/// Splits a `file:line:col` or `file:line` argument into the path and the 1-indexed position.
+///
+/// NOTE: If a file with the full name exists it's opened as is, so `a:1` can still be opened.
+fn parse_file_position(arg: &str) -> (PathBuf, Option<(usize, usize)>) {
+    let path = PathBuf::from(arg);
+    if path.exists() {
+        return (path, None);
+    }
+
+    // Compiler output usually ends the location with a ':'
+    let trimmed = arg.strip_suffix(':').unwrap_or(arg);
 
-    if path.file_name().is_none() || (path.exists() && path.is_dir()) {
-        // Opening a folder
-        Err(io::Error::new(
-            io::ErrorKind::InvalidInput,
-            "Opening directories is not yet supported",
-        ))
-    } else {
-        // Opening a file
-        msg_sender.send(EditorEvent::OpenFile(path)).unwrap();
-        Ok(())
+    let mut parts = trimmed.rsplitn(3, ':');
+    let last = parts.next().map(|p| p.parse::<usize>());
+    let second_last = parts.next().map(|p| p.parse::<usize>());
+    let rest = parts.next();
+
+    match (rest, second_last, last) {
+        (Some(file), Some(Ok(line)), Some(Ok(col))) => (PathBuf::from(file), Some((line, col))),
+        (None, Some(_), Some(Ok(line))) | (Some(_), Some(Err(_)), Some(Ok(line))) => {
+            let file = &trimmed[..trimmed.rfind(':').unwrap()];
+            (PathBuf::from(file), Some((line, 1)))
+        }
+        _ => (path, None),
     }
 }
 
-fn input(out: Sender<EditorEvent>) {
-    loop {
+/// Forwards terminal events to the editor until `running` is cleared.
+///
+/// NOTE: We poll with a timeout instead of blocking on `read`, so the thread notices when the editor
+///       quits without waiting for one more key press.
+fn input(out: Sender<EditorEvent>, running: Arc<AtomicBool>) {
+    while running.load(Ordering::Relaxed) {
+        match poll(Duration::from_millis(50)) {
+            Ok(true) => (),
+            Ok(false) => continue,
+            Err(_) => break,
+        }
+
         if let Ok(event) = read() {
-            if let Event::Key(KeyEvent {
-                code: KeyCode::Char('q'),
-                modifiers: KeyModifiers::CONTROL,
-                ..
-            }) = event
-            {
-                out.send(EditorEvent::Input(event)).unwrap();
+            if out.send(EditorEvent::Input(event)).is_err() {
                 break;
-            } else if let Err(_) = out.send(EditorEvent::Input(event)) {
+            }
+        }
+    }
+}
+
+/// Sends `EditorEvent::WriteSwapFiles` every `interval` until `running` is cleared.
+fn swap_timer(out: Sender<EditorEvent>, running: Arc<AtomicBool>, interval: Duration) {
+    let mut last_tick = Instant::now();
+
+    // Short sleeps, so the thread notices when the editor quits
+    while running.load(Ordering::Relaxed) {
+        thread::sleep(Duration::from_millis(50));
+
+        if last_tick.elapsed() >= interval {
+            last_tick = Instant::now();
+            if out.send(EditorEvent::WriteSwapFiles).is_err() {
                 break;
             }
         }
@@ -123,3 +259,77 @@ fn setup_panic_handler() {
         println!("Panic payload: {:?}", panic_info.payload());
     }));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_file_position_line_and_column() {
+        let parsed = parse_file_position("src/missing.rs:12:5");
+        assert_eq!(parsed, (PathBuf::from("src/missing.rs"), Some((12, 5))));
+    }
+
+    #[test]
+    fn parse_file_position_line_only() {
+        let parsed = parse_file_position("src/missing.rs:12");
+        assert_eq!(parsed, (PathBuf::from("src/missing.rs"), Some((12, 1))));
+    }
+
+    #[test]
+    fn parse_file_position_trailing_colon() {
+        let parsed = parse_file_position("src/missing.rs:12:5:");
+        assert_eq!(parsed, (PathBuf::from("src/missing.rs"), Some((12, 5))));
+    }
+
+    #[test]
+    fn parse_file_position_colon_in_name() {
+        let parsed = parse_file_position("a:b:3");
+        assert_eq!(parsed, (PathBuf::from("a:b"), Some((3, 1))));
+    }
+
+    #[test]
+    fn parse_file_position_without_position() {
+        assert_eq!(
+            parse_file_position("missing.rs"),
+            (PathBuf::from("missing.rs"), None)
+        );
+        assert_eq!(
+            parse_file_position("missing.rs:x"),
+            (PathBuf::from("missing.rs:x"), None)
+        );
+    }
+
+    #[test]
+    fn parse_file_position_existing_file_is_kept_whole() {
+        let path = env::temp_dir().join(format!("revo-test-{}:3", std::process::id()));
+        std::fs::write(&path, "").unwrap();
+        let parsed = parse_file_position(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(parsed, (path, None));
+    }
+
+    #[test]
+    fn parse_args_resolves_paths_from_the_start_folder() {
+        let start_dir = env::temp_dir().join(format!("revo-args-{}", std::process::id()));
+        std::fs::create_dir_all(start_dir.join("proj")).unwrap();
+        std::fs::write(start_dir.join("proj/notes.txt"), "").unwrap();
+
+        // `revo proj proj/notes.txt` opens the file in `proj`, not `proj/proj/notes.txt`
+        let (send, recv) = mpsc::channel();
+        let args = vec!["proj".to_string(), "proj/notes.txt:2".to_string()];
+        let opened_any = parse_args(args, &start_dir, send).unwrap();
+        let events: Vec<EditorEvent> = recv.try_iter().collect();
+        std::fs::remove_dir_all(&start_dir).unwrap();
+
+        assert!(opened_any);
+        assert!(matches!(
+            &events[..],
+            [
+                EditorEvent::OpenFolder(folder),
+                EditorEvent::OpenFileAt { path, line: 2, column: 1 },
+            ] if *folder == start_dir.join("proj") && *path == start_dir.join("proj/notes.txt")
+        ));
+    }
+}
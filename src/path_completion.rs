@@ -0,0 +1,127 @@
+#![allow(dead_code)]
+use std::{fs, path::Path};
+
+/// Result of completing the last part of a typed path
+pub struct PathCompletion {
+    /// The typed text, extended as far as all the matching entries agree
+    pub text: String,
+    /// Names of the matching entries, when there is more than one
+    pub candidates: Vec<String>,
+}
+
+/// Completes the last part of `text` with the entries of the folder it points into. Relative paths
+/// start from `root`, and folders get a trailing `/`.
+///
+/// NOTE: Hidden entries are only listed once the typed name starts with a `.`
+pub fn complete(root: &Path, text: &str) -> PathCompletion {
+    let (dir_part, prefix) = match text.rfind('/') {
+        Some(i) => (&text[..=i], &text[i + 1..]),
+        None => ("", text),
+    };
+    let dir = root.join(dir_part);
+
+    let mut matches: Vec<String> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .flatten()
+            .filter_map(|entry| {
+                let mut name = entry.file_name().to_string_lossy().to_string();
+                if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.'))
+                {
+                    return None;
+                }
+                if entry.path().is_dir() {
+                    name.push('/');
+                }
+                Some(name)
+            })
+            .collect(),
+        Err(_) => vec![],
+    };
+    matches.sort();
+
+    let completed = match matches.as_slice() {
+        [] => prefix.to_string(),
+        [only] => only.clone(),
+        [first, rest @ ..] => rest.iter().fold(first.clone(), |common, name| {
+            common
+                .chars()
+                .zip(name.chars())
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| a)
+                .collect()
+        }),
+    };
+
+    let candidates = if matches.len() > 1 { matches } else { vec![] };
+    PathCompletion {
+        text: format!("{dir_part}{completed}"),
+        candidates,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Folder with `src/main.rs`, `src/settings.rs`, `src/syntax/`, `README.md` and `.git/`
+    fn tree(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("revo-{name}-{}", std::process::id()));
+        fs::create_dir_all(root.join("src/syntax")).unwrap();
+        fs::create_dir_all(root.join(".git")).unwrap();
+        for file in ["src/main.rs", "src/settings.rs", "README.md"] {
+            fs::write(root.join(file), "").unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn completes_the_only_match() {
+        let root = tree("only-match");
+        let completion = complete(&root, "READ");
+        let folder = complete(&root, "s");
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(completion.text, "README.md");
+        assert!(completion.candidates.is_empty());
+        assert_eq!(folder.text, "src/");
+    }
+
+    #[test]
+    fn extends_to_the_common_prefix_and_lists_the_matches() {
+        let root = tree("common-prefix");
+        let completion = complete(&root, "src/s");
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(completion.text, "src/s");
+        assert_eq!(completion.candidates, ["settings.rs", "syntax/"]);
+    }
+
+    #[test]
+    fn hidden_entries_need_a_dot() {
+        let root = tree("hidden");
+        let all = complete(&root, "");
+        let hidden = complete(&root, ".");
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(all.candidates, ["README.md", "src/"]);
+        assert_eq!(hidden.text, ".git/");
+    }
+
+    #[test]
+    fn absolute_paths_ignore_the_root() {
+        let root = tree("absolute");
+        let text = format!("{}/src/m", root.display());
+        let completion = complete(Path::new("/nowhere"), &text);
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(completion.text, format!("{}/src/main.rs", root.display()));
+    }
+
+    #[test]
+    fn missing_folders_complete_nothing() {
+        let completion = complete(Path::new("/nowhere"), "missing/fi");
+        assert_eq!(completion.text, "missing/fi");
+        assert!(completion.candidates.is_empty());
+    }
+}
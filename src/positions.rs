@@ -0,0 +1,158 @@
+#![allow(dead_code)]
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::save::{self, Backup};
+
+/// Most files whose position is remembered, older ones are forgotten
+const MAX_FILES: usize = 500;
+
+/// Where the cursor and the view of a file were left
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilePosition {
+    /// 0-indexed cursor position
+    pub line: usize,
+    pub column: usize,
+    pub scroll_x: usize,
+    pub scroll_y: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Entry {
+    path: PathBuf,
+    #[serde(flatten)]
+    position: FilePosition,
+}
+
+/// Last positions of recently edited files, most recent first, kept in `~/.revo/positions.json`
+pub struct FilePositions {
+    file: PathBuf,
+    entries: Vec<Entry>,
+}
+
+impl FilePositions {
+    /// Reads the store from `file`. Files that were deleted or renamed since are forgotten, and a
+    /// missing or broken store starts out empty.
+    pub fn load(file: PathBuf) -> Self {
+        let mut entries: Vec<Entry> = fs::read_to_string(&file)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        entries.retain(|entry| entry.path.is_file());
+
+        Self { file, entries }
+    }
+
+    pub fn get(&self, path: &Path) -> Option<FilePosition> {
+        let path = key(path);
+        self.entries
+            .iter()
+            .find(|entry| entry.path == path)
+            .map(|entry| entry.position)
+    }
+
+    /// Remembers the position of `path`, making it the most recent file
+    pub fn record(&mut self, path: &Path, position: FilePosition) {
+        let path = key(path);
+        self.entries.retain(|entry| entry.path != path);
+        self.entries.insert(0, Entry { path, position });
+        self.entries.truncate(MAX_FILES);
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        if let Some(dir) = self.file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(&self.entries)?;
+        save::write_atomic(&self.file, &json, &Backup::None)
+    }
+}
+
+/// Files are remembered by their real path, so a file opened through a symlink is found either way
+fn key(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(line: usize, column: usize) -> FilePosition {
+        FilePosition {
+            line,
+            column,
+            scroll_x: 0,
+            scroll_y: line.saturating_sub(10),
+        }
+    }
+
+    #[test]
+    fn saves_and_restores_positions() {
+        let dir = std::env::temp_dir().join(format!("revo-positions-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a.txt"), dir.join("b.txt"));
+        fs::write(&a, "a\n").unwrap();
+        fs::write(&b, "b\n").unwrap();
+
+        let file = dir.join("store").join("positions.json");
+        let mut positions = FilePositions::load(file.clone());
+        positions.record(&a, position(1, 2));
+        positions.record(&b, position(30, 4));
+        positions.record(&a, position(12, 0));
+        positions.save().unwrap();
+
+        let positions = FilePositions::load(file.clone());
+        assert_eq!(positions.get(&a), Some(position(12, 0)));
+        assert_eq!(positions.get(&b), Some(position(30, 4)));
+        assert_eq!(positions.get(&dir.join("c.txt")), None);
+        // Found by the real path of the file
+        assert_eq!(
+            positions.get(&dir.join(".").join("b.txt")),
+            Some(position(30, 4))
+        );
+
+        // Deleted files are forgotten
+        fs::remove_file(&b).unwrap();
+        let positions = FilePositions::load(file);
+        assert_eq!(positions.get(&b), None);
+        assert_eq!(positions.entries.len(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn ignores_missing_and_corrupt_stores() {
+        let dir = std::env::temp_dir().join(format!("revo-positions-bad-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let a = dir.join("a.txt");
+        fs::write(&a, "a\n").unwrap();
+
+        let missing = FilePositions::load(dir.join("missing.json"));
+        assert!(missing.entries.is_empty());
+
+        let file = dir.join("positions.json");
+        for corrupt in [
+            "",
+            "{",
+            "not json",
+            "{\"path\": 1}",
+            "[{\"path\": \"a.txt\"}]",
+        ] {
+            fs::write(&file, corrupt).unwrap();
+            let mut positions = FilePositions::load(file.clone());
+            assert!(positions.entries.is_empty());
+
+            // And is replaced on the next save
+            positions.record(&a, position(3, 1));
+            positions.save().unwrap();
+            let positions = FilePositions::load(file.clone());
+            assert_eq!(positions.get(&a), Some(position(3, 1)));
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,262 @@
+#![allow(dead_code)]
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use uuid::Uuid;
+
+/// Where a copy of the previous version of a file is kept when it is saved over
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Backup {
+    None,
+    /// Next to the file, as `name~`
+    Adjacent,
+    /// In the given folder, named after the file's full path
+    Directory(PathBuf),
+}
+
+/// Saves `contents` to `path` without ever leaving a half written file behind.
+///
+/// The text is written to a temporary file in the same folder, synced to disk, and then renamed
+/// over the original. If `path` is a symlink, the file it points to is replaced instead of the
+/// link. The permissions (and on Unix the owner) of the original file are kept.
+pub fn write_atomic(path: &Path, contents: &str, backup: &Backup) -> io::Result<()> {
+    let target = resolve_symlinks(path)?;
+    let metadata = fs::metadata(&target).ok();
+
+    if metadata.is_some() {
+        make_backup(&target, backup)?;
+    }
+
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let name = target
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Path has no file name"))?;
+    let temp_path = dir.join(format!(".{}.{}.tmp", name.to_string_lossy(), Uuid::new_v4()));
+
+    let result = (|| {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)?;
+        // Before writing, so the text is never readable by more users than the original was
+        if let Some(metadata) = &metadata {
+            file.set_permissions(metadata.permissions())?;
+            keep_owner(&file, metadata);
+        }
+
+        file.write_all(contents.as_bytes())?;
+
+        file.sync_all()?;
+        fs::rename(&temp_path, &target)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+        return result;
+    }
+
+    // Make the rename itself durable. Not every platform can open a folder, so this is best effort
+    if let Ok(dir) = File::open(&dir) {
+        let _ = dir.sync_all();
+    }
+
+    Ok(())
+}
+
+/// Follows symlinks until a path that is not a link, which may not exist yet
+fn resolve_symlinks(path: &Path) -> io::Result<PathBuf> {
+    let mut path = path.to_path_buf();
+
+    // Bounded, in case of a symlink loop
+    for _ in 0..40 {
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                let link = fs::read_link(&path)?;
+                path = match path.parent() {
+                    Some(parent) => parent.join(link),
+                    None => link,
+                };
+            }
+            _ => return Ok(path),
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "Too many levels of symbolic links",
+    ))
+}
+
+#[cfg(unix)]
+fn keep_owner(file: &File, metadata: &fs::Metadata) {
+    use std::os::unix::fs::{fchown, MetadataExt};
+
+    // Only works when we own the file or are root, a new file owned by us is fine otherwise
+    let _ = fchown(file, Some(metadata.uid()), Some(metadata.gid()));
+}
+
+#[cfg(not(unix))]
+fn keep_owner(_file: &File, _metadata: &fs::Metadata) {}
+
+/// Returns true if we are allowed to write to the existing file at `path`, which depends on who
+/// owns it and not only on its write bits
+#[cfg(target_os = "linux")]
+pub fn is_writable(path: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+
+    let Ok(c_path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    unsafe { libc::access(c_path.as_ptr(), libc::W_OK) == 0 }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn is_writable(path: &Path) -> bool {
+    OpenOptions::new().write(true).open(path).is_ok()
+}
+
+fn make_backup(target: &Path, backup: &Backup) -> io::Result<()> {
+    let backup_path = match backup {
+        Backup::None => return Ok(()),
+        Backup::Adjacent => {
+            let mut name = target.file_name().unwrap_or_default().to_os_string();
+            name.push("~");
+            target.with_file_name(name)
+        }
+        Backup::Directory(dir) => {
+            fs::create_dir_all(dir)?;
+
+            // The full path keeps backups of files with the same name apart
+            dir.join(flat_file_name(&target.canonicalize()?))
+        }
+    };
+
+    fs::copy(target, backup_path).map(|_| ())
+}
+
+/// Turns a full path into a single file name, so files from any folder can be kept side by side
+pub fn flat_file_name(path: &Path) -> String {
+    path.to_string_lossy()
+        .chars()
+        .map(|c| if c == '/' || c == '\\' || c == ':' { '%' } else { c })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a new empty folder for a test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("revo-save-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn replaces_the_file_at_once() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = test_dir("atomic");
+        let path = dir.join("file.txt");
+        fs::write(&path, "old").unwrap();
+        let before = fs::metadata(&path).unwrap().ino();
+
+        write_atomic(&path, "new", &Backup::None).unwrap();
+        let after = fs::metadata(&path).unwrap().ino();
+        let files: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(contents, "new");
+        // No temporary file is left behind
+        assert_eq!(files, vec![path]);
+        // The file was renamed over, not written in place
+        assert_ne!(before, after);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn writes_the_target_of_symlinks() {
+        let dir = test_dir("symlink");
+        let target = dir.join("target.txt");
+        let link = dir.join("link.txt");
+        fs::write(&target, "old").unwrap();
+        std::os::unix::fs::symlink("target.txt", &link).unwrap();
+
+        write_atomic(&link, "new", &Backup::None).unwrap();
+        let is_link = fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink();
+        let contents = fs::read_to_string(&target).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(is_link);
+        assert_eq!(contents, "new");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_the_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = test_dir("permissions");
+        let path = dir.join("script.sh");
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o750)).unwrap();
+
+        write_atomic(&path, "new", &Backup::None).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(mode & 0o777, 0o750);
+    }
+
+    #[test]
+    fn backs_up_the_previous_version() {
+        let dir = test_dir("backup");
+        let path = dir.join("file.txt");
+        let backups = dir.join("backups");
+
+        // A new file has nothing to back up
+        write_atomic(&path, "first", &Backup::Adjacent).unwrap();
+        let adjacent_of_new = dir.join("file.txt~").exists();
+
+        write_atomic(&path, "second", &Backup::Adjacent).unwrap();
+        let adjacent = fs::read_to_string(dir.join("file.txt~")).unwrap();
+
+        write_atomic(&path, "third", &Backup::Directory(backups.clone())).unwrap();
+        let flat_name = flat_file_name(&path.canonicalize().unwrap());
+        let in_directory = fs::read_to_string(backups.join(flat_name)).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(!adjacent_of_new);
+        assert_eq!(adjacent, "first");
+        assert_eq!(in_directory, "second");
+        assert_eq!(contents, "third");
+    }
+
+    #[test]
+    fn is_writable_checks_the_file() {
+        let path = std::env::temp_dir().join(format!("revo-writable-{}", std::process::id()));
+        fs::write(&path, "").unwrap();
+        let writable = is_writable(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(writable);
+        assert!(!is_writable(&path.join("missing")));
+    }
+}
@@ -0,0 +1,367 @@
+#![allow(dead_code)]
+
+/// A single entry shown by a `BufferLogic::Selector` buffer
+pub struct SelectorItem {
+    pub label: String,
+    pub detail: Option<String>,
+    /// Sent back in `BufferEvent::ResumeEvent` when this item is chosen
+    pub value: String,
+}
+
+impl SelectorItem {
+    pub fn new(label: impl Into<String>, detail: Option<String>) -> Self {
+        let label = label.into();
+
+        Self {
+            value: label.clone(),
+            label,
+            detail,
+        }
+    }
+
+    pub fn with_value(mut self, value: impl Into<String>) -> Self {
+        self.value = value.into();
+        self
+    }
+}
+
+/// An item that matched the current query
+struct Match {
+    index: usize,
+    score: i64,
+    /// Char indices in the label that matched the query
+    positions: Vec<usize>,
+}
+
+const SCORE_MATCH: i64 = 16;
+const BONUS_WORD_START: i64 = 10;
+const BONUS_CONSECUTIVE: i64 = 12;
+const BONUS_FIRST_CHAR: i64 = 8;
+const PENALTY_GAP: i64 = 1;
+
+/// Scores how well `query` matches `text` as a subsequence (ignoring case).
+///
+/// Returns `None` if not all characters of `query` are present in `text` in order, otherwise the
+/// best score and the char indices of `text` that produced it.
+pub fn fuzzy_match(query: &[char], text: &str) -> Option<(i64, Vec<usize>)> {
+    let text: Vec<char> = text.chars().collect();
+
+    if query.is_empty() {
+        return Some((0, vec![]));
+    }
+
+    // Quick reject before doing the full scoring
+    let mut query_iter = query.iter().peekable();
+    for ch in text.iter() {
+        if query_iter.peek().is_some_and(|q| eq_ignore_case(**q, *ch)) {
+            query_iter.next();
+        }
+    }
+    if query_iter.peek().is_some() {
+        return None;
+    }
+
+    let n = text.len();
+    let m = query.len();
+
+    let bonus: Vec<i64> = (0..n)
+        .map(|j| {
+            let prev = if j == 0 { None } else { Some(text[j - 1]) };
+            match prev {
+                None => BONUS_FIRST_CHAR + BONUS_WORD_START,
+                Some(p) if is_separator(p) => BONUS_WORD_START,
+                Some(p) if p.is_lowercase() && text[j].is_uppercase() => BONUS_WORD_START,
+                _ => 0,
+            }
+        })
+        .collect();
+
+    // scores[i][j] is the best score with query[i] matched at text[j]
+    let mut scores = vec![vec![None; n]; m];
+    let mut from = vec![vec![0usize; n]; m];
+
+    for j in 0..n {
+        if eq_ignore_case(query[0], text[j]) {
+            scores[0][j] = Some(SCORE_MATCH + bonus[j] - PENALTY_GAP * j as i64);
+        }
+    }
+
+    for i in 1..m {
+        // Best `score - gap penalty` seen so far, for a previous match at least 2 chars back
+        let mut best_gapped: Option<(i64, usize)> = None;
+
+        for j in i..n {
+            if j >= 2 {
+                if let Some(prev) = scores[i - 1][j - 2] {
+                    let candidate = prev + PENALTY_GAP * (j - 2) as i64;
+                    if best_gapped.is_none_or(|(best, _)| candidate > best) {
+                        best_gapped = Some((candidate, j - 2));
+                    }
+                }
+            }
+
+            if !eq_ignore_case(query[i], text[j]) {
+                continue;
+            }
+
+            let consecutive = scores[i - 1][j - 1].map(|s| (s + BONUS_CONSECUTIVE, j - 1));
+            let gapped = best_gapped.map(|(s, k)| (s - PENALTY_GAP * (j - 1) as i64, k));
+
+            let best = match (consecutive, gapped) {
+                (Some(c), Some(g)) => Some(if c.0 >= g.0 { c } else { g }),
+                (c, g) => c.or(g),
+            };
+
+            if let Some((score, k)) = best {
+                scores[i][j] = Some(score + SCORE_MATCH + bonus[j]);
+                from[i][j] = k;
+            }
+        }
+    }
+
+    let (mut j, score) = scores[m - 1]
+        .iter()
+        .enumerate()
+        .filter_map(|(j, s)| s.map(|s| (j, s)))
+        .max_by_key(|(_, s)| *s)?;
+
+    let mut positions = vec![j; m];
+    for i in (1..m).rev() {
+        j = from[i][j];
+        positions[i - 1] = j;
+    }
+
+    Some((score, positions))
+}
+
+fn eq_ignore_case(a: char, b: char) -> bool {
+    a == b || a.to_lowercase().eq(b.to_lowercase())
+}
+
+fn is_separator(ch: char) -> bool {
+    matches!(ch, ' ' | '/' | '\\' | '_' | '-' | '.' | ':')
+}
+
+/// State of a list picker, the query itself lives in the `BufferData` of the owning buffer
+pub struct Selector {
+    items: Vec<SelectorItem>,
+    query: Vec<char>,
+    matches: Vec<Match>,
+    /// Index into `matches`
+    selected: usize,
+    /// Index of the first match shown on screen
+    scroll: usize,
+}
+
+impl Selector {
+    pub fn new(items: Vec<SelectorItem>) -> Self {
+        let mut selector = Self {
+            items,
+            query: vec![],
+            matches: vec![],
+            selected: 0,
+            scroll: 0,
+        };
+        selector.refresh();
+        selector
+    }
+
+    /// Filters and ranks the items by how well they match `query`
+    pub fn filter(&mut self, query: &str) {
+        self.query = query.chars().collect();
+        self.refresh();
+        self.selected = 0;
+        self.scroll = 0;
+    }
+
+    /// Adds more items, keeping the current query and selection. Only the new items are scored,
+    /// and merged into the ranked matches.
+    pub fn extend(&mut self, items: Vec<SelectorItem>) {
+        let selected_index = self.matches.get(self.selected).map(|m| m.index);
+
+        let start = self.items.len();
+        self.items.extend(items);
+        let added = self.score(start);
+
+        // Both lists are ranked, and on equal scores the earlier items stay first
+        let mut merged = Vec::with_capacity(self.matches.len() + added.len());
+        let mut old = std::mem::take(&mut self.matches).into_iter().peekable();
+        let mut new = added.into_iter().peekable();
+        loop {
+            let take_old = match (old.peek(), new.peek()) {
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (Some(o), Some(n)) => self.query.is_empty() || o.score >= n.score,
+                (None, None) => break,
+            };
+            merged.extend(if take_old { old.next() } else { new.next() });
+        }
+        self.matches = merged;
+
+        if let Some(index) = selected_index {
+            self.selected = self
+                .matches
+                .iter()
+                .position(|m| m.index == index)
+                .unwrap_or(0);
+        }
+    }
+
+    fn refresh(&mut self) {
+        self.matches = self.score(0);
+    }
+
+    /// Returns the matches of the items from `start` on, ranked
+    fn score(&self, start: usize) -> Vec<Match> {
+        let mut matches: Vec<Match> = self.items[start..]
+            .iter()
+            .enumerate()
+            .filter_map(|(i, item)| {
+                fuzzy_match(&self.query, &item.label).map(|(score, positions)| Match {
+                    index: start + i,
+                    score,
+                    positions,
+                })
+            })
+            .collect();
+
+        // Best score first, ties keep the original order
+        if !self.query.is_empty() {
+            matches.sort_by_key(|m| std::cmp::Reverse(m.score));
+        }
+        matches
+    }
+
+    pub fn select_prev(&mut self, count: usize) {
+        self.selected = self.selected.saturating_sub(count);
+    }
+
+    pub fn select_next(&mut self, count: usize) {
+        let last = self.matches.len().saturating_sub(1);
+        self.selected = std::cmp::min(self.selected + count, last);
+    }
+
+    pub fn select_first(&mut self) {
+        self.selected = 0;
+    }
+
+    pub fn select_last(&mut self) {
+        self.selected = self.matches.len().saturating_sub(1);
+    }
+
+    /// Adjusts the scroll so that the selected item is one of the `rows` visible items
+    pub fn scroll_to_selection(&mut self, rows: usize) {
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if rows > 0 && self.selected >= self.scroll + rows {
+            self.scroll = self.selected + 1 - rows;
+        }
+    }
+
+    pub fn selected_item(&self) -> Option<&SelectorItem> {
+        self.matches.get(self.selected).map(|m| &self.items[m.index])
+    }
+
+    pub fn selected_index(&self) -> usize {
+        self.selected
+    }
+
+    pub fn scroll(&self) -> usize {
+        self.scroll
+    }
+
+    /// Returns the items matching the current query along with the matched char indices of their labels
+    pub fn visible_items(&self) -> impl Iterator<Item = (&SelectorItem, &[usize])> {
+        self.matches
+            .iter()
+            .map(|m| (&self.items[m.index], m.positions.as_slice()))
+    }
+
+    pub fn visible_count(&self) -> usize {
+        self.matches.len()
+    }
+
+    pub fn total_count(&self) -> usize {
+        self.items.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(selector: &Selector) -> Vec<&str> {
+        selector
+            .visible_items()
+            .map(|(item, _)| item.label.as_str())
+            .collect()
+    }
+
+    fn items(labels: &[&str]) -> Vec<SelectorItem> {
+        labels.iter().map(|l| SelectorItem::new(*l, None)).collect()
+    }
+
+    fn query(text: &str) -> Vec<char> {
+        text.chars().collect()
+    }
+
+    #[test]
+    fn fuzzy_match_needs_every_char_in_order() {
+        assert!(fuzzy_match(&query("mn"), "main").is_some());
+        assert!(fuzzy_match(&query("nm"), "main").is_none());
+        assert!(fuzzy_match(&query("mains"), "main").is_none());
+    }
+
+    #[test]
+    fn fuzzy_match_ignores_case() {
+        let (_, positions) = fuzzy_match(&query("READ"), "readme.md").unwrap();
+        assert_eq!(positions, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn fuzzy_match_empty_query_matches_anything() {
+        assert_eq!(fuzzy_match(&[], "anything"), Some((0, vec![])));
+    }
+
+    #[test]
+    fn fuzzy_match_prefers_word_starts() {
+        let (_, positions) = fuzzy_match(&query("fb"), "foo_bar").unwrap();
+        assert_eq!(positions, [0, 4]);
+        let (_, positions) = fuzzy_match(&query("fb"), "fooBar").unwrap();
+        assert_eq!(positions, [0, 3]);
+    }
+
+    #[test]
+    fn fuzzy_match_ranks_consecutive_and_early_matches_higher() {
+        let score = |text| fuzzy_match(&query("main"), text).unwrap().0;
+        assert!(score("main.rs") > score("src/main.rs"));
+        assert!(score("src/main.rs") > score("my_animation.rs"));
+    }
+
+    #[test]
+    fn extend_ranks_new_items_with_the_old_ones() {
+        let mut selector = Selector::new(items(&["src/main.rs", "README.md"]));
+        selector.filter("main");
+        selector.extend(items(&["main.rs", "docs/domain.txt"]));
+
+        assert_eq!(labels(&selector), ["main.rs", "src/main.rs", "docs/domain.txt"]);
+    }
+
+    #[test]
+    fn extend_keeps_the_selection() {
+        let mut selector = Selector::new(items(&["b1", "b2", "b3"]));
+        selector.filter("b");
+        selector.select_next(2);
+        selector.extend(items(&["b"]));
+
+        assert_eq!(selector.selected_item().unwrap().label, "b3");
+    }
+
+    #[test]
+    fn extend_without_query_appends() {
+        let mut selector = Selector::new(items(&["b", "a"]));
+        selector.extend(items(&["c"]));
+
+        assert_eq!(labels(&selector), ["b", "a", "c"]);
+    }
+}
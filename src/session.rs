@@ -0,0 +1,226 @@
+#![allow(dead_code)]
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    layout::{LayoutNode, SplitDirection},
+    save::{self, Backup},
+};
+
+/// Open files and panes of the editor, restored when `revo` is started again in the same working
+/// directory
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Session {
+    /// Buffers in tab order. A file shown in several panes has one entry per view.
+    pub buffers: Vec<SessionBuffer>,
+    pub layout: Option<SessionLayout>,
+    /// Index of the buffer in the active pane
+    pub active: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SessionBuffer {
+    pub path: PathBuf,
+    /// 0-indexed cursor position
+    pub line: usize,
+    pub column: usize,
+    pub scroll_x: usize,
+    pub scroll_y: usize,
+}
+
+/// `LayoutNode` with panes pointing into `Session::buffers`
+#[derive(Serialize, Deserialize, Debug)]
+pub enum SessionLayout {
+    Pane(usize),
+    Split {
+        direction: SplitDirection,
+        ratio: f32,
+        first: Box<SessionLayout>,
+        second: Box<SessionLayout>,
+    },
+}
+
+impl SessionLayout {
+    /// Converts a layout, leaving out the panes of buffers that are not in the session
+    pub fn from_layout(node: &LayoutNode, index_of: &HashMap<Uuid, usize>) -> Option<Self> {
+        match node {
+            LayoutNode::Pane(id) => index_of.get(id).map(|i| SessionLayout::Pane(*i)),
+            LayoutNode::Split {
+                direction,
+                ratio,
+                first,
+                second,
+            } => match (
+                Self::from_layout(first, index_of),
+                Self::from_layout(second, index_of),
+            ) {
+                (Some(first), Some(second)) => Some(SessionLayout::Split {
+                    direction: *direction,
+                    ratio: *ratio,
+                    first: Box::new(first),
+                    second: Box::new(second),
+                }),
+                (first, second) => first.or(second),
+            },
+        }
+    }
+
+    /// Converts back to a layout of the restored buffers, `ids` has `None` for the buffers that
+    /// could not be restored, and their panes are left out
+    pub fn to_layout(&self, ids: &[Option<Uuid>]) -> Option<LayoutNode> {
+        match self {
+            SessionLayout::Pane(i) => ids.get(*i).copied().flatten().map(LayoutNode::Pane),
+            SessionLayout::Split {
+                direction,
+                ratio,
+                first,
+                second,
+            } => match (first.to_layout(ids), second.to_layout(ids)) {
+                (Some(first), Some(second)) => Some(LayoutNode::Split {
+                    direction: *direction,
+                    ratio: *ratio,
+                    first: Box::new(first),
+                    second: Box::new(second),
+                }),
+                (first, second) => first.or(second),
+            },
+        }
+    }
+}
+
+/// Session file for the root folder `root`
+fn session_path(dir: &Path, root: &Path) -> PathBuf {
+    let mut name = save::flat_file_name(root);
+    name.push_str(".json");
+    dir.join(name)
+}
+
+pub fn load(dir: &Path, root: &Path) -> Option<Session> {
+    let session = fs::read_to_string(session_path(dir, root)).ok()?;
+    serde_json::from_str(&session).ok()
+}
+
+/// Saves the session of `root`, an empty session just removes the previous one
+pub fn save(dir: &Path, root: &Path, session: &Session) -> io::Result<()> {
+    let path = session_path(dir, root);
+
+    if session.buffers.is_empty() {
+        return match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    }
+
+    fs::create_dir_all(dir)?;
+    let json = serde_json::to_string_pretty(session)?;
+    save::write_atomic(&path, &json, &Backup::None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, line: usize, column: usize) -> SessionBuffer {
+        SessionBuffer {
+            path: PathBuf::from(path),
+            line,
+            column,
+            scroll_x: 0,
+            scroll_y: line.saturating_sub(5),
+        }
+    }
+
+    #[test]
+    fn saves_and_loads_files_cursors_and_layout() {
+        let dir = std::env::temp_dir().join(format!("revo-session-{}", std::process::id()));
+        let root = Path::new("/some/project");
+
+        let ids: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+        let layout = LayoutNode::Split {
+            direction: SplitDirection::Vertical,
+            ratio: 0.3,
+            first: Box::new(LayoutNode::Pane(ids[2])),
+            second: Box::new(LayoutNode::Split {
+                direction: SplitDirection::Horizontal,
+                ratio: 0.5,
+                first: Box::new(LayoutNode::Pane(ids[0])),
+                second: Box::new(LayoutNode::Pane(ids[1])),
+            }),
+        };
+        let index_of = ids.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+        let session = Session {
+            buffers: vec![
+                entry("a.rs", 10, 4),
+                entry("b.rs", 0, 0),
+                entry("a.rs", 80, 2),
+            ],
+            layout: SessionLayout::from_layout(&layout, &index_of),
+            active: Some(1),
+        };
+
+        save(&dir, root, &session).unwrap();
+        let loaded = load(&dir, root).unwrap();
+        assert!(load(&dir, Path::new("/other/project")).is_none());
+
+        let buffers = |session: &Session| {
+            session
+                .buffers
+                .iter()
+                .map(|b| (b.path.clone(), b.line, b.column, b.scroll_x, b.scroll_y))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(buffers(&loaded), buffers(&session));
+        assert_eq!(loaded.active, Some(1));
+
+        // The panes point at the same buffers once they get their new ids
+        let new_ids: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+        let restored_ids: Vec<Option<Uuid>> = new_ids.iter().copied().map(Some).collect();
+        let restored = loaded.layout.unwrap().to_layout(&restored_ids).unwrap();
+        assert_eq!(restored.panes(), vec![new_ids[2], new_ids[0], new_ids[1]]);
+        let new_index_of = new_ids.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+        assert_eq!(
+            format!("{:?}", SessionLayout::from_layout(&restored, &new_index_of)),
+            format!("{:?}", session.layout)
+        );
+
+        // Saving no files removes the session
+        save(&dir, root, &Session::default()).unwrap();
+        assert!(load(&dir, root).is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn leaves_out_the_panes_of_missing_files() {
+        let layout = SessionLayout::Split {
+            direction: SplitDirection::Horizontal,
+            ratio: 0.5,
+            first: Box::new(SessionLayout::Pane(0)),
+            second: Box::new(SessionLayout::Split {
+                direction: SplitDirection::Vertical,
+                ratio: 0.5,
+                first: Box::new(SessionLayout::Pane(1)),
+                second: Box::new(SessionLayout::Pane(2)),
+            }),
+        };
+        let ids: Vec<Uuid> = (0..3).map(|_| Uuid::new_v4()).collect();
+
+        let without_first = layout
+            .to_layout(&[None, Some(ids[1]), Some(ids[2])])
+            .unwrap();
+        assert_eq!(without_first.panes(), vec![ids[1], ids[2]]);
+
+        let only_first = layout.to_layout(&[Some(ids[0]), None, None]).unwrap();
+        assert!(matches!(only_first, LayoutNode::Pane(id) if id == ids[0]));
+
+        assert!(layout.to_layout(&[None, None, None]).is_none());
+        // Panes past the restored buffers are left out as well
+        assert!(layout.to_layout(&[]).is_none());
+    }
+}
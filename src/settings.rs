@@ -1,10 +1,11 @@
 #![allow(dead_code)]
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::{env, fs, io};
 
 use serde::{Deserialize, Serialize};
 
-use crate::theme::Theme;
+use crate::{lsp::ServerConfig, save::Backup, theme::Theme};
 
 fn get_user_home_dir() -> Option<PathBuf> {
     if cfg!(target_os = "windows") {
@@ -45,6 +46,12 @@ pub fn read_editor_settings() -> io::Result<Settings> {
     let mut settings_file_path = home_dir.clone();
     settings_file_path.push("settings.json");
 
+    let backups_path = home_dir.join("backups");
+    let swap_dir = home_dir.join("swap");
+    let sessions_dir = home_dir.join("sessions");
+    let positions_file = home_dir.join("positions.json");
+    let languages_dir = home_dir.join("languages");
+
     let mut themes_path = home_dir;
     themes_path.push("themes");
 
@@ -94,8 +101,23 @@ pub fn read_editor_settings() -> io::Result<Settings> {
         }
     };
     
+    let backup = match settings_schema.backup {
+        BackupMode::None => Backup::None,
+        BackupMode::Adjacent => Backup::Adjacent,
+        BackupMode::Directory => Backup::Directory(backups_path),
+    };
+
     Ok(Settings {
         theme,
+        file_finder_exclude: settings_schema.file_finder_exclude,
+        backup,
+        swap_dir,
+        swap_interval: settings_schema.swap_interval,
+        sessions_dir,
+        positions_file,
+        languages_dir,
+        language_servers: settings_schema.language_servers,
+        word_completion_trigger: settings_schema.word_completion_trigger,
     })
 }
 
@@ -104,13 +126,63 @@ struct SettingsSchema {
     /// Name of the active theme
     pub active_theme: String,
 
+    /// Glob patterns (gitignore syntax) of paths the file finder should skip
+    #[serde(default = "default_file_finder_exclude")]
+    pub file_finder_exclude: Vec<String>,
+
+    /// Keep a copy of the previous version of a file when saving over it
+    #[serde(default)]
+    pub backup: BackupMode,
+
+    /// Seconds between writes of unsaved changes to swap files, 0 turns them off
+    #[serde(default = "default_swap_interval")]
+    pub swap_interval: u64,
+
+    /// Commands starting the language server of a language, by language name, like
+    /// `"rust": { "command": "rust-analyzer" }`
+    #[serde(default)]
+    pub language_servers: HashMap<String, ServerConfig>,
+
+    /// Characters of a word to type before the words completing it are listed, 0 only lists
+    /// them on Ctrl+Space
+    #[serde(default = "default_word_completion_trigger")]
+    pub word_completion_trigger: usize,
+
     // Add settings in here
 }
 
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+enum BackupMode {
+    #[default]
+    None,
+    /// `name~` next to the file
+    Adjacent,
+    /// In `~/.revo/backups`
+    Directory,
+}
+
+fn default_file_finder_exclude() -> Vec<String> {
+    vec![".git".to_string(), "target".to_string(), "node_modules".to_string()]
+}
+
+fn default_swap_interval() -> u64 {
+    4
+}
+
+fn default_word_completion_trigger() -> usize {
+    3
+}
+
 impl Default for SettingsSchema {
     fn default() -> Self {
         SettingsSchema {
             active_theme: "default".to_string(),
+            file_finder_exclude: default_file_finder_exclude(),
+            backup: BackupMode::default(),
+            swap_interval: default_swap_interval(),
+            language_servers: HashMap::new(),
+            word_completion_trigger: default_word_completion_trigger(),
         }
     }
 }
@@ -118,4 +190,20 @@ impl Default for SettingsSchema {
 #[derive(Debug)]
 pub struct Settings {
     pub theme: Theme,
+    pub file_finder_exclude: Vec<String>,
+    pub backup: Backup,
+    /// Where unsaved changes are kept for crash recovery, `~/.revo/swap`
+    pub swap_dir: PathBuf,
+    /// Seconds between swap file writes, 0 when they are off
+    pub swap_interval: u64,
+    /// Where the open files of every root folder are kept, `~/.revo/sessions`
+    pub sessions_dir: PathBuf,
+    /// Last cursor positions of recently edited files, `~/.revo/positions.json`
+    pub positions_file: PathBuf,
+    /// Grammars of user defined languages, `~/.revo/languages`
+    pub languages_dir: PathBuf,
+    /// Language servers by language name
+    pub language_servers: HashMap<String, ServerConfig>,
+    /// Characters of a word to type before completions are listed, 0 when it takes Ctrl+Space
+    pub word_completion_trigger: usize,
 }
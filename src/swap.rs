@@ -0,0 +1,224 @@
+#![allow(dead_code)]
+use std::{
+    fs::{self, OpenOptions},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Sender},
+    thread::{self, JoinHandle},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::save::{self, Backup};
+
+/// Unsaved text of a file, written periodically so it can be recovered after a crash
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SwapFile {
+    pub path: PathBuf,
+    /// 0-indexed cursor position
+    pub line: usize,
+    pub column: usize,
+    pub contents: String,
+}
+
+enum SwapJob {
+    Write { swap_path: PathBuf, swap: SwapFile },
+    Remove { swap_path: PathBuf },
+}
+
+/// Swap files of the open documents, kept in a single folder.
+///
+/// Writing happens on a background thread, so large files don't block the editor. Jobs run in the
+/// order they are sent, and the pending ones are finished when this is dropped.
+pub struct SwapFiles {
+    dir: PathBuf,
+    sender: Option<Sender<SwapJob>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SwapFiles {
+    pub fn new(dir: PathBuf) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let writer_dir = dir.clone();
+
+        let thread = thread::spawn(move || {
+            for job in receiver {
+                // There is nobody to report to, the next write tries again
+                let _ = match job {
+                    SwapJob::Write { swap_path, swap } => write(&writer_dir, &swap_path, &swap),
+                    SwapJob::Remove { swap_path } => fs::remove_file(swap_path),
+                };
+            }
+        });
+
+        Self {
+            dir,
+            sender: Some(sender),
+            thread: Some(thread),
+        }
+    }
+
+    /// Returns where the swap file of `file` is kept
+    pub fn swap_path(&self, file: &Path) -> PathBuf {
+        // The file itself may not exist yet
+        let absolute = file
+            .canonicalize()
+            .or_else(|_| std::path::absolute(file))
+            .unwrap_or_else(|_| file.to_path_buf());
+
+        let mut name = save::flat_file_name(&absolute);
+        name.push_str(".swp");
+        self.dir.join(name)
+    }
+
+    pub fn write(&self, swap: SwapFile) {
+        let swap_path = self.swap_path(&swap.path);
+        self.send(SwapJob::Write { swap_path, swap });
+    }
+
+    pub fn remove(&self, file: &Path) {
+        let swap_path = self.swap_path(file);
+        self.send(SwapJob::Remove { swap_path });
+    }
+
+    fn send(&self, job: SwapJob) {
+        if let Some(sender) = &self.sender {
+            sender.send(job).expect("Swap file writer stopped");
+        }
+    }
+
+    /// Reads the swap file of `file` if it was written after the file was last modified
+    pub fn read_newer(&self, file: &Path) -> Option<SwapFile> {
+        let swap_path = self.swap_path(file);
+        let swap_modified = fs::metadata(&swap_path).and_then(|m| m.modified()).ok()?;
+
+        // A file that no longer exists is older than any swap file
+        let file_modified = fs::metadata(file).and_then(|m| m.modified()).ok();
+        if file_modified.is_some_and(|modified| modified > swap_modified) {
+            return None;
+        }
+
+        let swap = fs::read_to_string(swap_path).ok()?;
+        serde_json::from_str(&swap).ok()
+    }
+}
+
+impl Drop for SwapFiles {
+    fn drop(&mut self) {
+        // Closing the channel ends the writer once it's done with the queued jobs
+        self.sender.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn write(dir: &Path, swap_path: &Path, swap: &SwapFile) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+
+    // The swap file gets the permissions of the file, which `write_atomic` then keeps, so the
+    // text of a private file doesn't end up in a file anyone can read
+    if let Ok(metadata) = fs::metadata(&swap.path) {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(swap_path)?;
+        file.set_permissions(metadata.permissions())?;
+    }
+
+    let json = serde_json::to_string(swap)?;
+    save::write_atomic(swap_path, &json, &Backup::None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a new empty folder for a test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("revo-swap-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn swap_of(path: &Path, contents: &str) -> SwapFile {
+        SwapFile {
+            path: path.to_path_buf(),
+            line: 1,
+            column: 2,
+            contents: contents.to_string(),
+        }
+    }
+
+    #[test]
+    fn writes_finds_and_removes_swap_files() {
+        let dir = test_dir("find");
+        let file = dir.join("file.txt");
+        fs::write(&file, "saved").unwrap();
+
+        // Dropping the swap files waits for the writes
+        let swap_files = SwapFiles::new(dir.join("swap"));
+        swap_files.write(swap_of(&file, "unsaved"));
+        drop(swap_files);
+
+        let swap_files = SwapFiles::new(dir.join("swap"));
+        let found = swap_files.read_newer(&file);
+        let swap_path = swap_files.swap_path(&file);
+        swap_files.remove(&file);
+        drop(swap_files);
+        let removed = !swap_path.exists();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let found = found.unwrap();
+        assert_eq!(found.path, file);
+        assert_eq!((found.line, found.column), (1, 2));
+        assert_eq!(found.contents, "unsaved");
+        assert!(removed);
+    }
+
+    #[test]
+    fn ignores_swap_files_older_than_the_file() {
+        let dir = test_dir("older");
+        let file = dir.join("file.txt");
+        fs::write(&file, "saved").unwrap();
+
+        let swap_files = SwapFiles::new(dir.join("swap"));
+        swap_files.write(swap_of(&file, "unsaved"));
+        drop(swap_files);
+
+        // Saved again after the swap file was written
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+        fs::File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+
+        let found = SwapFiles::new(dir.join("swap")).read_newer(&file);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(found.is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn swap_files_are_as_private_as_the_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = test_dir("private");
+        let file = dir.join("secret.txt");
+        fs::write(&file, "saved").unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o600)).unwrap();
+
+        let swap_files = SwapFiles::new(dir.join("swap"));
+        let swap_path = swap_files.swap_path(&file);
+        swap_files.write(swap_of(&file, "unsaved"));
+        drop(swap_files);
+        let mode = fs::metadata(&swap_path).unwrap().permissions().mode();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
@@ -0,0 +1,795 @@
+#![allow(dead_code)]
+use std::ops::Range;
+
+use tree_sitter::{Decode, InputEdit, Parser, Point, Query, QueryCursor, StreamingIterator, Tree};
+
+use crate::{
+    document::{Change, ChangeReader, Document, Line},
+    highlight::Span,
+    history::EditKind,
+    language::{Language, TokenKind},
+};
+
+/// Bytes tree-sitter sees for every character, the text is given to it as UTF-32 so byte offsets
+/// are character positions times 4
+const CHAR_BYTES: usize = 4;
+/// Characters handed to tree-sitter at once while parsing
+const CHUNK_CHARS: usize = 1024;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NodeKind {
+    /// The whole text
+    Root,
+    /// Text between a pair of brackets, the brackets included
+    Group {
+        open: char,
+        close: char,
+    },
+    String,
+    Comment,
+    /// Any other node of a tree-sitter grammar, by its name in the grammar
+    Named(&'static str),
+}
+
+/// A node of the syntax tree, covering the characters from `start` up to `end` (exclusive)
+#[derive(Clone, Debug)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub start: usize,
+    pub end: usize,
+    pub children: Vec<Node>,
+}
+
+impl Node {
+    fn new(kind: NodeKind, start: usize, end: usize) -> Self {
+        Self {
+            kind,
+            start,
+            end,
+            children: vec![],
+        }
+    }
+
+    pub fn contains(&self, pos: usize) -> bool {
+        self.start <= pos && pos < self.end
+    }
+
+    /// Text inside the node, without its brackets
+    pub fn inner(&self) -> (usize, usize) {
+        match self.kind {
+            NodeKind::Group { .. } if self.end - self.start >= 2 => (self.start + 1, self.end - 1),
+            _ => (self.start, self.end),
+        }
+    }
+}
+
+/// Structure of a document.
+///
+/// For languages with a bundled parser it's the tree of their grammar, see `IncrementalParser`.
+/// For the others it's the bracket groups of the language, and the strings and comments found by
+/// its highlighter. Brackets inside strings and comments don't count, and a closing bracket that
+/// doesn't match is skipped, so a tree can be built for text that is being typed.
+pub struct SyntaxTree {
+    pub root: Node,
+}
+
+impl SyntaxTree {
+    /// Builds the tree of `chars`, taking the highlighted parts of every line from `spans`
+    pub fn parse(
+        language: &Language,
+        chars: &[char],
+        lines: &[Line],
+        mut spans: impl FnMut(usize) -> Vec<Span>,
+    ) -> Self {
+        // Groups that are not closed yet, the root is at the bottom
+        let mut open = vec![Node::new(NodeKind::Root, 0, chars.len())];
+
+        for (row, line) in lines.iter().enumerate() {
+            let mut pos = line.start;
+            let line_end = std::cmp::min(line.end + 1, chars.len());
+
+            for span in spans(row) {
+                let (start, end) = (line.start + span.start, line.start + span.end);
+                add_brackets(language, chars, pos, start, &mut open);
+                pos = end;
+
+                let kind = match span.kind {
+                    TokenKind::String => NodeKind::String,
+                    TokenKind::Comment => NodeKind::Comment,
+                    _ => continue,
+                };
+                let parent = open.last_mut().expect("The root is never closed");
+
+                // Strings and comments spanning lines are highlighted line by line, and comments
+                // on consecutive lines make one block
+                match parent.children.last_mut() {
+                    Some(last) if last.kind == kind && joins_line_above(chars, last.end, start) => {
+                        last.end = end
+                    }
+                    _ => parent.children.push(Node::new(kind, start, end)),
+                }
+            }
+
+            add_brackets(language, chars, pos, line_end, &mut open);
+        }
+
+        // Groups that are never closed end with the text
+        while open.len() > 1 {
+            let mut group = open.pop().expect("Checked above");
+            group.end = chars.len();
+            open.last_mut()
+                .expect("The root is never closed")
+                .children
+                .push(group);
+        }
+
+        Self {
+            root: open.pop().expect("The root is never closed"),
+        }
+    }
+
+    /// Converts the tree of a bundled parser. Anonymous nodes, like keywords and punctuation,
+    /// are left out.
+    fn from_tree_sitter(tree: &Tree, chars: &[char], brackets: &[(char, char)]) -> Self {
+        let mut root = Node::new(NodeKind::Root, 0, chars.len());
+        root.children = named_children(tree.root_node(), chars, brackets);
+        Self { root }
+    }
+
+    /// Returns the nodes containing `pos`, from the root down to the innermost one
+    pub fn path_at(&self, pos: usize) -> Vec<&Node> {
+        let mut path = vec![&self.root];
+        while let Some(child) = path
+            .last()
+            .and_then(|node| node.children.iter().find(|child| child.contains(pos)))
+        {
+            path.push(child);
+        }
+        path
+    }
+
+    /// Returns the smallest node, or text inside a group, that is larger than the range from
+    /// `start` to `end` and contains it
+    pub fn enclosing_range(&self, start: usize, end: usize) -> Option<(usize, usize)> {
+        self.path_at(start)
+            .iter()
+            .rev()
+            .flat_map(|node| [node.inner(), (node.start, node.end)])
+            .find(|(s, e)| *s <= start && end <= *e && e - s > end - start)
+    }
+
+    /// Returns the start of the node around `pos`. On the start of a node, it's the start of the
+    /// node around that one.
+    pub fn parent_start(&self, pos: usize) -> Option<usize> {
+        let path = self.path_at(pos);
+        let depth = match path.last() {
+            Some(node) if node.start == pos => path.len().checked_sub(2)?,
+            _ => path.len() - 1,
+        };
+        // The root is not a node to go to
+        path.get(depth).filter(|_| depth > 0).map(|node| node.start)
+    }
+
+    /// Returns the start of the next node (or the previous one) on the same level as `pos`
+    pub fn sibling_start(&self, pos: usize, forward: bool) -> Option<usize> {
+        let path = self.path_at(pos);
+        // On the start of a node, its siblings are the other children of its parent
+        let parent = match path.last() {
+            Some(node) if node.start == pos && path.len() > 1 => path[path.len() - 2],
+            _ => path.last()?,
+        };
+
+        let mut children = parent.children.iter();
+        let sibling = match forward {
+            true => children.find(|child| child.start > pos),
+            false => children.rev().find(|child| child.start < pos),
+        };
+        sibling.map(|node| node.start)
+    }
+}
+
+/// Opens and closes the groups of the brackets from `start` up to `end`
+fn add_brackets(
+    language: &Language,
+    chars: &[char],
+    start: usize,
+    end: usize,
+    open: &mut Vec<Node>,
+) {
+    for (pos, &ch) in chars.iter().enumerate().take(end).skip(start) {
+        if let Some((o, c)) = language.brackets.iter().find(|(o, _)| *o == ch) {
+            open.push(Node::new(
+                NodeKind::Group {
+                    open: *o,
+                    close: *c,
+                },
+                pos,
+                pos + 1,
+            ));
+            continue;
+        }
+        if !language.brackets.iter().any(|(_, c)| *c == ch) {
+            continue;
+        }
+
+        // Groups left open inside the one being closed end with it
+        let matching = open
+            .iter()
+            .rposition(|node| matches!(node.kind, NodeKind::Group { close, .. } if close == ch));
+        let Some(matching) = matching else {
+            continue;
+        };
+        while open.len() > matching {
+            let mut group = open.pop().expect("Checked above");
+            group.end = pos + 1;
+            open.last_mut()
+                .expect("The root is never closed")
+                .children
+                .push(group);
+        }
+    }
+}
+
+/// Returns true if only one line break and indentation are between `end` and `start`
+fn joins_line_above(chars: &[char], end: usize, start: usize) -> bool {
+    let between = chars.get(end..start).unwrap_or(&[]);
+    between.iter().all(|c| c.is_whitespace()) && between.iter().filter(|c| **c == '\n').count() == 1
+}
+
+/// A tree-sitter grammar bundled with revo
+#[derive(Clone, Copy, Debug)]
+pub struct BundledParser {
+    pub language: fn() -> tree_sitter::Language,
+    /// Highlight queries, joined in this order. When several patterns capture a node the first
+    /// one wins.
+    pub highlights: &'static [&'static str],
+}
+
+/// Parses a document with a bundled grammar, and highlights it with the grammar's queries.
+///
+/// The tree is kept between edits: the changes the document logged since the last parse give the
+/// range of the text that was edited, and only that part of the tree is parsed again.
+pub struct IncrementalParser {
+    parser: Parser,
+    highlights: Query,
+    brackets: Vec<(char, char)>,
+    tree: Option<Tree>,
+    /// Changes of the document parsed into `tree`, `None` before the first parse
+    parsed_changes: Option<ChangeReader>,
+    /// `Document::replacements` when `tree` was parsed, a replaced text is parsed from scratch
+    replacements: usize,
+}
+
+/// The text is given to tree-sitter as little endian UTF-32
+struct Utf32;
+
+impl Decode for Utf32 {
+    fn decode(bytes: &[u8]) -> (i32, u32) {
+        match bytes.first_chunk::<CHAR_BYTES>() {
+            Some(bytes) => (i32::from_le_bytes(*bytes), CHAR_BYTES as u32),
+            // Tells tree-sitter the character is cut, it asks for a new chunk
+            None => (-1, 0),
+        }
+    }
+}
+
+impl IncrementalParser {
+    /// Returns `None` if the grammar doesn't load, e.g. when it was built for another version of
+    /// tree-sitter
+    pub fn new(bundled: &BundledParser, brackets: &[(char, char)]) -> Option<Self> {
+        let language = (bundled.language)();
+        let mut parser = Parser::new();
+        parser.set_language(&language).ok()?;
+        let highlights = Query::new(&language, &bundled.highlights.join("\n")).ok()?;
+
+        Some(Self {
+            parser,
+            highlights,
+            brackets: brackets.to_vec(),
+            tree: None,
+            parsed_changes: None,
+            replacements: 0,
+        })
+    }
+
+    /// Parses the text of `document` again, reusing the parts of the last tree outside the range
+    /// edited since
+    pub fn parse(&mut self, document: &Document) {
+        let changes = match &self.parsed_changes {
+            Some(reader) if self.replacements == document.replacements() => {
+                document.changes_since(reader.get())
+            }
+            _ => {
+                self.tree = None;
+                &[]
+            }
+        };
+        if let Some(tree) = &mut self.tree {
+            match edit_of(changes, document) {
+                Some(edit) => tree.edit(&edit),
+                None => return,
+            }
+        }
+
+        let chars = document.chars();
+        let mut input = |byte: usize, _| {
+            let start = std::cmp::min(byte / CHAR_BYTES, chars.len());
+            let end = std::cmp::min(start + CHUNK_CHARS, chars.len());
+            chars[start..end]
+                .iter()
+                .flat_map(|c| (*c as u32).to_le_bytes())
+                .collect::<Vec<u8>>()
+        };
+        let tree =
+            self.parser
+                .parse_custom_encoding::<Utf32, _, _>(&mut input, self.tree.as_ref(), None);
+
+        self.tree = tree;
+        self.parsed_changes
+            .get_or_insert_with(|| document.reader())
+            .set(document.change_count());
+        self.replacements = document.replacements();
+    }
+
+    /// Returns the tree of `chars`, the text last parsed
+    pub fn syntax_tree(&self, chars: &[char]) -> Option<SyntaxTree> {
+        let tree = self.tree.as_ref()?;
+        Some(SyntaxTree::from_tree_sitter(tree, chars, &self.brackets))
+    }
+
+    /// Returns the highlighted parts of every line in `rows` of `chars`, the text last parsed,
+    /// like `Highlighter::spans`. The lines are queried at once.
+    pub fn spans(&self, chars: &[char], lines: &[Line], rows: Range<usize>) -> Vec<Vec<Span>> {
+        let mut spans: Vec<Vec<Span>> = rows.clone().map(|_| vec![]).collect();
+        let lines = &lines[..std::cmp::min(rows.end, lines.len())];
+        let (Some(tree), Some(first), Some(last)) =
+            (&self.tree, lines.get(rows.start), lines.last())
+        else {
+            return spans;
+        };
+        let (range_start, range_end) = (first.start, text_end(chars, last));
+        if range_start >= range_end {
+            return spans;
+        }
+
+        let mut cursor = QueryCursor::new();
+        cursor.set_byte_range(range_start * CHAR_BYTES..range_end * CHAR_BYTES);
+        // Predicates like `#match?` compare the text of nodes
+        let node_text = |node: tree_sitter::Node| {
+            let chars = &chars[node.start_byte() / CHAR_BYTES..node.end_byte() / CHAR_BYTES];
+            std::iter::once(chars.iter().collect::<String>())
+        };
+        let mut captures = cursor.captures(&self.highlights, tree.root_node(), node_text);
+
+        let names = self.highlights.capture_names();
+        // Captures come by their start, the parts already highlighted are kept
+        let mut covered = range_start;
+        while let Some((found, index)) = captures.next() {
+            let capture = found.captures[*index];
+            let Some(kind) = capture_kind(names[capture.index as usize], capture.node.kind())
+            else {
+                continue;
+            };
+
+            let start = (capture.node.start_byte() / CHAR_BYTES).max(covered);
+            let end = (capture.node.end_byte() / CHAR_BYTES).min(range_end);
+            if start >= end {
+                continue;
+            }
+            covered = end;
+
+            // Captures over several lines, like block comments, are split at the line breaks
+            let mut row = lines.partition_point(|line| line.end < start);
+            while let Some(line) = lines.get(row).filter(|line| line.start < end) {
+                let span_start = std::cmp::max(start, line.start);
+                let span_end = std::cmp::min(end, text_end(chars, line));
+                if span_start < span_end {
+                    spans[row - rows.start].push(Span {
+                        start: span_start - line.start,
+                        end: span_end - line.start,
+                        kind,
+                    });
+                }
+                row += 1;
+            }
+        }
+        spans
+    }
+}
+
+/// Returns the end of the text of `line`, without its '\n'
+fn text_end(chars: &[char], line: &Line) -> usize {
+    let end = std::cmp::min(line.end + 1, chars.len());
+    match chars.get(end.wrapping_sub(1)) {
+        Some('\n') => end - 1,
+        _ => end,
+    }
+}
+
+/// Kind of the text captured as `name` by a highlight query, `node` being the kind of the
+/// captured node
+fn capture_kind(name: &str, node: &str) -> Option<TokenKind> {
+    let kind = match name.split('.').next()? {
+        "comment" => TokenKind::Comment,
+        "string" | "escape" => TokenKind::String,
+        "number" => TokenKind::Number,
+        // Grammars like Rust's capture numbers and `true` alike
+        "keyword" | "boolean" | "constant" | "variable"
+            if ["integer", "float", "number"]
+                .iter()
+                .any(|number| node.contains(number)) =>
+        {
+            TokenKind::Number
+        }
+        "keyword" | "boolean" => TokenKind::Keyword,
+        "constant" | "variable" if name.ends_with(".builtin") => TokenKind::Keyword,
+        _ => return None,
+    };
+    Some(kind)
+}
+
+/// Returns the edit `changes` made to the text, from the first to the last character they
+/// changed, `document` having the text they left. `None` if there are no changes.
+fn edit_of(changes: &[Change], document: &Document) -> Option<InputEdit> {
+    // The edited range, in the text after each change
+    let first = changes.first()?;
+    let (mut start, mut end) = (first.pos, first.pos);
+    for change in changes {
+        start = std::cmp::min(start, change.pos);
+        end = match change.kind {
+            EditKind::Insert if change.pos <= end => end + 1,
+            EditKind::Insert => change.pos + 1,
+            EditKind::Delete if change.pos < end => end - 1,
+            EditKind::Delete => change.pos,
+        };
+    }
+
+    // The text the range had before, found by undoing the changes on it
+    let mut old: Vec<char> = document.chars()[start..end].to_vec();
+    for change in changes.iter().rev() {
+        match change.kind {
+            EditKind::Insert => {
+                old.remove(change.pos - start);
+            }
+            EditKind::Delete => old.insert(change.pos - start, change.ch),
+        }
+    }
+
+    let start_position = point(document, start);
+    let old_end_position = old.iter().fold(start_position, |point, c| match c {
+        '\n' => Point::new(point.row + 1, 0),
+        _ => Point::new(point.row, point.column + CHAR_BYTES),
+    });
+    Some(InputEdit {
+        start_byte: start * CHAR_BYTES,
+        old_end_byte: (start + old.len()) * CHAR_BYTES,
+        new_end_byte: end * CHAR_BYTES,
+        start_position,
+        old_end_position,
+        new_end_position: point(document, end),
+    })
+}
+
+/// Row and column, in tree-sitter's bytes, of the character at `pos` of `document`
+fn point(document: &Document, pos: usize) -> Point {
+    let row = document.line_of(pos);
+    let column = pos - document.lines()[row].start;
+    Point::new(row, column * CHAR_BYTES)
+}
+
+/// Converts the named children of `node`. Comments on consecutive lines make one block, like in
+/// the trees of the other languages.
+fn named_children(node: tree_sitter::Node, chars: &[char], brackets: &[(char, char)]) -> Vec<Node> {
+    let mut children: Vec<Node> = vec![];
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        let (start, end) = (
+            child.start_byte() / CHAR_BYTES,
+            child.end_byte() / CHAR_BYTES,
+        );
+        // Nodes tree-sitter made up to recover from an error, like a missing `;`
+        if start == end {
+            continue;
+        }
+
+        let kind = node_kind(child, brackets);
+        match children.last_mut() {
+            Some(last)
+                if kind == NodeKind::Comment
+                    && last.kind == kind
+                    && joins_line_above(chars, last.end, start) =>
+            {
+                last.end = end;
+            }
+            _ => {
+                let mut converted = Node::new(kind, start, end);
+                converted.children = named_children(child, chars, brackets);
+                children.push(converted);
+            }
+        }
+    }
+    children
+}
+
+fn node_kind(node: tree_sitter::Node, brackets: &[(char, char)]) -> NodeKind {
+    let kind = node.kind();
+    if kind.contains("comment") {
+        return NodeKind::Comment;
+    }
+    if kind.contains("string") {
+        return NodeKind::String;
+    }
+
+    // Nodes like blocks and argument lists start and end with a pair of brackets
+    let token = |child: Option<tree_sitter::Node>| {
+        let child = child.filter(|child| !child.is_named())?;
+        let mut chars = child.kind().chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+    let first = token(node.child(0));
+    let last = token(node.child(node.child_count().saturating_sub(1)));
+    match brackets.iter().find(|(open, _)| Some(*open) == first) {
+        Some((open, close)) if node.child_count() > 1 && Some(*close) == last => NodeKind::Group {
+            open: *open,
+            close: *close,
+        },
+        _ => NodeKind::Named(kind),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{document::Document, language};
+    use std::path::Path;
+
+    fn document(name: &str, text: &str) -> Document {
+        let mut document = Document::from(text.to_string());
+        let languages = language::builtin();
+        document.set_language(language::detect(&languages, Path::new(name), ""));
+        document
+    }
+
+    fn kinds(document: &mut Document, row: usize) -> Vec<(usize, usize, TokenKind)> {
+        document.highlights(&[row])[0]
+            .iter()
+            .map(|span| (span.start, span.end, span.kind))
+            .collect()
+    }
+
+    fn kinds_of(node: &Node) -> Vec<NodeKind> {
+        node.children.iter().map(|child| child.kind).collect()
+    }
+
+    #[test]
+    fn highlights_rust_with_its_grammar() {
+        let mut document = document(
+            "a.rs",
+            "let s = \"a { b\"; // c\nlet n = 0x1f + r#\"x\"#;\n",
+        );
+        assert_eq!(
+            kinds(&mut document, 0),
+            [
+                (0, 3, TokenKind::Keyword),
+                (8, 15, TokenKind::String),
+                (17, 21, TokenKind::Comment)
+            ]
+        );
+        assert_eq!(
+            kinds(&mut document, 1),
+            [
+                (0, 3, TokenKind::Keyword),
+                (8, 12, TokenKind::Number),
+                (15, 21, TokenKind::String)
+            ]
+        );
+    }
+
+    #[test]
+    fn highlights_json_and_toml() {
+        let mut json = document("a.json", "{\"a\": [1, true, null]}");
+        assert_eq!(
+            kinds(&mut json, 0),
+            [
+                (1, 4, TokenKind::String),
+                (7, 8, TokenKind::Number),
+                (10, 14, TokenKind::Keyword),
+                (16, 20, TokenKind::Keyword)
+            ]
+        );
+
+        let mut toml = document("a.toml", "[package]\nname = \"x\" # c\n");
+        assert_eq!(
+            kinds(&mut toml, 0),
+            [
+                (0, 1, TokenKind::Keyword),
+                (1, 8, TokenKind::Keyword),
+                (8, 9, TokenKind::Keyword)
+            ]
+        );
+        assert_eq!(
+            kinds(&mut toml, 1),
+            [(7, 10, TokenKind::String), (11, 14, TokenKind::Comment)]
+        );
+    }
+
+    #[test]
+    fn builds_the_tree_of_the_grammar() {
+        let mut document = document("a.rs", "// a\n// b\nfn f(x: u8) {\n    g(\"}\");\n}\n");
+        let tree = document.syntax_tree().unwrap();
+        assert_eq!(
+            kinds_of(&tree.root),
+            [NodeKind::Comment, NodeKind::Named("function_item")]
+        );
+        // The comments on consecutive lines make one node
+        assert_eq!(
+            (tree.root.children[0].start, tree.root.children[0].end),
+            (0, 9)
+        );
+
+        let function = &tree.root.children[1];
+        let parens = NodeKind::Group {
+            open: '(',
+            close: ')',
+        };
+        let braces = NodeKind::Group {
+            open: '{',
+            close: '}',
+        };
+        assert_eq!(
+            kinds_of(function),
+            [NodeKind::Named("identifier"), parens, braces]
+        );
+
+        // The brace inside the string doesn't end the block
+        let block = &function.children[2];
+        assert_eq!(document.chars()[block.end - 1], '}');
+        assert_eq!(block.end, document.len() - 1);
+    }
+
+    #[test]
+    fn parses_edits_again() {
+        let mut document = document("a.rs", "fn f() {}\n");
+        document.syntax_tree();
+        for (i, ch) in "let x = \"a\";".chars().enumerate() {
+            document.insert(8 + i, ch);
+        }
+        document.remove(0);
+        assert_eq!(kinds(&mut document, 0)[1], (15, 18, TokenKind::String));
+        let tree = document.syntax_tree().unwrap();
+        // Without `fn` the text doesn't parse
+        assert_eq!(tree.root.children[0].kind, NodeKind::Named("ERROR"));
+    }
+
+    #[test]
+    fn reparsed_tree_matches_a_new_one() {
+        let bundled = language::builtin()[0].parser.unwrap();
+        let mut document = Document::from("fn f() {\n    a(1);\n}\n".to_string());
+        let mut edited = IncrementalParser::new(&bundled, &[]).unwrap();
+        edited.parse(&document);
+
+        document.replace(16, 16, ", \"x\"");
+        let end = document.len() - 2;
+        for (i, ch) in "    b();\n".chars().enumerate() {
+            document.insert(end + i, ch);
+        }
+        document.remove(3);
+        edited.parse(&document);
+        let mut fresh = IncrementalParser::new(&bundled, &[]).unwrap();
+        fresh.parse(&document);
+
+        assert_eq!(
+            document.to_string(),
+            "fn () {\n    a(1, \"x\");\n    b();\n}\n"
+        );
+        let sexp = |parser: &IncrementalParser| parser.tree.as_ref().unwrap().root_node().to_sexp();
+        assert_eq!(sexp(&edited), sexp(&fresh));
+        // The nodes kept from the last tree moved along with the edits
+        let nodes = |parser: &IncrementalParser| {
+            format!("{:?}", parser.syntax_tree(document.chars()).unwrap().root)
+        };
+        assert_eq!(nodes(&edited), nodes(&fresh));
+    }
+
+    #[test]
+    fn parses_a_replaced_text_from_scratch() {
+        let bundled = language::builtin()[0].parser.unwrap();
+        let mut document = Document::from("fn f() {}\n".to_string());
+        let mut parser = IncrementalParser::new(&bundled, &[]).unwrap();
+        parser.parse(&document);
+
+        // No change is logged
+        document.replace_text("struct S;\n".to_string());
+        parser.parse(&document);
+        let tree = parser.syntax_tree(document.chars()).unwrap();
+        assert_eq!(kinds_of(&tree.root), [NodeKind::Named("struct_item")]);
+    }
+
+    #[test]
+    fn finds_the_edited_range() {
+        let edit = |text: &str, edit: fn(&mut Document)| {
+            let mut document = Document::from(text.to_string());
+            let reader = document.reader();
+            edit(&mut document);
+            edit_of(document.changes_since(reader.get()), &document).unwrap()
+        };
+        let bytes = |edit: &InputEdit| (edit.start_byte, edit.old_end_byte, edit.new_end_byte);
+
+        // "ab\ncd\nef" -> "ab\ncXYd\nef"
+        let inserted = edit("ab\ncd\nef", |document| {
+            document.insert(4, 'X');
+            document.insert(5, 'Y');
+        });
+        assert_eq!(
+            bytes(&inserted),
+            (4 * CHAR_BYTES, 4 * CHAR_BYTES, 6 * CHAR_BYTES)
+        );
+        assert_eq!(inserted.start_position, Point::new(1, CHAR_BYTES));
+        assert_eq!(inserted.old_end_position, Point::new(1, CHAR_BYTES));
+        assert_eq!(inserted.new_end_position, Point::new(1, 3 * CHAR_BYTES));
+
+        // "ab\ncd\nef" -> "a\nef", the deleted text spans a line break
+        let deleted = edit("ab\ncd\nef", |document| document.replace(1, 5, ""));
+        assert_eq!(bytes(&deleted), (CHAR_BYTES, 5 * CHAR_BYTES, CHAR_BYTES));
+        assert_eq!(deleted.old_end_position, Point::new(1, 2 * CHAR_BYTES));
+        assert_eq!(deleted.new_end_position, Point::new(0, CHAR_BYTES));
+
+        // Edits apart, the later one before the earlier: "0123456789" -> "01X246789"
+        let apart = edit("0123456789", |document| {
+            document.remove(5);
+            document.remove(3);
+            document.insert(2, 'X');
+        });
+        assert_eq!(
+            bytes(&apart),
+            (2 * CHAR_BYTES, 6 * CHAR_BYTES, 5 * CHAR_BYTES)
+        );
+    }
+
+    #[test]
+    fn highlights_lines_at_once() {
+        let mut document = document("a.rs", "let a; /* b\nc\n*/ let d;\n");
+        assert_eq!(
+            document.highlights(&[0, 1, 2]),
+            [
+                vec![
+                    Span {
+                        start: 0,
+                        end: 3,
+                        kind: TokenKind::Keyword
+                    },
+                    Span {
+                        start: 7,
+                        end: 11,
+                        kind: TokenKind::Comment
+                    }
+                ],
+                vec![Span {
+                    start: 0,
+                    end: 1,
+                    kind: TokenKind::Comment
+                }],
+                vec![
+                    Span {
+                        start: 0,
+                        end: 2,
+                        kind: TokenKind::Comment
+                    },
+                    Span {
+                        start: 3,
+                        end: 6,
+                        kind: TokenKind::Keyword
+                    }
+                ],
+            ]
+        );
+        // Folded lines are left out, and the rows past the text have no highlights
+        let spans = document.highlights(&[0, 2, 3, 9]);
+        assert_eq!(spans.len(), 4);
+        assert_eq!((spans[1][0].start, spans[1][0].end), (0, 2));
+        assert!(spans[2].is_empty() && spans[3].is_empty());
+    }
+}
@@ -6,8 +6,14 @@ use std::{
 
 use crossterm::{
     cursor::{Hide, MoveTo, SetCursorStyle, Show},
+    event::{
+        KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
     execute, queue,
-    style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor},
+    style::{
+        Attribute, Color, Print, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor,
+        SetUnderlineColor,
+    },
     terminal::{
         self, disable_raw_mode, enable_raw_mode, DisableLineWrap, EnableLineWrap,
         EnterAlternateScreen, LeaveAlternateScreen,
@@ -16,8 +22,11 @@ use crossterm::{
 
 use crate::{
     buffer::{Buffer, BufferLogic, Padding},
+    layout::Separator,
+    selector::SelectorItem,
     theme::Theme,
     vec_ext::VecExt,
+    word_completion::VISIBLE_WORDS,
 };
 
 enum BrushEvent {
@@ -25,6 +34,16 @@ enum BrushEvent {
     PreviousBG,
     SetFG(Color),
     PreviousFG,
+    /// Underlines don't nest, every one ends before the next starts
+    SetUnderline(Color),
+    EndUnderline,
+}
+
+/// A tab in the tab bar
+pub struct Tab {
+    pub title: String,
+    pub active: bool,
+    pub modified: bool,
 }
 
 pub struct Terminal<W: Write> {
@@ -33,44 +52,56 @@ pub struct Terminal<W: Write> {
     buffer: Vec<Vec<char>>,
     brushes: Vec<Vec<(usize, BrushEvent)>>,
     out: W,
+    keyboard_enhanced: bool,
 }
 
 impl<W: Write> Terminal<W> {
     pub fn new(out: W) -> io::Result<Self> {
         let size = terminal::size()?;
 
-        let buffer = (0..size.1)
-            .into_iter()
-            .map(|_| (0..size.0).map(|_| ' ').collect())
-            .collect();
-
-        let brushes = (0..size.1).into_iter().map(|_| vec![]).collect();
-
         enable_raw_mode()?;
 
-        let mut display = Self {
-            width: size.0,
-            height: size.1,
-            buffer,
-            brushes,
-            out,
-        };
+        let mut display = Self::with_size(out, size.0, size.1);
 
         execute!(display.out, EnterAlternateScreen, DisableLineWrap)?;
 
+        // Lets terminals that support it report key combinations like Ctrl + Shift + P, which are
+        // otherwise indistinguishable from Ctrl + P
+        if terminal::supports_keyboard_enhancement().unwrap_or(false) {
+            execute!(
+                display.out,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES)
+            )?;
+            display.keyboard_enhanced = true;
+        }
+
         Ok(display)
     }
 
+    /// Creates a terminal of `width` x `height` that is drawn to `out`, without setting up the
+    /// real terminal
+    pub fn with_size(out: W, width: u16, height: u16) -> Self {
+        let mut display = Self {
+            width: 0,
+            height: 0,
+            buffer: vec![],
+            brushes: vec![],
+            out,
+            keyboard_enhanced: false,
+        };
+        display.resize(width, height);
+        display
+    }
+
     pub fn resize(&mut self, w: u16, h: u16) {
         self.width = w;
         self.height = h;
 
         self.buffer = (0..h)
-            .into_iter()
             .map(|_| (0..w).map(|_| ' ').collect())
             .collect();
 
-        self.brushes = (0..h).into_iter().map(|_| vec![]).collect();
+        self.brushes = (0..h).map(|_| vec![]).collect();
     }
 
     pub fn flush(&mut self) -> io::Result<()> {
@@ -108,7 +139,7 @@ impl<W: Write> Terminal<W> {
             let mut fg_prev_prev_color = None; // Hack... I wish there is a better way
             for (idx, color) in row_burshes {
                 let colored_str = &row[start_idx..*idx];
-                let colored_str: String = colored_str.into_iter().collect();
+                let colored_str: String = colored_str.iter().collect();
                 queue!(
                     self.out,
                     MoveTo(start_idx as u16, i as u16),
@@ -139,11 +170,21 @@ impl<W: Write> Terminal<W> {
                         fg_prev_prev_color = fg_prev_color;
                         fg_prev_color = Some(fg_color);
                     }
+                    BrushEvent::SetUnderline(color) => {
+                        queue!(
+                            self.out,
+                            SetUnderlineColor(*color),
+                            SetAttribute(Attribute::Underlined)
+                        )?;
+                    }
+                    BrushEvent::EndUnderline => {
+                        queue!(self.out, SetAttribute(Attribute::NoUnderline))?;
+                    }
                 };
                 start_idx = *idx;
             }
             let colored_str = &row[start_idx..];
-            let colored_str: String = colored_str.into_iter().collect();
+            let colored_str: String = colored_str.iter().collect();
             queue!(
                 self.out,
                 MoveTo(start_idx as u16, i as u16),
@@ -158,6 +199,11 @@ impl<W: Write> Terminal<W> {
     ///
     /// Color is provided as Hex RGB (#FFFFFF)
     fn paint_bg(&mut self, row: usize, start: usize, end: usize, color: &str) {
+        let end = std::cmp::min(end, self.width as usize);
+        if row >= self.brushes.len() || start >= end {
+            return;
+        }
+
         self.brushes[row].push((start, BrushEvent::SetBG(Theme::hex_to_color(color))));
 
         self.brushes[row].push((end, BrushEvent::PreviousBG));
@@ -167,13 +213,93 @@ impl<W: Write> Terminal<W> {
     ///
     /// Color is provided as Hex RGB (#FFFFFF)
     fn paint_fg(&mut self, row: usize, start: usize, end: usize, color: &str) {
+        let end = std::cmp::min(end, self.width as usize);
+        if row >= self.brushes.len() || start >= end {
+            return;
+        }
+
         self.brushes[row].push((start, BrushEvent::SetFG(Theme::hex_to_color(color))));
 
         self.brushes[row].push((end, BrushEvent::PreviousFG));
     }
 
+    /// Underlines the terminal row from start column to end column (exclusive) in color.
+    ///
+    /// Color is provided as Hex RGB (#FFFFFF)
+    fn paint_underline(&mut self, row: usize, start: usize, end: usize, color: &str) {
+        let end = std::cmp::min(end, self.width as usize);
+        if row >= self.brushes.len() || start >= end {
+            return;
+        }
+
+        self.brushes[row].push((start, BrushEvent::SetUnderline(Theme::hex_to_color(color))));
+
+        self.brushes[row].push((end, BrushEvent::EndUnderline));
+    }
+
+    /// Paints columns start to end (exclusive) of the terminal row over whatever was painted there
+    /// before, dropping its brushes so its colors don't show through.
+    ///
+    /// Colors are provided as Hex RGB (#FFFFFF)
+    fn paint_over(&mut self, row: usize, start: usize, end: usize, bg: &str, fg: &str) {
+        let end = std::cmp::min(end, self.width as usize);
+        let Some(brushes) = self.brushes.get_mut(row) else {
+            return;
+        };
+        if start >= end {
+            return;
+        }
+
+        // The brushes after the columns only work from the colors they were painted over, so
+        // those are set again at the end, the previous one first
+        brushes.sort_by_key(|b| b.0);
+        let (mut bg_colors, mut fg_colors, mut underline) = ([None; 2], [None; 2], None);
+        for (_, brush) in brushes.iter().take_while(|(column, _)| *column < end) {
+            match brush {
+                BrushEvent::SetBG(color) => bg_colors = [bg_colors[1], Some(*color)],
+                BrushEvent::PreviousBG => bg_colors = [bg_colors[1], bg_colors[0]],
+                BrushEvent::SetFG(color) => fg_colors = [fg_colors[1], Some(*color)],
+                BrushEvent::PreviousFG => fg_colors = [fg_colors[1], fg_colors[0]],
+                BrushEvent::SetUnderline(color) => underline = Some(*color),
+                BrushEvent::EndUnderline => underline = None,
+            }
+        }
+
+        let restored = bg_colors.into_iter().flatten().map(BrushEvent::SetBG);
+        let restored = restored.chain(fg_colors.into_iter().flatten().map(BrushEvent::SetFG));
+        let restored = restored.chain(underline.map(BrushEvent::SetUnderline));
+        let restored: Vec<_> = restored.map(|brush| (end, brush)).collect();
+
+        brushes.retain(|(column, _)| !(start..end).contains(column));
+        // The sort is stable, so the colors come back before the brushes starting at the end
+        brushes.splice(0..0, restored);
+        brushes.push((start, BrushEvent::EndUnderline));
+        brushes.push((start, BrushEvent::SetBG(Theme::hex_to_color(bg))));
+        brushes.push((start, BrushEvent::SetFG(Theme::hex_to_color(fg))));
+    }
+
+    /// Writes `text` on `row` from column `start`, cutting it off at column `end` (exclusive)
+    fn put(&mut self, row: usize, start: usize, end: usize, text: &str) {
+        let Some(line) = self.buffer.get_mut(row) else {
+            return;
+        };
+
+        let text: String = text.chars().take(end.saturating_sub(start)).collect();
+        line.replace_from(start, &text);
+    }
+
+    /// Draws a vertical separator line between two panes
+    pub fn draw_separator(&mut self, separator: Separator, theme: &Theme) {
+        let x = separator.x as usize;
+        for row in separator.y as usize..(separator.y + separator.height) as usize {
+            self.put(row, x, x + 1, "│");
+            self.paint_bg(row, x, x + 1, &theme.editor.bg);
+            self.paint_fg(row, x, x + 1, &theme.editor.line_numbers);
+        }
+    }
+
     pub fn clear(&mut self) -> io::Result<()> {
-        let line: String = (0..self.width).into_iter().map(|_| ' ').collect();
+        let line: String = (0..self.width).map(|_| ' ').collect();
         for i in 0..self.height {
             queue!(self.out, MoveTo(0, i), Print(&line))?;
         }
@@ -195,12 +321,16 @@ impl<W: Write> Terminal<W> {
 
     pub fn draw_buffer(&mut self, buffer: &Buffer, theme: &Theme) {
         let Padding {
-            top, bottom, left, ..
+            top,
+            bottom,
+            left,
+            right,
         } = buffer.get_padding();
 
         let mut row_idx = buffer.y as usize + top;
         let buf_x = buffer.x as usize;
-        let buf_end = (buffer.x + buffer.width) as usize;
+        // Everything is clipped to the buffer's rectangle, and to the terminal
+        let buf_end = std::cmp::min((buffer.x + buffer.width) as usize, self.width as usize);
         let start_x = buf_x + left;
         let buf_current_line = buffer.data.current_line();
 
@@ -216,17 +346,21 @@ impl<W: Write> Terminal<W> {
         };
 
         if buffer.bordered {
-            self.buffer[buffer.y as usize].replace_from(buf_x, &buffer.top_border);
+            self.put(buffer.y as usize, buf_x, buf_end, &buffer.top_border);
 
             self.paint_bg(buffer.y as usize, buf_x, buf_end, border_bg_color);
             self.paint_fg(buffer.y as usize, buf_x, buf_end, border_fg_color);
         }
 
-        for line_num in (buffer.scroll_y..buffer.data.line_count())
-            .take((height as usize).saturating_sub(bottom + top))
-        {
+        // Folded lines are skipped
+        let lines = buffer.visible_lines((height as usize).saturating_sub(bottom + top));
+        let highlights = match buffer.logic {
+            BufferLogic::Editor => buffer.highlights(&lines),
+            _ => vec![],
+        };
+        for (i, &line_num) in lines.iter().enumerate() {
             if let Some(display_line) = buffer.get_row(line_num) {
-                self.buffer[row_idx].replace_from(buf_x, &display_line);
+                self.put(row_idx, buf_x, buf_end, &display_line);
 
                 match buffer.logic {
                     BufferLogic::Editor => {
@@ -236,62 +370,347 @@ impl<W: Write> Terminal<W> {
                             &theme.editor.bg
                         };
 
-                        self.paint_bg(row_idx, buf_x, buf_end, &line_color);
+                        // The selected columns get their own background, the runs must not
+                        // overlap
+                        let text_end = buf_end.saturating_sub(right);
+                        let selected = buffer.selected_columns(line_num).and_then(|(s, e)| {
+                            let start = start_x + s.saturating_sub(buffer.scroll_x);
+                            let end = start_x + e.saturating_sub(buffer.scroll_x);
+                            let end = std::cmp::min(end, text_end);
+                            (start < end).then_some((start, end))
+                        });
+                        match selected {
+                            Some((start, end)) => {
+                                self.paint_bg(row_idx, buf_x, start, line_color);
+                                self.paint_bg(row_idx, start, end, &theme.editor.selection);
+                                if end < buf_end {
+                                    self.paint_bg(row_idx, end, buf_end, line_color);
+                                }
+                            }
+                            None => self.paint_bg(row_idx, buf_x, buf_end, line_color),
+                        }
 
+                        // The most severe diagnostic of the line gets a sign in the gutter, and
+                        // its message after the text if there is room
+                        let diagnostic = buffer.line_diagnostic(line_num);
                         if buffer.line_numbers {
-                            let border_gap = if buffer.bordered { 1 } else { 0 };
-                            self.paint_fg(
-                                row_idx,
-                                buf_x + border_gap,
-                                start_x,
-                                &theme.editor.line_numbers,
-                            );
+                            let sign_x = buf_x + if buffer.bordered { 1 } else { 0 };
+                            let sign_color = match &diagnostic {
+                                Some((severity, _)) => theme.editor.diagnostic_color(*severity),
+                                None => &theme.editor.line_numbers,
+                            };
+                            self.paint_fg(row_idx, sign_x, sign_x + 1, sign_color);
+                            self.paint_fg(row_idx, sign_x + 1, start_x, &theme.editor.line_numbers);
+                        }
+
+                        let line_len = buffer.line_text(line_num).chars().count();
+                        let virtual_text = diagnostic.and_then(|(severity, message)| {
+                            let start = start_x + line_len.saturating_sub(buffer.scroll_x) + 2;
+                            (start < text_end).then_some((start, severity, message))
+                        });
+                        let fg_end = match &virtual_text {
+                            Some((start, _, _)) => *start,
+                            None => buf_end,
+                        };
+                        if let Some((start, severity, message)) = &virtual_text {
+                            self.put(row_idx, *start, text_end, &format!("● {message}"));
+                            let color = theme.editor.diagnostic_color(*severity);
+                            self.paint_fg(row_idx, *start, buf_end, color);
+                        }
+
+                        for (start, end, severity) in buffer.diagnostic_columns(line_num) {
+                            let start = start_x + start.saturating_sub(buffer.scroll_x);
+                            let end = start_x + end.saturating_sub(buffer.scroll_x);
+                            let end = std::cmp::min(end, text_end);
+                            let color = theme.editor.diagnostic_color(severity);
+                            self.paint_underline(row_idx, start, end, color);
                         }
 
-                        self.paint_fg(row_idx, start_x, buf_end, &theme.editor.text);
+                        // Paint the highlighted tokens in their own color, the runs must not
+                        // overlap
+                        let mut run_start = start_x;
+                        for span in &highlights[i] {
+                            let start = start_x + span.start.saturating_sub(buffer.scroll_x);
+                            let end = start_x + span.end.saturating_sub(buffer.scroll_x);
+                            let end = std::cmp::min(end, text_end);
+                            if start >= end {
+                                continue;
+                            }
+
+                            if run_start < start {
+                                self.paint_fg(row_idx, run_start, start, &theme.editor.text);
+                            }
+                            self.paint_fg(row_idx, start, end, theme.editor.token_color(span.kind));
+                            run_start = end;
+                        }
+                        if run_start < fg_end {
+                            self.paint_fg(row_idx, run_start, fg_end, &theme.editor.text);
+                        }
                     }
                     BufferLogic::InputBox => {
                         self.paint_bg(row_idx, buf_x, buf_end, &theme.overlay.bg);
                         self.paint_fg(row_idx, start_x, buf_end, &theme.overlay.text);
                     }
-                    BufferLogic::Selector => todo!(),
+                    BufferLogic::Selector => {
+                        self.paint_bg(row_idx, buf_x, buf_end, &theme.overlay.bg);
+                        self.paint_fg(row_idx, start_x, buf_end, &theme.overlay.text);
+                    }
+                    BufferLogic::Explorer => {
+                        let line_color = if buf_current_line == line_num {
+                            &theme.editor.current_line
+                        } else {
+                            &theme.editor.bg
+                        };
+
+                        self.paint_bg(row_idx, buf_x, buf_end, line_color);
+                        // The last column is the separator, painted below
+                        self.paint_fg(row_idx, start_x, buf_end.saturating_sub(1), &theme.editor.text);
+                    }
                 }
             }
 
             row_idx += 1;
         }
 
-        if buffer.show_status_line {
+        if let (BufferLogic::Selector, Some(selector)) = (&buffer.logic, &buffer.selector) {
+            let items_end = ((buffer.y + height) as usize).saturating_sub(bottom);
+            let rows = items_end.saturating_sub(row_idx);
+            let label_start = buf_x + if buffer.bordered { 1 } else { 0 };
+
+            for (i, (item, positions)) in selector
+                .visible_items()
+                .enumerate()
+                .skip(selector.scroll())
+                .take(rows)
+            {
+                let display_line = buffer.get_selector_row(item);
+                self.put(row_idx, buf_x, buf_end, &display_line);
+
+                let line_color = if i == selector.selected_index() {
+                    &theme.editor.current_line
+                } else {
+                    &theme.overlay.bg
+                };
+                self.paint_bg(row_idx, buf_x, buf_end, line_color);
+
+                // Paint the matched characters in their own color, the runs must not overlap
+                let label_width = buffer.selector_label_width(item);
+                let mut run_start = buf_x;
+                for pos in positions.iter().filter(|p| **p < label_width) {
+                    let pos = label_start + pos;
+                    if run_start < pos {
+                        self.paint_fg(row_idx, run_start, pos, &theme.overlay.text);
+                    }
+                    self.paint_fg(row_idx, pos, pos + 1, &theme.overlay.match_highlight);
+                    run_start = pos + 1;
+                }
+                if run_start < buf_end {
+                    self.paint_fg(row_idx, run_start, buf_end, &theme.overlay.text);
+                }
+
+                row_idx += 1;
+            }
+
+            // Fill the remaining space so the overlay hides whatever is behind it
+            while row_idx < items_end {
+                let empty = SelectorItem::new("", None);
+                self.put(row_idx, buf_x, buf_end, &buffer.get_selector_row(&empty));
+                self.paint_bg(row_idx, buf_x, buf_end, &theme.overlay.bg);
+                self.paint_fg(row_idx, buf_x, buf_end, &theme.overlay.text);
+
+                row_idx += 1;
+            }
+        }
+
+        if buffer.logic == BufferLogic::Explorer {
+            let separator_x = buf_end.saturating_sub(1);
+            let end = std::cmp::min(buffer.y + buffer.height, self.height) as usize;
+            for row in buffer.y as usize..end {
+                self.put(row, separator_x, buf_end, "│");
+                self.paint_fg(row, separator_x, buf_end, &theme.editor.line_numbers);
+            }
+        }
+
+        // Rows below the buffer belong to whatever is placed there
+        let buf_bottom = (buffer.y + height) as usize;
+
+        if buffer.show_status_line && height as usize >= bottom + top {
+            // Always on the last row of the buffer, however short the text is
+            row_idx = std::cmp::max(row_idx, buf_bottom - bottom);
             let line = buffer.get_status_line();
-            self.buffer[row_idx].replace_from(buf_x, &line);
+            self.put(row_idx, buf_x, buf_end, &line);
 
             self.paint_bg(row_idx, buf_x, buf_end, &theme.status_line.bg);
             self.paint_fg(row_idx, buf_x, buf_end, &theme.status_line.text);
             row_idx += 1;
         }
 
-        if buffer.bordered {
-            self.buffer[row_idx].replace_from(buf_x, &buffer.bottom_border);
+        if buffer.bordered && row_idx < buf_bottom {
+            self.put(row_idx, buf_x, buf_end, &buffer.bottom_border);
 
             self.paint_bg(row_idx, buf_x, buf_end, border_bg_color);
             self.paint_fg(row_idx, buf_x, buf_end, border_fg_color);
         }
     }
 
+    /// Draws the word completions of an editor buffer under the word being typed, or over it when
+    /// there is no room below
+    pub fn draw_completion(&mut self, buffer: &Buffer, theme: &Theme) {
+        let Some(completion) = &buffer.completion else {
+            return;
+        };
+
+        let width = completion
+            .visible_words()
+            .map(|(_, word)| word.chars().count() + 2)
+            .max()
+            .unwrap_or_default();
+        let width = std::cmp::min(width, self.width as usize);
+        let height = std::cmp::min(completion.words.len(), VISIBLE_WORDS);
+
+        // The words line up with the one being typed, after a column of padding
+        let (cursor_x, cursor_y) = buffer.cursor_xy();
+        let typed = buffer.data.cursor().saturating_sub(completion.word_start);
+        let x = (cursor_x - typed as isize - 1).max(0) as usize;
+        let x = std::cmp::min(x, self.width as usize - width);
+        let y = if cursor_y + 1 + height as isize <= self.height as isize {
+            cursor_y + 1
+        } else {
+            cursor_y - height as isize
+        };
+        if y < 0 {
+            return;
+        }
+
+        for (row, (i, word)) in (y as usize..).zip(completion.visible_words()) {
+            let bg = if i == completion.selected {
+                &theme.editor.current_line
+            } else {
+                &theme.overlay.bg
+            };
+            let line = format!(" {word:width$}", width = width - 1);
+            self.put(row, x, x + width, &line);
+            self.paint_over(row, x, x + width, bg, &theme.overlay.text);
+        }
+    }
+
     pub fn draw_welcome_msg(&mut self) {
-        let msg = vec!["Revo v0.1", "", "Quit: Ctrl + Q"];
+        let msg = [
+            "Revo v0.1",
+            "",
+            "Command Palette: Ctrl + Shift + P",
+            "Quit: Ctrl + Q",
+        ];
 
         let x_center = (self.width as f32 * 0.5) as usize;
         let y_center = (self.height as f32 * 0.4) as usize;
 
         for (i, line) in msg.iter().enumerate() {
-            let line_x = x_center - (line.len() / 2);
-            let line_y = (y_center as i16 + (i as i16 - msg.len() as i16 / 2)) as usize;
+            let line_x = x_center.saturating_sub(line.len() / 2);
+            let line_y = y_center as isize + (i as isize - msg.len() as isize / 2);
 
-            self.buffer[line_y].replace_from(line_x as usize, line);
+            // Lines that don't fit in a tiny terminal are skipped
+            if line_y >= 0 {
+                self.put(line_y as usize, line_x, self.width as usize, line);
+            }
         }
     }
 
+    /// Draws the tab bar on row `y`, from column `x` to `x + width`.
+    ///
+    /// If the tabs don't fit, they are shifted so the active tab stays visible.
+    pub fn draw_tab_bar(&mut self, x: u16, y: u16, width: u16, tabs: &[Tab], theme: &Theme) {
+        let row = y as usize;
+        if row >= self.buffer.len() {
+            return;
+        }
+
+        let start = x as usize;
+        let end = std::cmp::min((x + width) as usize, self.width as usize);
+
+        let titles: Vec<String> = tabs
+            .iter()
+            .map(|tab| {
+                let marker = if tab.modified { " ●" } else { "" };
+                format!(" {}{} ", tab.title, marker)
+            })
+            .collect();
+
+        // Skip tabs from the left until the active one fits
+        let active = tabs.iter().position(|t| t.active).unwrap_or(0);
+        let mut first = 0;
+        while first < active
+            && titles[first..=active]
+                .iter()
+                .map(|t| t.chars().count() + 1)
+                .sum::<usize>()
+                > end - start
+        {
+            first += 1;
+        }
+
+        let mut line = String::new();
+        // (start, end, active) of each drawn tab
+        let mut spans = vec![];
+        let mut col = start;
+        for (tab, title) in tabs.iter().zip(titles.iter()).skip(first) {
+            let len = title.chars().count();
+            if col >= end {
+                break;
+            }
+            line.push_str(title);
+            spans.push((col, std::cmp::min(col + len, end), tab.active));
+            col += len;
+
+            line.push('│');
+            col += 1;
+        }
+        let padding = (end - start).saturating_sub(line.chars().count());
+        line.push_str(&" ".repeat(padding));
+        let line: String = line.chars().take(end - start).collect();
+
+        self.buffer[row].replace_from(start, &line);
+
+        // The runs must not overlap, so each gap and tab is painted on its own
+        let mut run_start = start;
+        for (tab_start, tab_end, active) in spans {
+            if run_start < tab_start {
+                self.paint_bg(row, run_start, tab_start, &theme.ui.base_bg);
+                self.paint_fg(row, run_start, tab_start, &theme.editor.line_numbers);
+            }
+            if active {
+                self.paint_bg(row, tab_start, tab_end, &theme.editor.bg);
+                self.paint_fg(row, tab_start, tab_end, &theme.editor.text);
+            } else {
+                self.paint_bg(row, tab_start, tab_end, &theme.ui.base_bg);
+                self.paint_fg(row, tab_start, tab_end, &theme.editor.line_numbers);
+            }
+            run_start = tab_end;
+        }
+        if run_start < end {
+            self.paint_bg(row, run_start, end, &theme.ui.base_bg);
+            self.paint_fg(row, run_start, end, &theme.editor.line_numbers);
+        }
+    }
+
+    /// Draws a message over the last row of the terminal
+    pub fn draw_message(&mut self, message: &str, theme: &Theme) {
+        let row = match (self.height as usize).checked_sub(1) {
+            Some(row) => row,
+            None => return,
+        };
+        let width = self.width as usize;
+
+        let mut line: String = message.chars().take(width.saturating_sub(1)).collect();
+        line.insert(0, ' ');
+        let padding = width.saturating_sub(line.chars().count());
+        line.push_str(&" ".repeat(padding));
+
+        self.buffer[row].replace_from(0, &line);
+        self.paint_bg(row, 0, width, &theme.status_line.bg);
+        self.paint_fg(row, 0, width, &theme.status_line.text);
+    }
+
     pub fn show_cursor(&mut self, cursor_x: u16, cursor_y: u16) -> io::Result<()> {
         execute!(self.out, MoveTo(cursor_x, cursor_y), Show,)
     }
@@ -299,6 +718,10 @@ impl<W: Write> Terminal<W> {
 
 impl<W: Write> Drop for Terminal<W> {
     fn drop(&mut self) {
+        if self.keyboard_enhanced {
+            let _ = execute!(self.out, PopKeyboardEnhancementFlags);
+        }
+
         if let Err(e) = disable_raw_mode() {
             eprintln!("ERROR : Failed to disable terminal raw mode : {e}");
             exit(1);
@@ -2,6 +2,8 @@
 use crossterm::style::Color;
 use serde::{Deserialize, Serialize};
 
+use crate::{diagnostics::Severity, language::TokenKind};
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Theme {
     pub ui: UIColors,
@@ -34,6 +36,60 @@ pub struct EditorColors {
     pub number_literal: String,
     #[serde(rename = "line_numbers")]
     pub line_numbers: String,
+    /// Background of selected text
+    #[serde(rename = "selection", default = "default_selection")]
+    pub selection: String,
+    /// Colors of diagnostics by severity, for their gutter signs, underlines and messages
+    #[serde(rename = "error", default = "default_error")]
+    pub error: String,
+    #[serde(rename = "warning", default = "default_warning")]
+    pub warning: String,
+    #[serde(rename = "info", default = "default_info")]
+    pub info: String,
+    #[serde(rename = "hint", default = "default_hint")]
+    pub hint: String,
+}
+
+fn default_selection() -> String {
+    "#44505f".to_string()
+}
+
+fn default_error() -> String {
+    "#e06c75".to_string()
+}
+
+fn default_warning() -> String {
+    "#e5c07b".to_string()
+}
+
+fn default_info() -> String {
+    "#61afef".to_string()
+}
+
+fn default_hint() -> String {
+    "#8a93a3".to_string()
+}
+
+impl EditorColors {
+    /// Color of highlighted text of `kind`
+    pub fn token_color(&self, kind: TokenKind) -> &str {
+        match kind {
+            TokenKind::Comment => &self.comments,
+            TokenKind::Keyword => &self.keywords,
+            TokenKind::String => &self.string_literal,
+            TokenKind::Number => &self.number_literal,
+        }
+    }
+
+    /// Color of diagnostics of `severity`
+    pub fn diagnostic_color(&self, severity: Severity) -> &str {
+        match severity {
+            Severity::Error => &self.error,
+            Severity::Warning => &self.warning,
+            Severity::Information => &self.info,
+            Severity::Hint => &self.hint,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -42,6 +98,13 @@ pub struct OverlayColors {
     pub bg: String,
     #[serde(rename = "text")]
     pub text: String,
+    /// Color of the characters matched by the query in a selector
+    #[serde(rename = "match_highlight", default = "default_match_highlight")]
+    pub match_highlight: String,
+}
+
+fn default_match_highlight() -> String {
+    "#FFA07A".to_string()
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -68,10 +131,16 @@ impl Default for Theme {
                 string_literal: "#98c379".to_string(), // Gentle pastel green for string literals
                 number_literal: "#a6a9d6".to_string(), // Soft pastel purple for numbers
                 line_numbers: "#767f8c".to_string(), // Darker, softer color for line numbers
+                selection: default_selection(),      // Lighter than the current line
+                error: default_error(),    // Soft red
+                warning: default_warning(), // Soft yellow
+                info: default_info(),      // Soft blue
+                hint: default_hint(),      // Gray, a little lighter than line numbers
             },
             overlay: OverlayColors {
                 bg: "#282c34".to_string(),  // Dark background for overlays
                 text: "#f8f8f2".to_string(), // Soft off-white text for overlays
+                match_highlight: default_match_highlight(), // Same orangish pastel as keywords
             },
             status_line: StatusLineColors {
                 bg: "#3b4048".to_string(),  // Subtle dark gray for status line background
@@ -17,12 +17,8 @@ impl VecExt for Vec<char> {
     /// 
     /// NOTE: If `self.len()` ends before all the characters from `string` are inserted, then we stop.
     fn replace_from(&mut self, index: usize, with: &str) {
-        let mut chars = with.chars();
-        for i in index..self.len() {
-            match chars.next() {
-                Some(ch) => self[i] = ch,
-                None => break,
-            }
+        for (slot, ch) in self.iter_mut().skip(index).zip(with.chars()) {
+            *slot = ch;
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,317 @@
+#![allow(dead_code)]
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::editor::EditorEvent;
+
+/// How often files are checked when the OS can't tell us about changes
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How long to wait for change notifications before looking for new commands again
+const NOTIFY_TIMEOUT: Duration = Duration::from_millis(100);
+
+enum WatchCommand {
+    Watch(PathBuf),
+    Unwatch(PathBuf),
+}
+
+/// Modification time and size of a file, `None` if it doesn't exist
+type Stamp = Option<(SystemTime, u64)>;
+
+/// Watches files on a background thread, and sends `EditorEvent::FileChanged` when one of them is
+/// modified, replaced or deleted by another program.
+///
+/// On Linux the folders of the files are watched with inotify, elsewhere (or when inotify is not
+/// available, or can't watch a folder) the files are polled.
+pub struct FileWatcher {
+    sender: Option<Sender<WatchCommand>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl FileWatcher {
+    pub fn new(msg_sender: Sender<EditorEvent>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let thread = thread::spawn(move || run(receiver, msg_sender));
+
+        Self {
+            sender: Some(sender),
+            thread: Some(thread),
+        }
+    }
+
+    pub fn watch(&self, path: PathBuf) {
+        self.send(WatchCommand::Watch(path));
+    }
+
+    pub fn unwatch(&self, path: PathBuf) {
+        self.send(WatchCommand::Unwatch(path));
+    }
+
+    fn send(&self, command: WatchCommand) {
+        if let Some(sender) = &self.sender {
+            sender.send(command).expect("File watcher stopped");
+        }
+    }
+}
+
+impl Drop for FileWatcher {
+    fn drop(&mut self) {
+        // Closing the channel stops the watcher the next time it looks for commands
+        self.sender.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run(commands: Receiver<WatchCommand>, msg_sender: Sender<EditorEvent>) {
+    let mut files: HashMap<PathBuf, Stamp> = HashMap::new();
+    let mut notifier = Notifier::new();
+    // Files the notifier can't watch, they are polled even when it's used
+    let mut polled: HashSet<PathBuf> = HashSet::new();
+    let mut last_poll = Instant::now();
+
+    loop {
+        // Either wait for a notification, or for the next poll
+        let command = match &mut notifier {
+            Some(_) => commands.try_recv().map_err(|e| match e {
+                TryRecvError::Empty => RecvTimeoutError::Timeout,
+                TryRecvError::Disconnected => RecvTimeoutError::Disconnected,
+            }),
+            None => commands.recv_timeout(POLL_INTERVAL),
+        };
+
+        let check = match command {
+            Ok(WatchCommand::Watch(path)) => {
+                if let Some(notifier) = &mut notifier {
+                    if !notifier.add(&path) {
+                        polled.insert(path.clone());
+                    }
+                }
+                files.insert(path.clone(), stamp(&path));
+                continue;
+            }
+            Ok(WatchCommand::Unwatch(path)) => {
+                if files.remove(&path).is_some() && !polled.remove(&path) {
+                    if let Some(notifier) = &mut notifier {
+                        notifier.remove(&path);
+                    }
+                }
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => return,
+            Err(RecvTimeoutError::Timeout) => match &mut notifier {
+                Some(notifier) => {
+                    let notified = notifier.wait(NOTIFY_TIMEOUT);
+                    notified || (!polled.is_empty() && last_poll.elapsed() >= POLL_INTERVAL)
+                }
+                None => true,
+            },
+        };
+
+        if !check {
+            continue;
+        }
+        last_poll = Instant::now();
+
+        // Notifications only say something changed in a folder, the stamps tell which files did
+        for (path, last) in files.iter_mut() {
+            let current = stamp(path);
+            if current != *last {
+                *last = current;
+                if msg_sender.send(EditorEvent::FileChanged(path.clone())).is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+fn stamp(path: &Path) -> Stamp {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Folder a file is in, which is what gets watched
+fn parent_dir(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Tells when something changes in the folders of the watched files, with inotify.
+///
+/// NOTE: Folders are watched instead of the files, since most tools replace a file by renaming a
+///       new one over it, which a watch on the old file would not follow.
+#[cfg(target_os = "linux")]
+struct Notifier {
+    fd: std::os::fd::OwnedFd,
+    /// Watch descriptor of every watched folder, and the number of files watched in it
+    dirs: HashMap<PathBuf, (i32, usize)>,
+}
+
+#[cfg(target_os = "linux")]
+impl Notifier {
+    /// Returns `None` if inotify can't be used, in which case files are polled instead
+    fn new() -> Option<Self> {
+        use std::os::fd::FromRawFd;
+
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return None;
+        }
+
+        Some(Self {
+            fd: unsafe { std::os::fd::OwnedFd::from_raw_fd(fd) },
+            dirs: HashMap::new(),
+        })
+    }
+
+    /// Watches the folder of `file`. Returns false if it can't be watched, e.g. because it
+    /// doesn't exist yet or there are too many watches, then the file has to be polled.
+    fn add(&mut self, file: &Path) -> bool {
+        use std::os::{fd::AsRawFd, unix::ffi::OsStrExt};
+
+        let dir = parent_dir(file);
+        if let Some((_, count)) = self.dirs.get_mut(&dir) {
+            *count += 1;
+            return true;
+        }
+
+        let Ok(c_dir) = std::ffi::CString::new(dir.as_os_str().as_bytes()) else {
+            return false;
+        };
+        let mask = libc::IN_CLOSE_WRITE
+            | libc::IN_MOVED_TO
+            | libc::IN_MOVED_FROM
+            | libc::IN_CREATE
+            | libc::IN_DELETE;
+        let wd = unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), c_dir.as_ptr(), mask) };
+
+        if wd < 0 {
+            return false;
+        }
+        self.dirs.insert(dir, (wd, 1));
+        true
+    }
+
+    fn remove(&mut self, file: &Path) {
+        use std::os::fd::AsRawFd;
+
+        let dir = parent_dir(file);
+        let Some((wd, count)) = self.dirs.get_mut(&dir) else {
+            return;
+        };
+
+        *count -= 1;
+        if *count == 0 {
+            unsafe { libc::inotify_rm_watch(self.fd.as_raw_fd(), *wd) };
+            self.dirs.remove(&dir);
+        }
+    }
+
+    /// Waits up to `timeout` for changes, and returns true if there were any
+    fn wait(&mut self, timeout: Duration) -> bool {
+        use std::os::fd::AsRawFd;
+
+        let mut poll_fd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let ready = unsafe { libc::poll(&mut poll_fd, 1, timeout.as_millis() as libc::c_int) };
+        if ready <= 0 {
+            return false;
+        }
+
+        // The events themselves are not needed, just drain them
+        let mut events = [0u8; 4096];
+        loop {
+            let read = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    events.as_mut_ptr() as *mut libc::c_void,
+                    events.len(),
+                )
+            };
+            if read <= 0 {
+                break;
+            }
+        }
+
+        true
+    }
+}
+
+/// Without inotify, files are polled
+#[cfg(not(target_os = "linux"))]
+struct Notifier;
+
+#[cfg(not(target_os = "linux"))]
+impl Notifier {
+    fn new() -> Option<Self> {
+        None
+    }
+
+    fn add(&mut self, _file: &Path) -> bool {
+        false
+    }
+
+    fn remove(&mut self, _file: &Path) {}
+
+    fn wait(&mut self, _timeout: Duration) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changed(receiver: &Receiver<EditorEvent>) -> Option<PathBuf> {
+        match receiver.recv_timeout(POLL_INTERVAL * 3) {
+            Ok(EditorEvent::FileChanged(path)) => Some(path),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn files_in_missing_folders_are_polled() {
+        let dir = std::env::temp_dir().join(format!("revo-watcher-{}", std::process::id()));
+        let file = dir.join("later").join("file.txt");
+        let (sender, receiver) = mpsc::channel();
+        let watcher = FileWatcher::new(sender);
+        watcher.watch(file.clone());
+        thread::sleep(NOTIFY_TIMEOUT);
+
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(&file, "text").unwrap();
+        let result = changed(&receiver);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(result, Some(file));
+    }
+
+    #[test]
+    fn watched_files_notify() {
+        let dir = std::env::temp_dir().join(format!("revo-watched-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("file.txt");
+        let (sender, receiver) = mpsc::channel();
+        let watcher = FileWatcher::new(sender);
+        watcher.watch(file.clone());
+        thread::sleep(NOTIFY_TIMEOUT);
+
+        fs::write(&file, "text").unwrap();
+        let result = changed(&receiver);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(result, Some(file));
+    }
+}
@@ -0,0 +1,267 @@
+#![allow(dead_code)]
+use std::{cmp::Reverse, collections::HashMap};
+
+/// Most words listed by the popup at once, the others are scrolled to
+pub const VISIBLE_WORDS: usize = 8;
+/// Most words offered for one prefix
+const MAX_WORDS: usize = 50;
+
+/// Words completing the one being typed in an editor buffer, listed in a popup at the cursor
+pub struct WordCompletion {
+    /// Where the word being typed starts, it is replaced up to the cursor
+    pub word_start: usize,
+    pub words: Vec<String>,
+    pub selected: usize,
+    /// First of the listed words
+    pub scroll: usize,
+}
+
+impl WordCompletion {
+    pub fn new(word_start: usize, words: Vec<String>) -> Self {
+        WordCompletion {
+            word_start,
+            words,
+            selected: 0,
+            scroll: 0,
+        }
+    }
+
+    /// Selects the next word, going around to the first one after the last
+    pub fn select_next(&mut self) {
+        self.select((self.selected + 1) % self.words.len().max(1));
+    }
+
+    /// Selects the previous word, going around to the last one before the first
+    pub fn select_prev(&mut self) {
+        let len = self.words.len().max(1);
+        self.select((self.selected + len - 1) % len);
+    }
+
+    fn select(&mut self, index: usize) {
+        self.selected = index;
+        if index < self.scroll {
+            self.scroll = index;
+        } else if index >= self.scroll + VISIBLE_WORDS {
+            self.scroll = index + 1 - VISIBLE_WORDS;
+        }
+    }
+
+    pub fn selected_word(&self) -> Option<&str> {
+        self.words.get(self.selected).map(|word| word.as_str())
+    }
+
+    /// Returns the listed words with their index
+    pub fn visible_words(&self) -> impl Iterator<Item = (usize, &String)> {
+        self.words
+            .iter()
+            .enumerate()
+            .skip(self.scroll)
+            .take(VISIBLE_WORDS)
+    }
+}
+
+pub fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The words of a text, with the lines they are used on. Numbers don't count as words.
+pub struct WordIndex {
+    /// Lines of every use of a word, in order
+    lines: HashMap<String, Vec<usize>>,
+}
+
+impl WordIndex {
+    pub fn new(text: &[char]) -> Self {
+        let mut lines: HashMap<String, Vec<usize>> = HashMap::new();
+        for (start, end, line) in words(text) {
+            let word = text[start..end].iter().collect();
+            lines.entry(word).or_default().push(line);
+        }
+        Self { lines }
+    }
+}
+
+/// How a word is used in the open texts
+struct Usage {
+    count: usize,
+    /// Lines between the cursor and the closest use in the edited text, `None` when it is only
+    /// used in the other texts
+    distance: Option<usize>,
+}
+
+/// Returns the words of the open texts completing `prefix`, best first. `text` is the one being
+/// edited, with `typed` being the word at the cursor on `cursor_line`, and `others` are the other
+/// open texts.
+///
+/// Words matching the case of the prefix come first. Then words used on nearby lines come before
+/// the ones further away, and words about as far away are ranked by how often they are used. Words
+/// only used in the other texts come last, by how often they are used.
+pub fn rank_words(
+    prefix: &str,
+    text: &WordIndex,
+    cursor_line: usize,
+    typed: &str,
+    others: &[&WordIndex],
+) -> Vec<String> {
+    let prefix: Vec<char> = prefix.chars().collect();
+    let mut usages: HashMap<String, Usage> = HashMap::new();
+
+    for (word, lines) in text.lines.iter() {
+        if !completes(word, &prefix) {
+            continue;
+        }
+        // The word being typed doesn't complete itself
+        let typed_at = (word == typed)
+            .then(|| lines.iter().position(|line| *line == cursor_line))
+            .flatten();
+        let lines = lines
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| Some(*i) != typed_at)
+            .map(|(_, line)| line);
+
+        for line in lines {
+            let usage = usages.entry(word.clone()).or_insert(Usage {
+                count: 0,
+                distance: None,
+            });
+            let distance = line.abs_diff(cursor_line);
+            usage.count += 1;
+            usage.distance = Some(usage.distance.map_or(distance, |d| d.min(distance)));
+        }
+    }
+
+    for other in others {
+        for (word, lines) in other.lines.iter() {
+            if completes(word, &prefix) {
+                let usage = usages.entry(word.clone()).or_insert(Usage {
+                    count: 0,
+                    distance: None,
+                });
+                usage.count += lines.len();
+            }
+        }
+    }
+
+    let prefix: String = prefix.into_iter().collect();
+    let mut ranked: Vec<(String, Usage)> = usages.into_iter().collect();
+    ranked.sort_by_cached_key(|(word, usage)| {
+        // Distances grow by powers of two, so close enough words are ranked by their count
+        let distance = match usage.distance {
+            Some(distance) => (distance + 1).ilog2(),
+            None => u32::MAX,
+        };
+        (
+            !word.starts_with(&prefix),
+            distance,
+            Reverse(usage.count),
+            word.clone(),
+        )
+    });
+
+    ranked
+        .into_iter()
+        .take(MAX_WORDS)
+        .map(|(word, _)| word)
+        .collect()
+}
+
+/// Returns true if `word` completes `prefix`, ignoring case, and is longer than it
+fn completes(word: &str, prefix: &[char]) -> bool {
+    word.chars().count() > prefix.len()
+        && word
+            .chars()
+            .zip(prefix)
+            .all(|(a, b)| a.to_lowercase().eq(b.to_lowercase()))
+}
+
+/// Returns the start, end and line of the words of `text`, skipping numbers
+fn words(text: &[char]) -> impl Iterator<Item = (usize, usize, usize)> + '_ {
+    let mut pos = 0;
+    let mut line = 0;
+    std::iter::from_fn(move || {
+        while pos < text.len() {
+            if !is_word_char(text[pos]) {
+                if text[pos] == '\n' {
+                    line += 1;
+                }
+                pos += 1;
+                continue;
+            }
+
+            let start = pos;
+            while pos < text.len() && is_word_char(text[pos]) {
+                pos += 1;
+            }
+            if !text[start].is_numeric() {
+                return Some((start, pos, line));
+            }
+        }
+        None
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Document;
+    use std::rc::Rc;
+
+    fn index(text: &str) -> WordIndex {
+        WordIndex::new(&text.chars().collect::<Vec<char>>())
+    }
+
+    #[test]
+    fn ranks_close_and_frequent_words_first() {
+        let text = index("alpha\nalps\nal\n\n\n\nalbum album album\n");
+        let other = index("alright alright alright alright");
+        let words = rank_words("al", &text, 2, "al", &[&other]);
+        assert_eq!(words, ["alpha", "alps", "album", "alright"]);
+
+        // Words about as far away are ranked by their count, then by themselves
+        let words = rank_words("al", &text, 4, "", &[]);
+        assert_eq!(words, ["album", "alpha", "alps"]);
+        let words = rank_words("al", &index("alps alpha alpha"), 0, "", &[]);
+        assert_eq!(words, ["alpha", "alps"]);
+    }
+
+    #[test]
+    fn prefers_words_matching_the_case() {
+        let text = index("Value value valid\n");
+        let words = rank_words("Va", &text, 0, "", &[]);
+        assert_eq!(words, ["Value", "valid", "value"]);
+    }
+
+    #[test]
+    fn leaves_out_the_typed_word() {
+        // Typed once, used once more on another line
+        let text = index("item\nite\nitems 42\n");
+        assert_eq!(rank_words("ite", &text, 1, "ite", &[]), ["item", "items"]);
+        let text = index("item\nitems\n");
+        assert_eq!(rank_words("ite", &text, 1, "items", &[]), ["item"]);
+        let text = index("items\nitems\n");
+        assert_eq!(rank_words("ite", &text, 1, "items", &[]), ["items"]);
+
+        // Numbers and words no longer than the prefix don't complete it
+        assert!(rank_words("4", &index("42 4a"), 0, "", &[]).is_empty());
+        assert!(rank_words("ab", &index("ab a"), 0, "", &[]).is_empty());
+    }
+
+    #[test]
+    fn documents_find_their_words_again_after_an_edit() {
+        let mut document = Document::from("one two".to_string());
+        let index = document.word_index();
+        assert!(Rc::ptr_eq(&index, &document.word_index()));
+
+        document.insert(0, 'x');
+        let edited = document.word_index();
+        assert!(!Rc::ptr_eq(&index, &edited));
+        assert_eq!(rank_words("xo", &edited, 0, "", &[]), ["xone"]);
+
+        document.replace_text("three".to_string());
+        assert_eq!(
+            rank_words("th", &document.word_index(), 0, "", &[]),
+            ["three"]
+        );
+    }
+}
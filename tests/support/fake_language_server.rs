@@ -0,0 +1,221 @@
+//! A language server for the tests of `lsp.rs`, spoken to over stdio like a real one.
+//!
+//! It keeps the texts of the open documents by applying the changes it's sent, and answers
+//! about the word at a position:
+//! - diagnostics: an error on every `bad` in the text, published after every change
+//! - hover: the word
+//! - definition: the first place the word is used
+//! - rename: every use of the word in the document
+//! - formatting: spaces at the end of lines are removed
+//!
+//! Positions are in UTF-16 code units, or in characters with `--utf-32`. Changes are taken as
+//! ranges, or as whole texts with `--full`.
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+};
+
+use serde_json::{json, Value};
+
+fn main() -> io::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let utf32 = args.iter().any(|arg| arg == "--utf-32");
+    let full = args.iter().any(|arg| arg == "--full");
+
+    let mut input = io::stdin().lock();
+    let mut output = io::stdout().lock();
+    let mut documents: HashMap<String, Vec<char>> = HashMap::new();
+
+    while let Some(message) = read_message(&mut input)? {
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let result = match message["method"].as_str().unwrap_or_default() {
+            "initialize" => json!({
+                "capabilities": {
+                    "positionEncoding": if utf32 { "utf-32" } else { "utf-16" },
+                    "textDocumentSync": if full { 1 } else { 2 },
+                },
+            }),
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                documents.insert(uri.to_string(), text.chars().collect());
+                publish_diagnostics(&mut output, uri, &documents[uri], utf32)?;
+                continue;
+            }
+            "textDocument/didChange" => {
+                let text = documents.entry(uri.to_string()).or_default();
+                for change in params["contentChanges"].as_array().unwrap() {
+                    let new_text = change["text"].as_str().unwrap().chars();
+                    match change.get("range") {
+                        Some(range) => {
+                            let start = position(text, &range["start"], utf32);
+                            let end = position(text, &range["end"], utf32);
+                            text.splice(start..end, new_text);
+                        }
+                        None => *text = new_text.collect(),
+                    }
+                }
+                publish_diagnostics(&mut output, uri, text, utf32)?;
+                continue;
+            }
+            "textDocument/didClose" => {
+                documents.remove(uri);
+                continue;
+            }
+            "exit" => return Ok(()),
+            "textDocument/hover" => {
+                let text = &documents[uri];
+                let (start, end) = word_at(text, position(text, &params["position"], utf32));
+                let word: String = text[start..end].iter().collect();
+                json!({ "contents": { "kind": "plaintext", "value": word } })
+            }
+            "textDocument/definition" => {
+                let text = &documents[uri];
+                let (start, end) = word_at(text, position(text, &params["position"], utf32));
+                match uses(text, &text[start..end]).first() {
+                    Some(first) => {
+                        json!({ "uri": uri, "range": range(text, *first, end - start, utf32) })
+                    }
+                    None => Value::Null,
+                }
+            }
+            "textDocument/rename" => {
+                let text = &documents[uri];
+                let (start, end) = word_at(text, position(text, &params["position"], utf32));
+                let edits: Vec<Value> = uses(text, &text[start..end])
+                    .into_iter()
+                    .map(|pos| {
+                        json!({
+                            "range": range(text, pos, end - start, utf32),
+                            "newText": params["newName"],
+                        })
+                    })
+                    .collect();
+                let mut edit = json!({ "changes": {} });
+                edit["changes"][uri] = Value::Array(edits);
+                edit
+            }
+            "textDocument/formatting" => {
+                let text = &documents[uri];
+                let mut edits = vec![];
+                let mut line_start = 0;
+                for line in text.split(|c| *c == '\n') {
+                    let kept = line.len() - line.iter().rev().take_while(|c| **c == ' ').count();
+                    if kept < line.len() {
+                        edits.push(json!({
+                            "range": range(text, line_start + kept, line.len() - kept, utf32),
+                            "newText": "",
+                        }));
+                    }
+                    line_start += line.len() + 1;
+                }
+                Value::Array(edits)
+            }
+            _ => Value::Null,
+        };
+
+        // Notifications get no answer
+        if let Some(id) = message.get("id") {
+            write_message(
+                &mut output,
+                json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn publish_diagnostics(
+    output: &mut impl Write,
+    uri: &str,
+    text: &[char],
+    utf32: bool,
+) -> io::Result<()> {
+    let diagnostics: Vec<Value> = uses(text, &['b', 'a', 'd'])
+        .into_iter()
+        .map(|pos| json!({ "range": range(text, pos, 3, utf32), "message": "bad word" }))
+        .collect();
+    let params = json!({ "uri": uri, "diagnostics": diagnostics });
+    write_message(
+        output,
+        json!({ "jsonrpc": "2.0", "method": "textDocument/publishDiagnostics", "params": params }),
+    )
+}
+
+/// Returns where the word `word` is used in `text`
+fn uses(text: &[char], word: &[char]) -> Vec<usize> {
+    if word.is_empty() {
+        return vec![];
+    }
+    (0..text.len())
+        .filter(|pos| {
+            text[*pos..].starts_with(word) && word_at(text, *pos) == (*pos, pos + word.len())
+        })
+        .collect()
+}
+
+/// Returns the range of the word around `pos`
+fn word_at(text: &[char], pos: usize) -> (usize, usize) {
+    let is_word = |c: &char| c.is_alphanumeric() || *c == '_';
+    let start = pos - text[..pos].iter().rev().take_while(|c| is_word(c)).count();
+    let end = pos + text[pos..].iter().take_while(|c| is_word(c)).count();
+    (start, end)
+}
+
+fn position(text: &[char], position: &Value, utf32: bool) -> usize {
+    let line = position["line"].as_u64().unwrap() as usize;
+    let mut units = position["character"].as_u64().unwrap() as usize;
+
+    let mut pos = 0;
+    for _ in 0..line {
+        pos += text[pos..].iter().position(|c| *c == '\n').unwrap() + 1;
+    }
+    while units > 0 {
+        units -= if utf32 { 1 } else { text[pos].len_utf16() };
+        pos += 1;
+    }
+    pos
+}
+
+fn range(text: &[char], start: usize, len: usize, utf32: bool) -> Value {
+    let lsp_position = |pos: usize| {
+        let line_start = text[..pos]
+            .iter()
+            .rposition(|c| *c == '\n')
+            .map_or(0, |i| i + 1);
+        let line = text[..line_start].iter().filter(|c| **c == '\n').count();
+        let before = &text[line_start..pos];
+        let character: usize = match utf32 {
+            true => before.len(),
+            false => before.iter().map(|c| c.len_utf16()).sum(),
+        };
+        json!({ "line": line, "character": character })
+    };
+    json!({ "start": lsp_position(start), "end": lsp_position(start + len) })
+}
+
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        match header.trim_end().split_once(": ") {
+            Some(("Content-Length", value)) => length = value.parse().unwrap(),
+            _ if header.trim_end().is_empty() => break,
+            _ => (),
+        }
+    }
+
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+fn write_message(output: &mut impl Write, message: Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}